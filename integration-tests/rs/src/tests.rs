@@ -68,6 +68,7 @@ async fn main() -> anyhow::Result<()> {
     test_enum_nft_tokens(&nft_contract, &worker).await?;
     test_enum_nft_supply_for_owner(&owner, &alice, &nft_contract, &worker).await?;
     test_enum_nft_tokens_for_owner(&owner, &alice, &nft_contract, &worker).await?;
+    test_approve_event(&owner, &alice, &nft_contract, &worker).await?;
     Ok(())
 }
 
@@ -727,3 +728,63 @@ async fn test_enum_nft_tokens_for_owner(
     println!("      Passed ✅ test_enum_nft_tokens_for_owner");
     Ok(())
 }
+
+async fn test_approve_event(
+    owner: &Account,
+    user: &Account,
+    nft_contract: &Contract,
+    worker: &Worker<Sandbox>,
+) -> anyhow::Result<()> {
+    let token_series: serde_json::Value = owner
+        .call(&worker, nft_contract.id(), "nft_create_series")
+        .args_json(json!({
+            "token_metadata": {
+                "title": "Olympus Mons",
+                "copies": 1,
+            }
+        }))?
+        .deposit(parse_near!("0.01 N"))
+        .transact()
+        .await?
+        .json()?;
+    let token: serde_json::Value = owner
+        .call(&worker, nft_contract.id(), "nft_mint")
+        .args_json(json!({
+            "token_series_id": token_series["token_series_id"],
+            "receiver_id": owner.id(),
+        }))?
+        .deposit(parse_near!("0.01 N"))
+        .transact()
+        .await?
+        .json()?;
+
+    let outcome = owner
+        .call(&worker, nft_contract.id(), "nft_approve")
+        .args_json(json!({
+            "token_id": token["token_id"],
+            "account_id": user.id(),
+        }))?
+        .deposit(parse_near!("0.01 N"))
+        .transact()
+        .await?;
+    let expected = format!(
+        r#"EVENT_JSON:{{"standard":"nep171","version":"1.0.0","event":"nft_approve","data":[{{"token_id":{},"owner_id":"{}","account_id":"{}","approval_id":1}}]}}"#,
+        token["token_id"],
+        owner.id(),
+        user.id()
+    );
+    assert!(outcome.logs().contains(&expected.as_str()));
+
+    let outcome = owner
+        .call(&worker, nft_contract.id(), "nft_revoke")
+        .args_json(json!({
+            "token_id": token["token_id"],
+            "account_id": user.id(),
+        }))?
+        .deposit(1)
+        .transact()
+        .await?;
+    assert!(outcome.logs().iter().any(|log| log.contains(r#""event":"nft_revoke""#)));
+    println!("      Passed ✅ test_approve_event");
+    Ok(())
+}
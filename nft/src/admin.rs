@@ -0,0 +1,912 @@
+use crate::{Contract, StorageKey};
+use crate::metadata::{MetadataHashPolicy, NFTContractMetadata};
+use crate::pause::PauseFlags;
+use crate::series::{TokenSeriesId, MAX_PRICE, MAX_ROYALTY, MAX_ROYALTY_ACCOUNTS};
+use crate::transaction_fee::TRANSACTION_FEE_DENOMINATOR;
+use crate::treasury::{MAX_PROTOCOL_FEE, MAX_REFERRAL_FEE};
+use crate::ContractExt;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId};
+use serde_json::json;
+
+/// Delay between proposing and executing an admin action, one day in nanoseconds.
+pub const DEFAULT_ADMIN_DELAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+/// Shortest admin delay, one hour, so holders always get a window to react to a change.
+pub const MIN_ADMIN_DELAY: u64 = 60 * 60 * 1_000_000_000;
+/// Longest admin delay, thirty days, so actions always become executable.
+pub const MAX_ADMIN_DELAY: u64 = 30 * DEFAULT_ADMIN_DELAY;
+
+pub type AdminActionId = u64;
+
+/// Sensitive administrative changes. They are never applied directly, they have to be
+/// proposed first and can only be executed once the admin delay has passed.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum AdminAction {
+    SetAdminDelay { delay: U64 },
+    /// Replace the owner set and the number of confirmations required to execute an action.
+    /// An empty set hands authority back to the single `owner_id`.
+    SetOwners { owners: Vec<AccountId>, threshold: u32 },
+    /// Hand the DAO-controlled parameters over to a Sputnik DAO account, or take them back.
+    SetDao { dao_id: Option<AccountId> },
+    AddModerator { account_id: AccountId },
+    RemoveModerator { account_id: AccountId },
+    /// Upper bound for series prices, capped by `series::MAX_PRICE`.
+    SetMaxPrice { max_price: U128 },
+    /// Royalty in basis points paid to the creator of new series that don't set their own.
+    SetDefaultRoyalty { royalty: u32 },
+    /// Caps on the royalty a series may set: total basis points, at most `series::MAX_ROYALTY`,
+    /// and number of payees, at most `series::MAX_ROYALTY_ACCOUNTS`.
+    SetRoyaltyLimits { max_royalty: u32, max_accounts: u32 },
+    /// How strictly metadata hashes are checked when series are created or their metadata changes.
+    SetMetadataHashPolicy { policy: MetadataHashPolicy },
+    /// Deploy the code staged with `stage_contract_code`, which must hash to `code_hash`,
+    /// then run `migrate`.
+    UpdateContract { code_hash: Base64VecU8 },
+    /// Account receiving the protocol and transaction fees.
+    SetTreasury { treasury_id: AccountId },
+    /// Protocol fee in basis points, at most `treasury::MAX_PROTOCOL_FEE`.
+    SetProtocolFee { protocol_fee: u16 },
+    /// Transaction fee of series created from then on, or from `start_time` on when set.
+    /// `start_time` has to fall after the admin delay.
+    SetTransactionFee { next_fee: u16, start_time: Option<U64> },
+    /// Share of the price of a mint paid to its referrer, in basis points, at most
+    /// `treasury::MAX_REFERRAL_FEE`.
+    SetReferralFee { referral_fee: u16 },
+    /// Let `account_id` mint any series, the contract pays for the storage.
+    AddMinter { account_id: AccountId },
+    RemoveMinter { account_id: AccountId },
+    // Counterparts of the owner-only methods, which are refused once the owner set requires
+    // several confirmations. The contract pays for the storage.
+    SetContractMetadata { metadata: NFTContractMetadata },
+    Unpause { flags: PauseFlags },
+    BanAccount { account_id: AccountId },
+    UnbanAccount { account_id: AccountId },
+    AddPaymentFt { ft_contract_id: AccountId },
+    RemovePaymentFt { ft_contract_id: AccountId },
+    SetStakingRewardRate { token_series_id: TokenSeriesId, reward_rate: U128 },
+}
+
+/// Parameters that only the configured DAO may change once one is set.
+pub const DAO_CONTROLLED_PARAMETERS: &[&str] =
+    &["dao", "moderation_roles", "max_price", "protocol_fee", "transaction_fee", "referral_fee"];
+
+impl AdminAction {
+    /// The governed parameter this action changes, if it is one of `DAO_CONTROLLED_PARAMETERS`.
+    pub fn dao_parameter(&self) -> Option<&'static str> {
+        match self {
+            AdminAction::SetDao { .. } => Some("dao"),
+            AdminAction::AddModerator { .. } | AdminAction::RemoveModerator { .. } => {
+                Some("moderation_roles")
+            }
+            AdminAction::SetMaxPrice { .. } => Some("max_price"),
+            AdminAction::SetProtocolFee { .. } => Some("protocol_fee"),
+            AdminAction::SetTransactionFee { .. } => Some("transaction_fee"),
+            AdminAction::SetReferralFee { .. } => Some("referral_fee"),
+            AdminAction::SetAdminDelay { .. }
+            | AdminAction::SetOwners { .. }
+            | AdminAction::SetDefaultRoyalty { .. }
+            | AdminAction::SetRoyaltyLimits { .. }
+            | AdminAction::SetMetadataHashPolicy { .. }
+            | AdminAction::UpdateContract { .. }
+            | AdminAction::SetTreasury { .. }
+            | AdminAction::AddMinter { .. }
+            | AdminAction::RemoveMinter { .. }
+            | AdminAction::SetContractMetadata { .. }
+            | AdminAction::Unpause { .. }
+            | AdminAction::BanAccount { .. }
+            | AdminAction::UnbanAccount { .. }
+            | AdminAction::AddPaymentFt { .. }
+            | AdminAction::RemovePaymentFt { .. }
+            | AdminAction::SetStakingRewardRate { .. } => None,
+        }
+    }
+}
+
+/// Contract-level parameters that admin actions can change.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractConfigJson {
+    pub max_price: U128,
+    pub admin_delay: U64,
+    pub default_royalty: u32,
+    pub max_royalty: u32,
+    pub max_royalty_accounts: u32,
+    pub metadata_hash_policy: MetadataHashPolicy,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PendingAdminAction {
+    pub action: AdminAction,
+    pub proposer_id: AccountId,
+    pub proposed_at: u64,
+    pub executable_at: u64,
+    pub confirmations: Vec<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingAdminActionJson {
+    pub action_id: U64,
+    pub action: AdminAction,
+    pub proposer_id: AccountId,
+    pub proposed_at: U64,
+    pub executable_at: U64,
+    pub confirmations: Vec<AccountId>,
+}
+
+/// Admin roles plus the queue of proposed admin actions waiting for their timelock to expire and,
+/// when an owner set is configured, for `threshold` distinct owners to confirm them.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AdminState {
+    pub delay: u64,
+    pub owners: Vec<AccountId>,
+    pub threshold: u32,
+    pub dao_id: Option<AccountId>,
+    pub moderators: Vec<AccountId>,
+    pub next_action_id: AdminActionId,
+    pub pending_actions: UnorderedMap<AdminActionId, PendingAdminAction>,
+    /// Account `owner_id` was offered to, it takes over once it accepts.
+    pub pending_owner_id: Option<AccountId>,
+}
+
+impl AdminState {
+    pub fn new() -> Self {
+        Self {
+            delay: DEFAULT_ADMIN_DELAY,
+            owners: vec![],
+            threshold: 1,
+            dao_id: None,
+            moderators: vec![],
+            next_action_id: 1,
+            pending_actions: UnorderedMap::new(StorageKey::PendingAdminActions),
+            pending_owner_id: None,
+        }
+    }
+}
+
+impl Default for AdminState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Queue an admin action. It can be executed by an owner once the admin delay has passed,
+    /// giving token holders time to see the change coming. The proposer's confirmation is
+    /// recorded right away. DAO-controlled actions can only be proposed by the DAO.
+    #[payable]
+    pub fn propose_admin_action(&mut self, action: AdminAction) -> U64 {
+        assert_one_yocto();
+        self.assert_can_govern(&action);
+
+        let action_id = self.admin.next_action_id;
+        let now = env::block_timestamp();
+        let executable_at = now.saturating_add(self.admin.delay);
+        match &action {
+            AdminAction::SetAdminDelay { delay } => assert!(
+                (MIN_ADMIN_DELAY..=MAX_ADMIN_DELAY).contains(&delay.0),
+                "FireFly: delay must be between {} and {}",
+                MIN_ADMIN_DELAY,
+                MAX_ADMIN_DELAY
+            ),
+            AdminAction::SetOwners { owners, threshold } => assert_valid_owners(owners, *threshold),
+            AdminAction::SetMaxPrice { max_price } => assert!(
+                max_price.0 > 0 && max_price.0 <= MAX_PRICE,
+                "FireFly: max_price must be between 1 and {}",
+                MAX_PRICE
+            ),
+            AdminAction::SetDefaultRoyalty { royalty } => assert!(
+                *royalty <= MAX_ROYALTY,
+                "FireFly: default royalty exceeds {}",
+                MAX_ROYALTY
+            ),
+            AdminAction::SetRoyaltyLimits { max_royalty, max_accounts } => {
+                assert!(
+                    *max_royalty <= MAX_ROYALTY,
+                    "FireFly: max_royalty exceeds {}",
+                    MAX_ROYALTY
+                );
+                assert!(
+                    *max_accounts > 0 && *max_accounts <= MAX_ROYALTY_ACCOUNTS,
+                    "FireFly: max_accounts must be between 1 and {}",
+                    MAX_ROYALTY_ACCOUNTS
+                );
+            }
+            AdminAction::UpdateContract { code_hash } => assert!(
+                self.get_staged_code_hash().as_ref() == Some(code_hash),
+                "FireFly: Staged code does not match code_hash"
+            ),
+            AdminAction::SetContractMetadata { metadata } => metadata.assert_valid(),
+            AdminAction::SetProtocolFee { protocol_fee } => assert!(
+                *protocol_fee <= MAX_PROTOCOL_FEE,
+                "FireFly: protocol fee exceeds {}",
+                MAX_PROTOCOL_FEE
+            ),
+            AdminAction::SetReferralFee { referral_fee } => assert!(
+                *referral_fee <= MAX_REFERRAL_FEE,
+                "FireFly: referral fee exceeds {}",
+                MAX_REFERRAL_FEE
+            ),
+            AdminAction::SetTransactionFee { next_fee, start_time } => {
+                assert!(
+                    u128::from(*next_fee) < TRANSACTION_FEE_DENOMINATOR,
+                    "FireFly: transaction fee must be less than {}",
+                    TRANSACTION_FEE_DENOMINATOR
+                );
+                if let Some(start_time) = start_time {
+                    assert!(
+                        start_time.0 > executable_at,
+                        "FireFly: start_time must be after the admin delay"
+                    );
+                }
+            }
+            _ => {}
+        }
+        let pending = PendingAdminAction {
+            action,
+            proposer_id: env::predecessor_account_id(),
+            proposed_at: now,
+            executable_at,
+            confirmations: vec![env::predecessor_account_id()],
+        };
+        self.admin.next_action_id += 1;
+        self.admin.pending_actions.insert(&action_id, &pending);
+
+        env::log_str(
+            json!({
+                "type": "propose_admin_action",
+                "params": pending_admin_action_json(action_id, pending),
+            }).to_string().as_str()
+        );
+
+        action_id.into()
+    }
+
+    /// Record the caller's confirmation on a pending admin action.
+    #[payable]
+    pub fn confirm_admin_action(&mut self, action_id: U64) {
+        assert_one_yocto();
+
+        let mut pending = self.admin.pending_actions.get(&action_id.0).expect("FireFly: Admin action not exist");
+        self.assert_can_govern(&pending.action);
+        let owner_id = env::predecessor_account_id();
+        assert!(
+            !pending.confirmations.contains(&owner_id),
+            "FireFly: Admin action already confirmed"
+        );
+        pending.confirmations.push(owner_id.clone());
+        self.admin.pending_actions.insert(&action_id.0, &pending);
+
+        env::log_str(
+            json!({
+                "type": "confirm_admin_action",
+                "params": {
+                    "action_id": action_id,
+                    "owner_id": owner_id,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Apply a proposed admin action whose timelock has expired and which has been confirmed
+    /// by enough of the current owners.
+    #[payable]
+    pub fn execute_admin_action(&mut self, action_id: U64) {
+        assert_one_yocto();
+
+        let pending = self.admin.pending_actions.get(&action_id.0).expect("FireFly: Admin action not exist");
+        self.assert_can_govern(&pending.action);
+        assert!(
+            env::block_timestamp() >= pending.executable_at,
+            "FireFly: Admin action is still timelocked"
+        );
+        // the DAO runs its own vote, owner confirmations only matter for the remaining actions
+        if !self.is_dao_governed(&pending.action) {
+            // confirmations from accounts that have since been removed from the owner set don't count
+            let confirmations =
+                pending.confirmations.iter().filter(|account_id| self.is_owner(account_id)).count();
+            assert!(
+                confirmations >= self.admin.threshold as usize,
+                "FireFly: Admin action needs {} confirmations",
+                self.admin.threshold
+            );
+        }
+        self.admin.pending_actions.remove(&action_id.0);
+
+        self.internal_apply_admin_action(pending.action.clone());
+
+        env::log_str(
+            json!({
+                "type": "execute_admin_action",
+                "params": {
+                    "action_id": action_id,
+                    "action": pending.action,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Drop a proposed admin action before it is executed.
+    #[payable]
+    pub fn cancel_admin_action(&mut self, action_id: U64) {
+        assert_one_yocto();
+
+        let pending = self.admin.pending_actions.get(&action_id.0).expect("FireFly: Admin action not exist");
+        self.assert_can_govern(&pending.action);
+        self.admin.pending_actions.remove(&action_id.0);
+
+        env::log_str(
+            json!({
+                "type": "cancel_admin_action",
+                "params": {
+                    "action_id": action_id,
+                    "action": pending.action,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Offer `owner_id` to `new_owner`, who has to `accept_ownership` before anything
+    /// changes. A later proposal replaces the pending one. Only for a single owner, an
+    /// owner set is changed with `AdminAction::SetOwners`.
+    #[payable]
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(
+            self.admin.owners.is_empty(),
+            "FireFly: Owner set configured, use AdminAction::SetOwners"
+        );
+        self.admin.pending_owner_id = Some(new_owner.clone());
+
+        env::log_str(
+            json!({
+                "type": "propose_owner",
+                "params": {
+                    "owner_id": self.tokens.core.owner_id,
+                    "new_owner": new_owner,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Take over `owner_id` after being proposed by the current owner.
+    #[payable]
+    pub fn accept_ownership(&mut self) {
+        assert_one_yocto();
+        let new_owner = env::predecessor_account_id();
+        assert_eq!(
+            self.admin.pending_owner_id.as_ref(),
+            Some(&new_owner),
+            "FireFly: Pending owner only"
+        );
+        let previous_owner = std::mem::replace(&mut self.tokens.core.owner_id, new_owner.clone());
+        self.admin.pending_owner_id = None;
+
+        env::log_str(
+            json!({
+                "type": "accept_ownership",
+                "params": {
+                    "previous_owner": previous_owner,
+                    "owner_id": new_owner,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.admin.pending_owner_id.clone()
+    }
+
+    pub fn get_pending_admin_actions(&self) -> Vec<PendingAdminActionJson> {
+        self.admin
+            .pending_actions
+            .iter()
+            .map(|(action_id, pending)| pending_admin_action_json(action_id, pending))
+            .collect()
+    }
+
+    pub fn get_admin_delay(&self) -> U64 {
+        self.admin.delay.into()
+    }
+
+    /// Accounts holding admin authority, the single `owner_id` unless an owner set is configured.
+    pub fn get_owners(&self) -> Vec<AccountId> {
+        if self.admin.owners.is_empty() {
+            vec![self.tokens.core.owner_id.clone()]
+        } else {
+            self.admin.owners.clone()
+        }
+    }
+
+    pub fn get_owner_threshold(&self) -> u32 {
+        self.admin.threshold
+    }
+
+    pub fn get_dao(&self) -> Option<AccountId> {
+        self.admin.dao_id.clone()
+    }
+
+    /// Parameters currently reserved to the DAO, empty while no DAO is configured.
+    pub fn get_dao_controlled_parameters(&self) -> Vec<String> {
+        if self.admin.dao_id.is_some() {
+            DAO_CONTROLLED_PARAMETERS.iter().map(|parameter| parameter.to_string()).collect()
+        } else {
+            vec![]
+        }
+    }
+
+    pub fn get_moderators(&self) -> Vec<AccountId> {
+        self.admin.moderators.clone()
+    }
+
+    pub fn get_config(&self) -> ContractConfigJson {
+        ContractConfigJson {
+            max_price: self.max_price.into(),
+            admin_delay: self.admin.delay.into(),
+            default_royalty: self.default_royalty,
+            max_royalty: self.max_royalty,
+            max_royalty_accounts: self.max_royalty_accounts,
+            metadata_hash_policy: self.metadata_hash_policy,
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn is_owner(&self, account_id: &AccountId) -> bool {
+        if self.admin.owners.is_empty() {
+            account_id == &self.tokens.core.owner_id
+        } else {
+            self.admin.owners.contains(account_id)
+        }
+    }
+
+    /// Owner-only methods act on a single owner's call, so once the owner set requires several
+    /// confirmations they are refused and the change has to go through an admin action.
+    pub(crate) fn assert_owner(&self) {
+        self.assert_in_owner_set();
+        assert!(
+            self.admin.threshold <= 1,
+            "FireFly: Owner set requires {} confirmations, use propose_admin_action",
+            self.admin.threshold
+        );
+    }
+
+    pub(crate) fn assert_in_owner_set(&self) {
+        assert!(self.is_owner(&env::predecessor_account_id()), "FireFly: Owner only");
+    }
+
+    pub(crate) fn is_moderator(&self, account_id: &AccountId) -> bool {
+        self.admin.moderators.contains(account_id)
+    }
+
+    pub(crate) fn assert_owner_or_moderator(&self) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.is_owner(&account_id) || self.is_moderator(&account_id),
+            "FireFly: Owner or moderator only"
+        );
+    }
+
+    fn is_dao_governed(&self, action: &AdminAction) -> bool {
+        self.admin.dao_id.is_some() && action.dao_parameter().is_some()
+    }
+
+    /// DAO-controlled actions belong to the DAO alone, everything else to the owners.
+    fn assert_can_govern(&self, action: &AdminAction) {
+        if self.is_dao_governed(action) {
+            assert_eq!(
+                Some(env::predecessor_account_id()),
+                self.admin.dao_id,
+                "FireFly: DAO only"
+            );
+        } else {
+            self.assert_in_owner_set();
+        }
+    }
+
+    fn internal_apply_admin_action(&mut self, action: AdminAction) {
+        match action {
+            AdminAction::SetAdminDelay { delay } => self.admin.delay = delay.0,
+            AdminAction::SetOwners { owners, threshold } => {
+                self.admin.threshold = if owners.is_empty() { 1 } else { threshold };
+                self.admin.owners = owners;
+            }
+            AdminAction::SetDao { dao_id } => self.admin.dao_id = dao_id,
+            AdminAction::AddModerator { account_id } => {
+                if !self.admin.moderators.contains(&account_id) {
+                    self.admin.moderators.push(account_id);
+                }
+            }
+            AdminAction::RemoveModerator { account_id } => {
+                self.admin.moderators.retain(|moderator_id| moderator_id != &account_id);
+            }
+            AdminAction::SetMaxPrice { max_price } => self.max_price = max_price.0,
+            AdminAction::SetDefaultRoyalty { royalty } => self.default_royalty = royalty,
+            AdminAction::SetRoyaltyLimits { max_royalty, max_accounts } => {
+                self.max_royalty = max_royalty;
+                self.max_royalty_accounts = max_accounts;
+            }
+            AdminAction::SetMetadataHashPolicy { policy } => self.metadata_hash_policy = policy,
+            AdminAction::UpdateContract { code_hash } => {
+                self.internal_update_contract(code_hash);
+            }
+            AdminAction::SetTreasury { treasury_id } => self.treasury_id = treasury_id,
+            AdminAction::SetProtocolFee { protocol_fee } => self.protocol_fee = protocol_fee,
+            AdminAction::SetTransactionFee { next_fee, start_time } => {
+                self.internal_set_transaction_fee(next_fee, start_time);
+            }
+            AdminAction::SetReferralFee { referral_fee } => self.referral_fee = referral_fee,
+            AdminAction::AddMinter { account_id } => {
+                self.minters.insert(&account_id);
+            }
+            AdminAction::RemoveMinter { account_id } => {
+                self.minters.remove(&account_id);
+            }
+            AdminAction::SetContractMetadata { metadata } => self.internal_set_contract_metadata(&metadata),
+            AdminAction::Unpause { flags } => {
+                self.internal_unpause(flags);
+            }
+            AdminAction::BanAccount { account_id } => self.internal_ban_account(&account_id),
+            AdminAction::UnbanAccount { account_id } => self.internal_unban_account(&account_id),
+            AdminAction::AddPaymentFt { ft_contract_id } => self.internal_add_payment_ft(&ft_contract_id),
+            AdminAction::RemovePaymentFt { ft_contract_id } => self.internal_remove_payment_ft(&ft_contract_id),
+            AdminAction::SetStakingRewardRate { token_series_id, reward_rate } => {
+                self.internal_set_staking_reward_rate(token_series_id, reward_rate);
+            }
+        }
+    }
+}
+
+fn assert_valid_owners(owners: &[AccountId], threshold: u32) {
+    if owners.is_empty() {
+        return;
+    }
+    for (i, owner_id) in owners.iter().enumerate() {
+        assert!(!owners[..i].contains(owner_id), "FireFly: Duplicate owner {}", owner_id);
+    }
+    assert!(
+        threshold >= 1 && threshold as usize <= owners.len(),
+        "FireFly: Threshold must be between 1 and the number of owners"
+    );
+}
+
+fn pending_admin_action_json(action_id: AdminActionId, pending: PendingAdminAction) -> PendingAdminActionJson {
+    PendingAdminActionJson {
+        action_id: action_id.into(),
+        action: pending.action,
+        proposer_id: pending.proposer_id,
+        proposed_at: pending.proposed_at.into(),
+        executable_at: pending.executable_at.into(),
+        confirmations: pending.confirmations,
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::test_utils::{get_context};
+    use super::*;
+
+    #[test]
+    fn test_execute_after_delay() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetAdminDelay { delay: U64(MIN_ADMIN_DELAY) });
+        assert_eq!(contract.get_pending_admin_actions().len(), 1);
+
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_admin_delay(), U64(MIN_ADMIN_DELAY));
+        assert!(contract.get_pending_admin_actions().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Admin action is still timelocked")]
+    fn test_execute_before_delay() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetAdminDelay { delay: U64(MIN_ADMIN_DELAY) });
+
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY - 1).build());
+        contract.execute_admin_action(action_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: delay must be between 3600000000000 and 2592000000000000")]
+    fn test_admin_delay_above_max() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.propose_admin_action(AdminAction::SetAdminDelay { delay: U64(MAX_ADMIN_DELAY + 1) });
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: delay must be between 3600000000000 and 2592000000000000")]
+    fn test_admin_delay_below_min() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.propose_admin_action(AdminAction::SetAdminDelay { delay: U64(0) });
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetAdminDelay { delay: U64(MIN_ADMIN_DELAY) });
+        contract.cancel_admin_action(action_id);
+        assert!(contract.get_pending_admin_actions().is_empty());
+        assert_eq!(contract.get_admin_delay(), U64(DEFAULT_ADMIN_DELAY));
+    }
+
+    #[test]
+    fn test_multi_owner_confirmations() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetOwners {
+            owners: vec![accounts(1), accounts(2), accounts(3)],
+            threshold: 2,
+        });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_owners(), vec![accounts(1), accounts(2), accounts(3)]);
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let action_id = contract.propose_admin_action(AdminAction::SetAdminDelay { delay: U64(MIN_ADMIN_DELAY) });
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(2 * DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.confirm_admin_action(action_id);
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_admin_delay(), U64(MIN_ADMIN_DELAY));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Admin action needs 2 confirmations")]
+    fn test_multi_owner_missing_confirmation() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetOwners {
+            owners: vec![accounts(1), accounts(2)],
+            threshold: 2,
+        });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let action_id = contract.propose_admin_action(AdminAction::SetAdminDelay { delay: U64(MIN_ADMIN_DELAY) });
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(2 * DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.execute_admin_action(action_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Owner set requires 2 confirmations, use propose_admin_action")]
+    fn test_multi_owner_direct_call() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetOwners {
+            owners: vec![accounts(1), accounts(2)],
+            threshold: 2,
+        });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.unpause(PauseFlags { minting: true, ..Default::default() });
+    }
+
+    #[test]
+    fn test_multi_owner_unpause() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetOwners {
+            owners: vec![accounts(1), accounts(2)],
+            threshold: 2,
+        });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let flags = PauseFlags { minting: true, ..Default::default() };
+        contract.pause(flags);
+        let action_id = contract.propose_admin_action(AdminAction::Unpause { flags });
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(2 * DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.confirm_admin_action(action_id);
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_paused(), PauseFlags::default());
+    }
+
+    #[test]
+    fn test_dao_controls_moderators() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetDao { dao_id: Some(accounts(4)) });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert!(contract.get_dao_controlled_parameters().contains(&"moderation_roles".to_string()));
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(4))
+            .build());
+        let action_id = contract.propose_admin_action(AdminAction::AddModerator { account_id: accounts(2) });
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(2 * DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(4))
+            .build());
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_moderators(), vec![accounts(2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: DAO only")]
+    fn test_owner_cannot_change_dao_parameter() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetDao { dao_id: Some(accounts(4)) });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+
+        contract.propose_admin_action(AdminAction::AddModerator { account_id: accounts(2) });
+    }
+
+    #[test]
+    fn test_dao_controls_protocol_fee() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetDao { dao_id: Some(accounts(4)) });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert!(contract.get_dao_controlled_parameters().contains(&"protocol_fee".to_string()));
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(4))
+            .build());
+        let action_id = contract.propose_admin_action(AdminAction::SetProtocolFee { protocol_fee: 300 });
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(2 * DEFAULT_ADMIN_DELAY)
+            .predecessor_account_id(accounts(4))
+            .build());
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_protocol_fee(), 300);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: DAO only")]
+    fn test_owner_cannot_change_protocol_fee_under_dao() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetDao { dao_id: Some(accounts(4)) });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+
+        contract.propose_admin_action(AdminAction::SetProtocolFee { protocol_fee: 300 });
+    }
+
+    #[test]
+    fn test_two_step_ownership() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.propose_owner(accounts(1));
+        assert_eq!(contract.get_pending_owner(), Some(accounts(1)));
+        assert_eq!(contract.get_owners(), vec![accounts(0)]);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_owners(), vec![accounts(1)]);
+        assert_eq!(contract.get_pending_owner(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Pending owner only")]
+    fn test_accept_ownership_not_pending_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.propose_owner(accounts(1));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(2)).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    fn test_set_max_price() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        assert_eq!(contract.get_config().max_price, U128(MAX_PRICE));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetMaxPrice { max_price: U128(100) });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_config().max_price, U128(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: max_price must be between 1 and")]
+    fn test_set_max_price_above_ceiling() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.propose_admin_action(AdminAction::SetMaxPrice { max_price: U128(MAX_PRICE + 1) });
+    }
+}
@@ -0,0 +1,214 @@
+//! Allowlist phase of a series: the creator stores the root of a Merkle tree of allowed
+//! account ids and, until the phase ends, only accounts that can prove they are in the tree
+//! may buy, through `nft_mint_allowlisted`.
+//!
+//! Leaves are `sha256(account_id)` and every parent is the sha256 of its two children
+//! concatenated smallest first, so proofs are a plain list of sibling hashes.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::TokenSeriesId;
+use crate::token::Token;
+use crate::utils::refund_deposit;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+
+/// Longest proof accepted, enough for a tree of 2^32 accounts.
+pub const MAX_PROOF_LEN: usize = 32;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Allowlist {
+    pub root: Base64VecU8,
+    /// End of the allowlist phase, after which anyone may `nft_buy`. `None` keeps the series
+    /// allowlist only.
+    pub ends_at: Option<U64>,
+}
+
+impl Allowlist {
+    pub fn is_active(&self) -> bool {
+        match self.ends_at {
+            Some(ends_at) => env::block_timestamp() < ends_at.0,
+            None => true,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set, or with `None` remove, the allowlist of a series. Creator only.
+    #[payable]
+    pub fn nft_set_series_allowlist(&mut self, token_series_id: TokenSeriesId, allowlist: Option<Allowlist>) {
+        let initial_storage_usage = env::storage_usage();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+
+        match &allowlist {
+            Some(allowlist) => {
+                assert_eq!(allowlist.root.0.len(), 32, "FireFly: Allowlist root must be 32 bytes");
+                self.allowlists.insert(&token_series_id, allowlist);
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+            }
+            None => {
+                self.allowlists.remove(&token_series_id);
+                let released = initial_storage_usage.saturating_sub(env::storage_usage());
+                let refund = Balance::from(released) * env::storage_byte_cost() + env::attached_deposit();
+                if refund > 0 {
+                    Promise::new(token_series.creator_id).transfer(refund);
+                }
+            }
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_set_series_allowlist",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "allowlist": allowlist,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    pub fn nft_series_allowlist(&self, token_series_id: TokenSeriesId) -> Option<Allowlist> {
+        self.allowlists.get(&token_series_id)
+    }
+
+    pub fn nft_is_allowlisted(
+        &self,
+        token_series_id: TokenSeriesId,
+        account_id: AccountId,
+        proof: Vec<Base64VecU8>,
+    ) -> bool {
+        self.allowlists
+            .get(&token_series_id)
+            .map(|allowlist| verify_proof(&allowlist.root.0, &account_id, &proof))
+            .unwrap_or(false)
+    }
+
+    /// `nft_buy` for accounts on the allowlist of a series, proven by `proof`. Works during
+    /// and after the allowlist phase.
+    #[payable]
+    pub fn nft_mint_allowlisted(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        receiver_id: AccountId,
+        max_price: U128,
+        proof: Vec<Base64VecU8>,
+    ) -> Token {
+        let allowlist = self.allowlists.get(&token_series_id).expect("FireFly: Token series has no allowlist");
+        assert!(
+            verify_proof(&allowlist.root.0, &env::predecessor_account_id(), &proof),
+            "FireFly: Not on the allowlist"
+        );
+        self.internal_buy(token_series_id, receiver_id, max_price, None)
+    }
+}
+
+impl Contract {
+    /// Panics while `token_series_id` is in its allowlist phase.
+    pub(crate) fn assert_allowlist_over(&self, token_series_id: &TokenSeriesId) {
+        if let Some(allowlist) = self.allowlists.get(token_series_id) {
+            assert!(
+                !allowlist.is_active(),
+                "FireFly: Token series is allowlist only, use nft_mint_allowlisted"
+            );
+        }
+    }
+}
+
+pub(crate) fn verify_proof(root: &[u8], account_id: &AccountId, proof: &[Base64VecU8]) -> bool {
+    if proof.len() > MAX_PROOF_LEN {
+        return false;
+    }
+    let mut hash = env::sha256(account_id.as_bytes());
+    for sibling in proof {
+        hash = hash_pair(&hash, &sibling.0);
+    }
+    hash == root
+}
+
+fn hash_pair(a: &[u8], b: &[u8]) -> Vec<u8> {
+    if a <= b {
+        env::sha256(&[a, b].concat())
+    } else {
+        env::sha256(&[b, a].concat())
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    /// Contract with a free series by alice, allowlisting charlie and danny until 100.
+    fn setup_contract() -> (VMContextBuilder, Contract, TokenSeriesId) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(0)), None);
+        let root = hash_pair(&env::sha256(accounts(2).as_bytes()), &env::sha256(accounts(3).as_bytes()));
+        contract.nft_set_series_allowlist(
+            token_series.token_series_id.clone(),
+            Some(Allowlist { root: Base64VecU8(root), ends_at: Some(U64(100)) }),
+        );
+        (context, contract, token_series.token_series_id)
+    }
+
+    #[test]
+    fn test_mint_allowlisted() {
+        let (mut context, mut contract, token_series_id) = setup_contract();
+        let proof = vec![Base64VecU8(env::sha256(accounts(3).as_bytes()))];
+        assert!(contract.nft_is_allowlisted(token_series_id.clone(), accounts(2), proof.clone()));
+        assert!(!contract.nft_is_allowlisted(token_series_id.clone(), accounts(4), proof.clone()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = contract.nft_mint_allowlisted(token_series_id, accounts(2), U128(0), proof);
+        assert_eq!(token.owner_id, accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token series is allowlist only, use nft_mint_allowlisted")]
+    fn test_buy_during_allowlist_phase() {
+        let (mut context, mut contract, token_series_id) = setup_contract();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(4))
+            .build());
+        contract.nft_buy(token_series_id, accounts(4), U128(0), None);
+    }
+
+    #[test]
+    fn test_buy_after_allowlist_phase() {
+        let (mut context, mut contract, token_series_id) = setup_contract();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(4))
+            .block_timestamp(100)
+            .build());
+        let token = contract.nft_buy(token_series_id, accounts(4), U128(0), None);
+        assert_eq!(token.owner_id, accounts(4));
+    }
+}
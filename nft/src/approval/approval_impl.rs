@@ -1,14 +1,16 @@
 use crate::Contract;
+use crate::ContractExt;
 use crate::approval::ext_nft_approval_receiver;
 /// Common implementation of the [approval management standard](https://nomicon.io/Standards/NonFungibleToken/ApprovalManagement.html) for NFTs.
 /// on the contract/account that has just been approved. This is not required to implement.
-use crate::approval::NonFungibleTokenApproval;
+use crate::approval::{NonFungibleTokenApproval, MAX_APPROVALS_PER_TOKEN};
+use crate::approval_events::{NftApprove, NftRevoke, NftRevokeAll};
 use crate::token::TokenId;
 use crate::utils::{
-    assert_at_least_one_yocto, bytes_for_approved_account_id, refund_approved_account_ids,
-    refund_approved_account_ids_iter, refund_deposit,
+    assert_at_least_one_yocto, bytes_for_approved_account_id, refund_approved_account_ids_iter,
 };
-use near_sdk::{assert_one_yocto, env, require, AccountId, Gas, Promise};
+use near_sdk::json_types::U64;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Gas, Promise};
 
 const GAS_FOR_NFT_APPROVE: Gas = Gas(10_000_000_000_000);
 
@@ -20,28 +22,46 @@ fn expect_approval<T>(option: Option<T>) -> T {
     option.unwrap_or_else(|| env::panic_str("next_approval_by_id must be set for approval ext"))
 }
 
+#[near_bindgen]
 impl NonFungibleTokenApproval for Contract {
+    #[payable]
     fn nft_approve(
         &mut self,
         token_id: TokenId,
         account_id: AccountId,
         msg: Option<String>,
+        expires_at: Option<U64>,
     ) -> Option<Promise> {
         assert_at_least_one_yocto();
+        self.assert_approvals_not_paused();
+        self.assert_not_banned(&env::predecessor_account_id());
+        self.assert_not_banned(&account_id);
+        self.assert_not_soulbound(&token_id);
+        if let Some(expires_at) = expires_at {
+            require!(
+                expires_at.0 > env::block_timestamp(),
+                "FireFly: Approval expiry must be in the future"
+            );
+        }
         let approvals_by_id = self
             .tokens
             .approvals_by_id
             .as_mut()
             .unwrap_or_else(|| env::panic_str("NFT does not support Approval Management"));
 
-        let owner_id = expect_token_found(self.tokens.owner_by_id.get(&token_id));
+        let owner_id = expect_token_found(self.tokens.core.owner_by_id.get(&token_id));
 
         require!(env::predecessor_account_id() == owner_id, "Predecessor must be token owner.");
 
         let next_approval_id_by_id = expect_approval(self.tokens.next_approval_id_by_id.as_mut());
-        // update HashMap of approvals for this token
+        // update approvals for this token
         let approved_account_ids = &mut approvals_by_id.get(&token_id).unwrap_or_default();
         let approval_id: u64 = next_approval_id_by_id.get(&token_id).unwrap_or(1u64);
+        require!(
+            approved_account_ids.get(&account_id).is_some()
+                || approved_account_ids.len() < MAX_APPROVALS_PER_TOKEN,
+            format!("FireFly: Token can have at most {} approvals", MAX_APPROVALS_PER_TOKEN)
+        );
         let old_approval_id = approved_account_ids.insert(account_id.clone(), approval_id);
 
         // save updated approvals HashMap to contract's LookupMap
@@ -50,12 +70,24 @@ impl NonFungibleTokenApproval for Contract {
         // increment next_approval_id for this token
         next_approval_id_by_id.insert(&token_id, &(approval_id + 1));
 
+        // the replaced approval id can't be used anymore, nor can its expiry
+        let expiry_storage_released = old_approval_id
+            .map(|old_approval_id| self.tokens.internal_remove_approval_expiry(&token_id, old_approval_id))
+            .unwrap_or(0);
+        let initial_storage_usage = env::storage_usage();
+        if let (Some(expires_at), Some(by_id)) = (expires_at, self.tokens.approval_expires_at.as_mut()) {
+            by_id.insert(&(token_id.clone(), approval_id), &expires_at.0);
+        }
+        let expiry_storage_used = env::storage_usage() - initial_storage_usage;
+
         // If this approval replaced existing for same account, no storage was used.
         // Otherwise, require that enough deposit was attached to pay for storage, and refund
         // excess.
         let storage_used =
             if old_approval_id.is_none() { bytes_for_approved_account_id(&account_id) } else { 0 };
-        refund_deposit(storage_used);
+        self.internal_charge_storage(0, (storage_used + expiry_storage_used).saturating_sub(expiry_storage_released));
+
+        NftApprove { token_id: &token_id, owner_id: &owner_id, account_id: &account_id, approval_id }.emit();
 
         // if given `msg`, schedule call to `nft_on_approve` and return it. Else, return None.
         msg.map(|msg| {
@@ -65,13 +97,14 @@ impl NonFungibleTokenApproval for Contract {
         })
     }
 
+    #[payable]
     fn nft_revoke(&mut self, token_id: TokenId, account_id: AccountId) {
         assert_one_yocto();
         let approvals_by_id = self.tokens.approvals_by_id.as_mut().unwrap_or_else(|| {
             env::panic_str("NFT does not support Approval Management");
         });
 
-        let owner_id = expect_token_found(self.tokens.owner_by_id.get(&token_id));
+        let owner_id = expect_token_found(self.tokens.core.owner_by_id.get(&token_id));
         let predecessor_account_id = env::predecessor_account_id();
 
         require!(predecessor_account_id == owner_id, "Predecessor must be token owner.");
@@ -79,11 +112,7 @@ impl NonFungibleTokenApproval for Contract {
         // if token has no approvals, do nothing
         if let Some(approved_account_ids) = &mut approvals_by_id.get(&token_id) {
             // if account_id was already not approved, do nothing
-            if approved_account_ids.remove(&account_id).is_some() {
-                refund_approved_account_ids_iter(
-                    predecessor_account_id,
-                    core::iter::once(&account_id),
-                );
+            if let Some(approval_id) = approved_account_ids.remove(&account_id) {
                 // if this was the last approval, remove the whole HashMap to save space.
                 if approved_account_ids.is_empty() {
                     approvals_by_id.remove(&token_id);
@@ -91,27 +120,37 @@ impl NonFungibleTokenApproval for Contract {
                     // otherwise, update approvals_by_id with updated HashMap
                     approvals_by_id.insert(&token_id, approved_account_ids);
                 }
+                let expiry_storage_released =
+                    self.tokens.internal_remove_approval_expiry(&token_id, approval_id);
+                refund_approved_account_ids_iter(
+                    predecessor_account_id,
+                    core::iter::once(&account_id),
+                )
+                .transfer(Balance::from(expiry_storage_released) * env::storage_byte_cost());
+                NftRevoke { token_id: &token_id, owner_id: &owner_id, account_id: &account_id }.emit();
             }
         }
     }
 
+    #[payable]
     fn nft_revoke_all(&mut self, token_id: TokenId) {
         assert_one_yocto();
         let approvals_by_id = self.tokens.approvals_by_id.as_mut().unwrap_or_else(|| {
             env::panic_str("NFT does not support Approval Management");
         });
 
-        let owner_id = expect_token_found(self.tokens.owner_by_id.get(&token_id));
+        let owner_id = expect_token_found(self.tokens.core.owner_by_id.get(&token_id));
         let predecessor_account_id = env::predecessor_account_id();
 
         require!(predecessor_account_id == owner_id, "Predecessor must be token owner.");
 
         // if token has no approvals, do nothing
         if let Some(approved_account_ids) = &mut approvals_by_id.get(&token_id) {
-            // otherwise, refund owner for storage costs of all approvals...
-            refund_approved_account_ids(predecessor_account_id, approved_account_ids);
-            // ...and remove whole HashMap of approvals
+            // otherwise, remove whole HashMap of approvals and their expiries...
             approvals_by_id.remove(&token_id);
+            // ...and refund owner for storage costs of all approvals
+            self.tokens.internal_refund_approvals(&token_id, predecessor_account_id, approved_account_ids);
+            NftRevokeAll { token_id: &token_id, owner_id: &owner_id }.emit();
         }
     }
 
@@ -121,7 +160,7 @@ impl NonFungibleTokenApproval for Contract {
         approved_account_id: AccountId,
         approval_id: Option<u64>,
     ) -> bool {
-        expect_token_found(self.tokens.owner_by_id.get(&token_id));
+        expect_token_found(self.tokens.core.owner_by_id.get(&token_id));
 
         let approvals_by_id = if let Some(a) = self.tokens.approvals_by_id.as_ref() {
             a
@@ -144,8 +183,12 @@ impl NonFungibleTokenApproval for Contract {
             return false;
         };
 
+        if self.tokens.is_approval_expired(&token_id, actual_approval_id) {
+            return false;
+        }
+
         if let Some(given_approval_id) = approval_id {
-            &given_approval_id == actual_approval_id
+            given_approval_id == actual_approval_id
         } else {
             // account approved, no approval_id given
             true
@@ -153,58 +196,28 @@ impl NonFungibleTokenApproval for Contract {
     }
 }
 
-
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use near_sdk::json_types::U128;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::test_utils::accounts;
     use near_sdk::testing_env;
-    use crate::TokenMetadata;
+    use crate::nft_core::{NonFungibleTokenCore, NonFungibleTokenResolver};
 
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
     use super::*;
 
-    // const MINT_STORAGE_COST: u128 = 5870000000000000000000;
-    const MINT_STORAGE_COST: u128 = 5910000000000000000000;
-
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .current_account_id(accounts(0))
-            .signer_account_id(predecessor_account_id.clone())
-            .predecessor_account_id(predecessor_account_id);
-        builder
-    }
-
-    fn sample_token_metadata() -> TokenMetadata {
-        TokenMetadata {
-            title: Some("Olympus Mons".into()),
-            description: Some("The tallest mountain in the charted solar system".into()),
-            media: None,
-            media_hash: None,
-            copies: Some(1u64),
-            issued_at: None,
-            expires_at: None,
-            starts_at: None,
-            updated_at: None,
-            extra: None,
-            reference: None,
-            reference_hash: None,
-        }
-    }
-
     #[test]
     fn test_approve() {
-        const MINT_STORAGE_COST: u128 = 6010000000000000000000;
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
             .build());
-        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128::from(0u128)));
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128::from(0u128)), None);
         let token = contract.nft_mint(token_series.token_series_id, accounts(2));
 
         // alice approves bob
@@ -213,7 +226,11 @@ mod tests {
             .attached_deposit(170000000000000000000)
             .predecessor_account_id(accounts(2))
             .build());
-        contract.nft_approve(token.token_id.clone(), accounts(3), None);
+        contract.nft_approve(token.token_id.clone(), accounts(3), None, None);
+        assert_eq!(
+            near_sdk::test_utils::get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"firefly_approval","version":"1.0.0","event":"nft_approve","data":[{"token_id":"1:1","owner_id":"charlie","account_id":"danny","approval_id":1}]}"#
+        );
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -224,19 +241,101 @@ mod tests {
         assert!(contract.nft_is_approved(token.token_id.clone(), accounts(3), Some(1)));
     }
 
+    #[test]
+    fn test_approval_expires() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_approve(token.token_id.clone(), accounts(3), None, Some(U64(100)));
+        assert!(contract.nft_is_approved(token.token_id.clone(), accounts(3), Some(1)));
+
+        testing_env!(context.block_timestamp(100).is_view(true).attached_deposit(0).build());
+        assert!(!contract.nft_is_approved(token.token_id.clone(), accounts(3), Some(1)));
+        assert!(contract.tokens.is_approval_expired(&token.token_id, 1));
+    }
+
+    #[test]
+    fn test_transfer_and_burn_forget_approval_expiries() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+        let burned_token = contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_approve(token.token_id.clone(), accounts(3), None, Some(U64(100)));
+        contract.nft_approve(burned_token.token_id.clone(), accounts(3), None, Some(U64(100)));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.nft_transfer(accounts(4), token.token_id.clone(), None, None);
+        contract.nft_burn(burned_token.token_id.clone());
+        let approval_expires_at = contract.tokens.approval_expires_at.as_ref().unwrap();
+        assert!(approval_expires_at.get(&(token.token_id, 1)).is_none());
+        assert!(approval_expires_at.get(&(burned_token.token_id, 1)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token can have at most 32 approvals")]
+    fn test_approve_above_limit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        for i in 0..=MAX_APPROVALS_PER_TOKEN {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(300000000000000000000)
+                .predecessor_account_id(accounts(2))
+                .build());
+            let account_id: AccountId = format!("market{}.near", i).parse().unwrap();
+            contract.nft_approve(token.token_id.clone(), account_id, None, None);
+        }
+    }
+
     #[test]
     fn test_revoke() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
             .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0));
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token_id = contract.nft_mint(token_series.token_series_id, accounts(0)).token_id;
 
         // alice approves bob
         testing_env!(context
@@ -244,7 +343,7 @@ mod tests {
             .attached_deposit(150000000000000000000)
             .predecessor_account_id(accounts(0))
             .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
+        contract.nft_approve(token_id.clone(), accounts(1), None, None);
 
         // alice revokes bob
         testing_env!(context
@@ -253,6 +352,10 @@ mod tests {
             .predecessor_account_id(accounts(0))
             .build());
         contract.nft_revoke(token_id.clone(), accounts(1));
+        assert_eq!(
+            near_sdk::test_utils::get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"firefly_approval","version":"1.0.0","event":"nft_revoke","data":[{"token_id":"1:1","owner_id":"alice","account_id":"bob"}]}"#
+        );
         testing_env!(context
             .storage_usage(env::storage_usage())
             .account_balance(env::account_balance())
@@ -266,15 +369,15 @@ mod tests {
     fn test_revoke_all() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
             .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0));
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token_id = contract.nft_mint(token_series.token_series_id, accounts(0)).token_id;
 
         // alice approves bob
         testing_env!(context
@@ -282,7 +385,7 @@ mod tests {
             .attached_deposit(150000000000000000000)
             .predecessor_account_id(accounts(0))
             .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
+        contract.nft_approve(token_id.clone(), accounts(1), None, None);
 
         // alice revokes bob
         testing_env!(context
@@ -291,6 +394,10 @@ mod tests {
             .predecessor_account_id(accounts(0))
             .build());
         contract.nft_revoke_all(token_id.clone());
+        assert_eq!(
+            near_sdk::test_utils::get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"firefly_approval","version":"1.0.0","event":"nft_revoke_all","data":[{"token_id":"1:1","owner_id":"alice"}]}"#
+        );
         testing_env!(context
             .storage_usage(env::storage_usage())
             .account_balance(env::account_balance())
@@ -299,4 +406,86 @@ mod tests {
             .build());
         assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_approved_transfer_event() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token_id = contract.nft_mint(token_series.token_series_id, accounts(0)).token_id;
+
+        // alice approves bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(150000000000000000000)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None, None);
+
+        // bob transfers to charlie on behalf of alice
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_transfer(accounts(2), token_id, None, Some("sale #1".to_string()));
+        assert_eq!(
+            near_sdk::test_utils::get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"alice","new_owner_id":"charlie","token_ids":["1:1"],"authorized_id":"bob","memo":"sale #1"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_resolve_transfer_restores_approvals() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token_id = contract.nft_mint(token_series.token_series_id, accounts(0)).token_id;
+
+        // alice approves bob, then sends the token to charlie's contract
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(150000000000000000000)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None, None);
+        let approvals = contract.tokens.approvals_by_id.as_ref().unwrap().get(&token_id);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .prepaid_gas(near_sdk::Gas(300_000_000_000_000))
+            .build());
+        contract.nft_transfer_call(accounts(2), token_id.clone(), None, None, "".to_string());
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), None));
+
+        // charlie's contract returns the token
+        testing_env!(
+            context.build(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(b"true".to_vec())],
+        );
+        assert!(!contract.nft_resolve_transfer(accounts(0), accounts(2), token_id.clone(), approvals));
+        assert!(contract.nft_is_owner(token_id.clone(), accounts(0)));
+        assert!(contract.nft_is_approved(token_id, accounts(1), None));
+        assert_eq!(
+            near_sdk::test_utils::get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"charlie","new_owner_id":"alice","token_ids":["1:1"]}]}"#
+        );
+    }
+}
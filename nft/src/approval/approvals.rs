@@ -0,0 +1,98 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::AccountId;
+use std::collections::HashMap;
+
+/// Most accounts a single token can be approved for at once.
+pub const MAX_APPROVALS_PER_TOKEN: usize = 32;
+
+/// Approved accounts of a token and their approval ids.
+///
+/// Stored as a short vector instead of a `HashMap`, so reading it is a plain scan without
+/// rebuilding a hash table. Borsh encodes a `HashMap` as its length followed by the entries
+/// sorted by key, which is also a valid encoding of this vector, so approvals written by
+/// earlier versions of the contract load unchanged. In JSON it is still the standard
+/// `{ account_id: approval_id }` object.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde", from = "HashMap<AccountId, u64>", into = "HashMap<AccountId, u64>")]
+pub struct Approvals(Vec<(AccountId, u64)>);
+
+impl Approvals {
+    pub fn get(&self, account_id: &AccountId) -> Option<u64> {
+        self.0.iter().find(|(id, _)| id == account_id).map(|(_, approval_id)| *approval_id)
+    }
+
+    /// Set the approval id of `account_id`, returning the one it replaced.
+    pub fn insert(&mut self, account_id: AccountId, approval_id: u64) -> Option<u64> {
+        match self.0.iter_mut().find(|(id, _)| id == &account_id) {
+            Some((_, old_approval_id)) => Some(std::mem::replace(old_approval_id, approval_id)),
+            None => {
+                self.0.push((account_id, approval_id));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, account_id: &AccountId) -> Option<u64> {
+        let index = self.0.iter().position(|(id, _)| id == account_id)?;
+        Some(self.0.swap_remove(index).1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn account_ids(&self) -> impl Iterator<Item = &AccountId> {
+        self.0.iter().map(|(id, _)| id)
+    }
+
+    pub fn approval_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.0.iter().map(|(_, approval_id)| *approval_id)
+    }
+
+    pub fn into_map(self) -> HashMap<AccountId, u64> {
+        self.0.into_iter().collect()
+    }
+}
+
+impl From<HashMap<AccountId, u64>> for Approvals {
+    fn from(map: HashMap<AccountId, u64>) -> Self {
+        Self(map.into_iter().collect())
+    }
+}
+
+impl From<Approvals> for HashMap<AccountId, u64> {
+    fn from(approvals: Approvals) -> Self {
+        approvals.into_map()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use super::*;
+
+    #[test]
+    fn test_reads_hash_map_encoding() {
+        let map = HashMap::from([(accounts(2), 3u64), (accounts(1), 7u64)]);
+        let approvals = Approvals::try_from_slice(&map.try_to_vec().unwrap()).unwrap();
+        assert_eq!(approvals.len(), 2);
+        assert_eq!(approvals.get(&accounts(1)), Some(7));
+        assert_eq!(approvals.get(&accounts(2)), Some(3));
+        assert_eq!(approvals.into_map(), map);
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut approvals = Approvals::default();
+        assert_eq!(approvals.insert(accounts(1), 1), None);
+        assert_eq!(approvals.insert(accounts(1), 2), Some(1));
+        assert_eq!(approvals.len(), 1);
+        assert_eq!(approvals.remove(&accounts(1)), Some(2));
+        assert!(approvals.is_empty());
+    }
+}
@@ -1,10 +1,14 @@
 mod approval_impl;
 mod approval_receiver;
+mod approvals;
+pub mod operator;
 
 pub use approval_impl::*;
 pub use approval_receiver::*;
+pub use approvals::*;
 
 use crate::token::TokenId;
+use near_sdk::json_types::U64;
 use near_sdk::AccountId;
 use near_sdk::Promise;
 
@@ -37,6 +41,8 @@ pub trait NonFungibleTokenApproval {
     /// * `token_id`: the token for which to add an approval
     /// * `account_id`: the account to add to `approvals`
     /// * `msg`: optional string to be passed to `nft_on_approve`
+    /// * `expires_at`: optional block timestamp, in nanoseconds, after which the approval is
+    ///   treated as absent and can no longer be used to transfer the token
     ///
     /// Returns void, if no `msg` given. Otherwise, returns promise call to
     /// `nft_on_approve`, which can resolve with whatever it wants.
@@ -45,6 +51,7 @@ pub trait NonFungibleTokenApproval {
         token_id: TokenId,
         account_id: AccountId,
         msg: Option<String>,
+        expires_at: Option<U64>,
     ) -> Option<Promise>;
 
     /// Revoke an approved account for a specific token.
@@ -84,7 +91,8 @@ pub trait NonFungibleTokenApproval {
     ///
     /// Returns:
     /// if `approval_id` given, `true` if `approved_account_id` is approved with given `approval_id`
-    /// otherwise, `true` if `approved_account_id` is in list of approved accounts
+    /// otherwise, `true` if `approved_account_id` is in list of approved accounts.
+    /// Always `false` once the approval has expired
     fn nft_is_approved(
         &self,
         token_id: TokenId,
@@ -0,0 +1,133 @@
+//! Approval for all: an owner approves an operator, typically a marketplace, for every token
+//! they hold or will hold, instead of one `nft_approve` per token. Operators are honored by
+//! `nft_transfer` and `nft_transfer_call` like a per-token approval without an approval id.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::approval_events::{NftApproveAll, NftRevokeAllFor};
+use crate::utils::{assert_at_least_one_yocto, bytes_for_approved_account_id, refund_approved_account_ids_iter, refund_deposit};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId};
+
+/// Most operators a single owner can approve at once.
+pub const MAX_OPERATORS_PER_OWNER: usize = 16;
+
+#[near_bindgen]
+impl Contract {
+    /// Approve `account_id` to transfer any token of the caller. The caller pays for storage.
+    #[payable]
+    pub fn nft_approve_all(&mut self, account_id: AccountId) {
+        assert_at_least_one_yocto();
+        self.assert_approvals_not_paused();
+        let owner_id = env::predecessor_account_id();
+        self.assert_not_banned(&owner_id);
+        self.assert_not_banned(&account_id);
+        let operators_by_owner = self
+            .tokens
+            .operators_by_owner
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("NFT does not support Approval Management"));
+        let mut operators = operators_by_owner.get(&owner_id).unwrap_or_default();
+        if operators.contains(&account_id) {
+            refund_deposit(0);
+            return;
+        }
+        assert!(
+            operators.len() < MAX_OPERATORS_PER_OWNER,
+            "FireFly: At most {} operators per owner",
+            MAX_OPERATORS_PER_OWNER
+        );
+        operators.push(account_id.clone());
+        operators_by_owner.insert(&owner_id, &operators);
+
+        NftApproveAll { owner_id: &owner_id, account_id: &account_id }.emit();
+        self.internal_charge_storage(0, bytes_for_approved_account_id(&account_id));
+    }
+
+    /// Withdraw the approval for all tokens given to `account_id`, refunding its storage.
+    #[payable]
+    pub fn nft_revoke_all_for(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let operators_by_owner = self
+            .tokens
+            .operators_by_owner
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("NFT does not support Approval Management"));
+        if let Some(mut operators) = operators_by_owner.get(&owner_id) {
+            let len = operators.len();
+            operators.retain(|operator_id| operator_id != &account_id);
+            if operators.len() == len {
+                return;
+            }
+            if operators.is_empty() {
+                operators_by_owner.remove(&owner_id);
+            } else {
+                operators_by_owner.insert(&owner_id, &operators);
+            }
+            refund_approved_account_ids_iter(owner_id.clone(), core::iter::once(&account_id));
+            NftRevokeAllFor { owner_id: &owner_id, account_id: &account_id }.emit();
+        }
+    }
+
+    pub fn nft_is_approved_for_all(&self, owner_id: AccountId, account_id: AccountId) -> bool {
+        self.tokens.is_operator(&owner_id, &account_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    fn test_operator_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(190000000000000000000)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_approve_all(accounts(2));
+        assert!(contract.nft_is_approved_for_all(accounts(1), accounts(2)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_transfer(accounts(3), token.token_id.clone(), None, None);
+        assert!(contract.nft_is_owner(token.token_id, accounts(3)));
+    }
+
+    #[test]
+    fn test_revoke_all_for() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(190000000000000000000)
+            .build());
+        contract.nft_approve_all(accounts(2));
+        contract.nft_approve_all(accounts(3));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.nft_revoke_all_for(accounts(2));
+        assert!(!contract.nft_is_approved_for_all(accounts(1), accounts(2)));
+        assert!(contract.nft_is_approved_for_all(accounts(1), accounts(3)));
+    }
+}
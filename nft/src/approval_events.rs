@@ -0,0 +1,143 @@
+//! Events of token approvals, logged in the events format (nep-297) under the
+//! `firefly_approval` standard. nep171 defines no approval events, so they aren't logged
+//! under its name.
+//!
+//! Approving an account for a token is logged as [`NftApprove`], revoking it as
+//! [`NftRevoke`] and revoking every approval of a token as [`NftRevokeAll`]. Approving an
+//! operator for all tokens of an owner is logged as [`NftApproveAll`], revoking it as
+//! [`NftRevokeAllFor`].
+//!
+//! <https://github.com/near/NEPs/blob/master/specs/Standards/EventsFormat.md>
+
+use crate::event::NearEvent;
+use near_sdk::AccountId;
+use serde::Serialize;
+
+/// Data to log when an account is approved for a token. To log this event,
+/// call [`.emit()`](NftApprove::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct NftApprove<'a> {
+    pub token_id: &'a str,
+    pub owner_id: &'a AccountId,
+    pub account_id: &'a AccountId,
+    pub approval_id: u64,
+}
+
+impl NftApprove<'_> {
+    pub fn emit(self) {
+        new_approval_v1(ApprovalEventKind::NftApprove(&[self])).emit()
+    }
+}
+
+/// Data to log when the approval of an account is revoked. To log this event,
+/// call [`.emit()`](NftRevoke::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct NftRevoke<'a> {
+    pub token_id: &'a str,
+    pub owner_id: &'a AccountId,
+    pub account_id: &'a AccountId,
+}
+
+impl NftRevoke<'_> {
+    pub fn emit(self) {
+        new_approval_v1(ApprovalEventKind::NftRevoke(&[self])).emit()
+    }
+}
+
+/// Data to log when all approvals of a token are revoked. To log this event,
+/// call [`.emit()`](NftRevokeAll::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct NftRevokeAll<'a> {
+    pub token_id: &'a str,
+    pub owner_id: &'a AccountId,
+}
+
+impl NftRevokeAll<'_> {
+    pub fn emit(self) {
+        new_approval_v1(ApprovalEventKind::NftRevokeAll(&[self])).emit()
+    }
+}
+
+/// Data to log when an account is approved for all tokens of an owner. To log this event,
+/// call [`.emit()`](NftApproveAll::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct NftApproveAll<'a> {
+    pub owner_id: &'a AccountId,
+    pub account_id: &'a AccountId,
+}
+
+impl NftApproveAll<'_> {
+    pub fn emit(self) {
+        new_approval_v1(ApprovalEventKind::NftApproveAll(&[self])).emit()
+    }
+}
+
+/// Data to log when an approval for all tokens of an owner is revoked. To log this event,
+/// call [`.emit()`](NftRevokeAllFor::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct NftRevokeAllFor<'a> {
+    pub owner_id: &'a AccountId,
+    pub account_id: &'a AccountId,
+}
+
+impl NftRevokeAllFor<'_> {
+    pub fn emit(self) {
+        new_approval_v1(ApprovalEventKind::NftRevokeAllFor(&[self])).emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ApprovalEvent<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: ApprovalEventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+enum ApprovalEventKind<'a> {
+    NftApprove(&'a [NftApprove<'a>]),
+    NftRevoke(&'a [NftRevoke<'a>]),
+    NftRevokeAll(&'a [NftRevokeAll<'a>]),
+    NftApproveAll(&'a [NftApproveAll<'a>]),
+    NftRevokeAllFor(&'a [NftRevokeAllFor<'a>]),
+}
+
+fn new_approval_v1(event_kind: ApprovalEventKind) -> NearEvent {
+    NearEvent::FireflyApproval(ApprovalEvent { version: "1.0.0", event_kind })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils;
+
+    #[test]
+    fn nft_approve() {
+        let owner_id = &AccountId::new_unchecked("bob".to_string());
+        let account_id = &AccountId::new_unchecked("alice".to_string());
+        NftApprove { token_id: "1:1", owner_id, account_id, approval_id: 3 }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_approval","version":"1.0.0","event":"nft_approve","data":[{"token_id":"1:1","owner_id":"bob","account_id":"alice","approval_id":3}]}"#
+        );
+    }
+
+    #[test]
+    fn nft_approve_all() {
+        let owner_id = &AccountId::new_unchecked("bob".to_string());
+        let account_id = &AccountId::new_unchecked("alice".to_string());
+        NftApproveAll { owner_id, account_id }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_approval","version":"1.0.0","event":"nft_approve_all","data":[{"owner_id":"bob","account_id":"alice"}]}"#
+        );
+    }
+}
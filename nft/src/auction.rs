@@ -0,0 +1,267 @@
+//! English auctions of minted tokens, so simple sales don't need an external marketplace.
+//! The seller's token is held by the contract while the auction runs. Bids are escrowed on
+//! the contract and an outbid bidder is refunded right away, as in `mint_auction`. Once the
+//! auction has ended anyone can finalize it: the winner gets the token and the winning bid is
+//! credited, minus the protocol fee, to the series royalty holders and the seller.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::events::NftTransfer;
+use crate::mint_auction::MIN_BID_INCREMENT_BPS;
+use crate::token::TokenId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenAuction {
+    pub seller_id: AccountId,
+    pub reserve_price: U128,
+    pub ends_at: U64,
+    pub bidder_id: Option<AccountId>,
+    pub bid: U128,
+}
+
+impl TokenAuction {
+    /// Smallest deposit `nft_auction_bid` currently accepts.
+    pub fn min_bid(&self) -> Balance {
+        if self.bidder_id.is_none() {
+            self.reserve_price.0
+        } else {
+            self.bid.0 + self.bid.0 * MIN_BID_INCREMENT_BPS / 10_000
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Auction one of the caller's tokens until `ends_at` (nanoseconds), starting at
+    /// `reserve_price`. The caller pays for the storage.
+    #[payable]
+    pub fn nft_start_auction(&mut self, token_id: TokenId, reserve_price: U128, ends_at: U64) {
+        assert_at_least_one_yocto();
+        self.assert_transfers_not_paused();
+        let seller_id = env::predecessor_account_id();
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        assert_eq!(owner_id, seller_id, "FireFly: Token owner only");
+        assert!(reserve_price.0 > 0, "FireFly: reserve_price must be positive");
+        assert!(reserve_price.0 < self.max_price, "FireFly: price higher than {}", self.max_price);
+        assert!(ends_at.0 > env::block_timestamp(), "FireFly: ends_at must be in the future");
+        self.assert_not_banned(&seller_id);
+        self.assert_not_soulbound(&token_id);
+        self.assert_not_locked(&token_id);
+
+        let initial_storage_usage = env::storage_usage();
+        self.internal_clear_rental(&token_id);
+        self.internal_record_transfer(&token_id);
+        self.tokens.internal_remove_approvals(&token_id);
+        let contract_id = env::current_account_id();
+        self.tokens.internal_transfer_unguarded(&token_id, &seller_id, &contract_id);
+        let auction = TokenAuction { seller_id: seller_id.clone(), reserve_price, ends_at, bidder_id: None, bid: U128(0) };
+        self.auctions.insert(&token_id, &auction);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        env::log_str(
+            json!({
+                "type": "nft_start_auction",
+                "params": {
+                    "token_id": token_id,
+                    "seller_id": seller_id,
+                    "reserve_price": reserve_price,
+                    "ends_at": ends_at,
+                }
+            }).to_string().as_str()
+        );
+        NftTransfer {
+            old_owner_id: &seller_id,
+            new_owner_id: &contract_id,
+            token_ids: &[&token_id],
+            authorized_id: None,
+            memo: Some("start_auction"),
+        }
+        .emit();
+    }
+
+    /// Bid the attached deposit. The previous highest bidder is refunded.
+    #[payable]
+    pub fn nft_auction_bid(&mut self, token_id: TokenId) {
+        let mut auction = self.auctions.get(&token_id).expect("FireFly: No auction for token");
+        assert!(env::block_timestamp() < auction.ends_at.0, "FireFly: Auction has ended");
+        let bid = env::attached_deposit();
+        let min_bid = auction.min_bid();
+        assert!(bid >= min_bid, "FireFly: Bid must be at least {}", min_bid);
+
+        let bidder_id = env::predecessor_account_id();
+        assert_ne!(bidder_id, auction.seller_id, "FireFly: Seller cannot bid");
+        self.assert_not_banned(&bidder_id);
+        if let Some(previous_bidder_id) = auction.bidder_id.replace(bidder_id.clone()) {
+            self.internal_payout(previous_bidder_id, auction.bid.0);
+        }
+        auction.bid = U128(bid);
+        self.auctions.insert(&token_id, &auction);
+
+        env::log_str(
+            json!({
+                "type": "nft_auction_bid",
+                "params": {
+                    "token_id": token_id,
+                    "bidder_id": bidder_id,
+                    "bid": U128(bid),
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Close an auction that has ended. The token goes to the highest bidder, or back to the
+    /// seller without bids. The winner's storage is taken from the proceeds. Callable by anyone.
+    pub fn finalize_auction(&mut self, token_id: TokenId) -> AccountId {
+        let auction = self.auctions.get(&token_id).expect("FireFly: No auction for token");
+        assert!(env::block_timestamp() >= auction.ends_at.0, "FireFly: Auction is still running");
+        self.assert_transfers_not_paused();
+
+        let initial_storage_usage = env::storage_usage();
+        self.auctions.remove(&token_id);
+        let new_owner_id = auction.bidder_id.clone().unwrap_or_else(|| auction.seller_id.clone());
+        let contract_id = env::current_account_id();
+        self.tokens.internal_transfer_unguarded(&token_id, &contract_id, &new_owner_id);
+        let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+
+        // the auction entry was paid for by the seller
+        let mut seller_refund = Balance::from(storage_released) * env::storage_byte_cost();
+        if auction.bidder_id.is_some() {
+            let storage_cost = Balance::from(storage_used) * env::storage_byte_cost();
+            let proceeds = self.internal_take_fees(auction.bid.0, 0).saturating_sub(storage_cost);
+            let payout = self.internal_compute_payout(&token_id, &auction.seller_id, proceeds, None);
+            for (account_id, amount) in payout.payout {
+                if account_id == auction.seller_id {
+                    seller_refund += amount.0;
+                } else {
+                    self.internal_credit_revenue(&account_id, amount.0);
+                }
+            }
+        }
+        self.internal_credit_revenue(&auction.seller_id, seller_refund);
+
+        env::log_str(
+            json!({
+                "type": "finalize_auction",
+                "params": {
+                    "token_id": token_id,
+                    "seller_id": auction.seller_id,
+                    "owner_id": new_owner_id,
+                    "price": auction.bidder_id.as_ref().map(|_| auction.bid),
+                }
+            }).to_string().as_str()
+        );
+        NftTransfer {
+            old_owner_id: &contract_id,
+            new_owner_id: &new_owner_id,
+            token_ids: &[&token_id],
+            authorized_id: None,
+            memo: Some("finalize_auction"),
+        }
+        .emit();
+        new_owner_id
+    }
+
+    /// Stop an auction nobody has bid on and take the token back. Seller only.
+    #[payable]
+    pub fn nft_cancel_auction(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let auction = self.auctions.get(&token_id).expect("FireFly: No auction for token");
+        let seller_id = env::predecessor_account_id();
+        assert_eq!(seller_id, auction.seller_id, "FireFly: Seller only");
+        assert!(auction.bidder_id.is_none(), "FireFly: Auction already has bids");
+
+        let initial_storage_usage = env::storage_usage();
+        self.auctions.remove(&token_id);
+        let contract_id = env::current_account_id();
+        self.tokens.internal_transfer_unguarded(&token_id, &contract_id, &seller_id);
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(seller_id.clone())
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+
+        NftTransfer {
+            old_owner_id: &contract_id,
+            new_owner_id: &seller_id,
+            token_ids: &[&token_id],
+            authorized_id: None,
+            memo: Some("cancel_auction"),
+        }
+        .emit();
+    }
+
+    pub fn nft_auction(&self, token_id: TokenId) -> Option<TokenAuction> {
+        self.auctions.get(&token_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    const ONE_NEAR: u128 = 10u128.pow(24);
+
+    fn setup_auction(context: &mut VMContextBuilder) -> (Contract, TokenId) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_start_auction(token.token_id.clone(), U128(ONE_NEAR), U64(100));
+
+        testing_env!(context.attached_deposit(ONE_NEAR).predecessor_account_id(accounts(2)).build());
+        contract.nft_auction_bid(token.token_id.clone());
+        (contract, token.token_id)
+    }
+
+    #[test]
+    fn test_auction() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_auction(&mut context);
+
+        testing_env!(context.attached_deposit(2 * ONE_NEAR).predecessor_account_id(accounts(3)).build());
+        contract.nft_auction_bid(token_id.clone());
+        assert_eq!(contract.nft_auction(token_id.clone()).unwrap().bidder_id, Some(accounts(3)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .block_timestamp(100)
+            .build());
+        assert_eq!(contract.finalize_auction(token_id.clone()), accounts(3));
+        assert_eq!(contract.nft_token(token_id.clone()).unwrap().owner_id, accounts(3));
+        assert_eq!(contract.nft_auction(token_id), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Auction already has bids")]
+    fn test_cancel_auction_with_bids() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_auction(&mut context);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.nft_cancel_auction(token_id);
+    }
+}
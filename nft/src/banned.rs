@@ -0,0 +1,159 @@
+//! Accounts banned for compliance reasons. A banned account can't mint, buy, send, receive
+//! or approve tokens, and tokens it owns can't be moved by anyone else either.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::token::TokenId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+
+#[near_bindgen]
+impl Contract {
+    /// Owner only, the caller pays for the storage. Owner sets requiring several
+    /// confirmations use `AdminAction::BanAccount`.
+    #[payable]
+    pub fn ban_account(&mut self, account_id: AccountId) {
+        assert_at_least_one_yocto();
+        self.assert_owner();
+        let initial_storage_usage = env::storage_usage();
+        self.internal_ban_account(&account_id);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Owner only, or `AdminAction::UnbanAccount` for owner sets requiring several confirmations.
+    #[payable]
+    pub fn unban_account(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        let initial_storage_usage = env::storage_usage();
+        self.internal_unban_account(&account_id);
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(env::predecessor_account_id())
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+    }
+
+    pub fn is_account_banned(&self, account_id: AccountId) -> bool {
+        self.banned_accounts.contains(&account_id)
+    }
+
+    pub fn get_banned_accounts(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<AccountId> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        self.banned_accounts
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_ban_account(&mut self, account_id: &AccountId) {
+        if self.banned_accounts.insert(account_id) {
+            env::log_str(
+                json!({
+                    "type": "ban_account",
+                    "params": {
+                        "account_id": account_id,
+                    }
+                }).to_string().as_str()
+            );
+        }
+    }
+
+    pub(crate) fn internal_unban_account(&mut self, account_id: &AccountId) {
+        if self.banned_accounts.remove(account_id) {
+            env::log_str(
+                json!({
+                    "type": "unban_account",
+                    "params": {
+                        "account_id": account_id,
+                    }
+                }).to_string().as_str()
+            );
+        }
+    }
+
+    pub(crate) fn assert_not_banned(&self, account_id: &AccountId) {
+        assert!(
+            !self.banned_accounts.contains(account_id),
+            "FireFly: Account {} is banned",
+            account_id
+        );
+    }
+
+    /// Panics if the caller, the owner of `token_id` or `receiver_id` is banned.
+    pub(crate) fn assert_transfer_not_banned(&self, token_id: &TokenId, receiver_id: &AccountId) {
+        self.assert_not_banned(&env::predecessor_account_id());
+        self.assert_not_banned(receiver_id);
+        if let Some(owner_id) = self.tokens.core.owner_by_id.get(token_id) {
+            self.assert_not_banned(&owner_id);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "FireFly: Account bob is banned")]
+    fn test_banned_owner_cannot_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+        contract.ban_account(accounts(1));
+        assert_eq!(contract.get_banned_accounts(None, None), vec![accounts(1)]);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.nft_transfer(accounts(2), token.token_id, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Account charlie is banned")]
+    fn test_mint_to_banned_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.ban_account(accounts(2));
+        contract.nft_mint(token_series.token_series_id, accounts(2));
+    }
+
+    #[test]
+    fn test_unban_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.ban_account(accounts(2));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.unban_account(accounts(2));
+        assert!(!contract.is_account_banned(accounts(2)));
+    }
+}
@@ -0,0 +1,247 @@
+//! Linkdrop-style claims. A creator registers ed25519 public keys for one of their series and
+//! hands out the private keys, e.g. as QR codes at an event. Whoever holds a key claims an
+//! edition for their own account with `nft_claim`, signing their account id with it, so the
+//! creator never needs to know who the recipients are and a signature seen in flight can't
+//! be used for another account. The creator prepays the storage of every claimed token.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::TokenSeriesId;
+use crate::token::Token;
+use ed25519_dalek::Verifier;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, CurveType, Promise, PublicKey};
+use serde_json::json;
+
+/// Most claims `nft_add_claims` registers in one call.
+pub const MAX_CLAIMS_PER_CALL: usize = 50;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Claim {
+    pub token_series_id: TokenSeriesId,
+    /// Account that added the claim, refunded what its deposit doesn't spend
+    pub funder_id: AccountId,
+    /// Set aside for the storage of the claimed token
+    pub deposit: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Register one claim per public key for a series. Creator only. The attached deposit,
+    /// minus the storage of the claims themselves, is split evenly between them to pay for
+    /// the storage of the claimed tokens; what a claim doesn't spend goes back to the caller.
+    #[payable]
+    pub fn nft_add_claims(&mut self, token_series_id: TokenSeriesId, public_keys: Vec<PublicKey>) {
+        assert!(
+            !public_keys.is_empty() && public_keys.len() <= MAX_CLAIMS_PER_CALL,
+            "FireFly: Must add between 1 and {} claims",
+            MAX_CLAIMS_PER_CALL
+        );
+        let initial_storage_usage = env::storage_usage();
+        let funder_id = env::predecessor_account_id();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(funder_id, token_series.creator_id, "FireFly: Creator only");
+
+        // Every entry is the same size, so each claim is given its share of the deposit
+        // once the entries are written.
+        let mut claim = Claim { token_series_id: token_series_id.clone(), funder_id, deposit: U128(0) };
+        for public_key in public_keys.iter() {
+            assert!(
+                matches!(public_key.curve_type(), CurveType::ED25519),
+                "FireFly: Claim key must be ed25519"
+            );
+            assert!(self.claims.insert(public_key, &claim).is_none(), "FireFly: Claim key already used");
+        }
+        let storage_cost = env::storage_byte_cost()
+            * Balance::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        let attached_deposit = env::attached_deposit();
+        assert!(
+            attached_deposit > storage_cost,
+            "FireFly: Must attach more than {} yoctoNEAR to cover claims storage",
+            storage_cost
+        );
+        claim.deposit = U128((attached_deposit - storage_cost) / public_keys.len() as Balance);
+        for public_key in public_keys.iter() {
+            self.claims.insert(public_key, &claim);
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_add_claims",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "public_keys": public_keys,
+                    "deposit": claim.deposit,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Withdraw unclaimed claims added by the caller, refunding their deposits and storage.
+    #[payable]
+    pub fn nft_remove_claims(&mut self, public_keys: Vec<PublicKey>) {
+        assert_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let funder_id = env::predecessor_account_id();
+        let mut refund: Balance = 0;
+        for public_key in public_keys.iter() {
+            let claim = self.claims.get(public_key).expect("FireFly: Claim not found");
+            assert_eq!(claim.funder_id, funder_id, "FireFly: Claim funder only");
+            self.claims.remove(public_key);
+            refund += claim.deposit.0;
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_remove_claims",
+                "params": {
+                    "funder_id": funder_id,
+                    "public_keys": public_keys,
+                }
+            }).to_string().as_str()
+        );
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        refund += Balance::from(storage_released) * env::storage_byte_cost();
+        if refund > 0 {
+            Promise::new(funder_id).transfer(refund);
+        }
+    }
+
+    /// Claim registered for `public_key`, `None` once it has been claimed.
+    pub fn nft_claim_info(&self, public_key: PublicKey) -> Option<Claim> {
+        self.claims.get(&public_key)
+    }
+
+    /// Mint an edition to the caller. `signature` is the signature, by the private key of
+    /// `public_key`, of this contract's account id followed by the Borsh encoded caller id.
+    pub fn nft_claim(&mut self, public_key: PublicKey, signature: Base64VecU8) -> Token {
+        let initial_storage_usage = env::storage_usage();
+        let receiver_id = env::predecessor_account_id();
+        let claim = self.claims.get(&public_key).expect("FireFly: Claim not found");
+        assert_valid_claim_signature(&public_key, &receiver_id, &signature.0);
+        self.assert_minting_not_paused();
+        self.assert_not_banned(&receiver_id);
+        self.assert_receiver_allowed(&receiver_id);
+        self.assert_open_edition_window(&claim.token_series_id);
+        self.assert_not_auctioned(&claim.token_series_id);
+
+        self.claims.remove(&public_key);
+        let token_series = self
+            .token_series_by_id
+            .get(&claim.token_series_id)
+            .expect("FireFly: Token series not exist");
+        self.internal_record_wallet_mint(&claim.token_series_id, &token_series, &receiver_id);
+        let token = self.tokens.internal_mint(
+            &mut self.token_series_by_id,
+            claim.token_series_id.clone(),
+            receiver_id.clone(),
+            None,
+        );
+
+        // the storage of the removed claim was paid by the funder too, it offsets the token's
+        let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        let storage_cost = Balance::from(storage_used) * env::storage_byte_cost();
+        assert!(storage_cost <= claim.deposit.0, "FireFly: Claim deposit does not cover storage");
+        let refund = claim.deposit.0 - storage_cost + Balance::from(storage_released) * env::storage_byte_cost();
+        if refund > 0 {
+            Promise::new(claim.funder_id).transfer(refund);
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_claim",
+                "params": {
+                    "token_series_id": claim.token_series_id,
+                    "token_id": token.token_id,
+                    "receiver_id": receiver_id,
+                    "public_key": public_key,
+                }
+            }).to_string().as_str()
+        );
+        token
+    }
+}
+
+fn assert_valid_claim_signature(public_key: &PublicKey, receiver_id: &AccountId, signature: &[u8]) {
+    let mut payload = env::current_account_id().as_bytes().to_vec();
+    payload.extend(receiver_id.try_to_vec().expect("FireFly: Cannot serialize account id"));
+    // the first byte of a near `PublicKey` is its curve type
+    let public_key = ed25519_dalek::PublicKey::from_bytes(&public_key.as_bytes()[1..])
+        .unwrap_or_else(|_| env::panic_str("FireFly: Invalid claim key"));
+    let signature = ed25519_dalek::Signature::from_bytes(signature)
+        .unwrap_or_else(|_| env::panic_str("FireFly: Invalid claim signature"));
+    assert!(public_key.verify(&payload, &signature).is_ok(), "FireFly: Invalid claim signature");
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+    use std::convert::TryFrom;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn keypair() -> Keypair {
+        let secret = SecretKey::from_bytes(&[7u8; 32]).unwrap();
+        let public = (&secret).into();
+        Keypair { secret, public }
+    }
+
+    fn sign(receiver_id: &AccountId) -> Base64VecU8 {
+        let mut payload = accounts(0).as_bytes().to_vec();
+        payload.extend(receiver_id.try_to_vec().unwrap());
+        Base64VecU8(keypair().sign(&payload).to_bytes().to_vec())
+    }
+
+    fn setup_contract() -> (VMContextBuilder, Contract, PublicKey) {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let public_key = PublicKey::try_from([vec![0u8], keypair().public.to_bytes().to_vec()].concat()).unwrap();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.nft_add_claims(token_series.token_series_id, vec![public_key.clone()]);
+        (context, contract, public_key)
+    }
+
+    #[test]
+    fn test_claim() {
+        let (mut context, mut contract, public_key) = setup_contract();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = contract.nft_claim(public_key.clone(), sign(&accounts(2)));
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(contract.nft_claim_info(public_key), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Invalid claim signature")]
+    fn test_claim_with_signature_for_other_account() {
+        let (mut context, mut contract, public_key) = setup_contract();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.nft_claim(public_key, sign(&accounts(2)));
+    }
+}
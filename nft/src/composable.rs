@@ -0,0 +1,249 @@
+//! Composable tokens. An owner can attach one of their tokens to another token of this
+//! contract, e.g. equipment to a character. The contract holds attached tokens, which follow
+//! their parent: whoever owns the root of a composition tree controls every token in it and
+//! is the only one who can detach them, getting them back in their own account.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::events::NftTransfer;
+use crate::token::TokenId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+
+/// Most tokens attached directly to one token.
+pub const MAX_CHILDREN_PER_TOKEN: usize = 16;
+/// Most levels of a composition tree, the root included.
+pub const MAX_COMPOSITION_DEPTH: u32 = 4;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenCompositionJson {
+    pub token_id: TokenId,
+    pub children: Vec<TokenCompositionJson>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Attach one of the caller's tokens to `parent_token_id`, whose composition tree the
+    /// caller must own. The caller pays for the storage.
+    #[payable]
+    pub fn nft_attach(&mut self, child_token_id: TokenId, parent_token_id: TokenId) {
+        assert_at_least_one_yocto();
+        self.assert_transfers_not_paused();
+        let owner_id = env::predecessor_account_id();
+        let child_owner_id = self.tokens.core.owner_by_id.get(&child_token_id).expect("FireFly: Token not found");
+        assert_eq!(child_owner_id, owner_id, "FireFly: Token owner only");
+        assert_eq!(
+            self.nft_root_owner_of(parent_token_id.clone()),
+            Some(owner_id.clone()),
+            "FireFly: Parent token owner only"
+        );
+        assert!(
+            child_token_id != parent_token_id && !self.ancestors_of(&parent_token_id).contains(&child_token_id),
+            "FireFly: Token cannot be attached to itself or its descendants"
+        );
+        assert!(
+            self.ancestors_of(&parent_token_id).len() as u32 + 1 + self.composition_depth(&child_token_id)
+                <= MAX_COMPOSITION_DEPTH,
+            "FireFly: Compositions can be at most {} tokens deep",
+            MAX_COMPOSITION_DEPTH
+        );
+        let mut children = self.token_children.get(&parent_token_id).unwrap_or_default();
+        assert!(
+            children.len() < MAX_CHILDREN_PER_TOKEN,
+            "FireFly: At most {} tokens can be attached to a token",
+            MAX_CHILDREN_PER_TOKEN
+        );
+        self.assert_not_banned(&owner_id);
+        self.assert_not_soulbound(&child_token_id);
+        self.assert_not_locked(&child_token_id);
+        self.assert_not_locked(&parent_token_id);
+
+        let initial_storage_usage = env::storage_usage();
+        self.internal_clear_rental(&child_token_id);
+        self.internal_record_transfer(&child_token_id);
+        self.tokens.internal_remove_approvals(&child_token_id);
+        let contract_id = env::current_account_id();
+        self.tokens.internal_transfer_unguarded(&child_token_id, &owner_id, &contract_id);
+        children.push(child_token_id.clone());
+        self.token_children.insert(&parent_token_id, &children);
+        self.token_parents.insert(&child_token_id, &parent_token_id);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        NftTransfer {
+            old_owner_id: &owner_id,
+            new_owner_id: &contract_id,
+            token_ids: &[&child_token_id],
+            authorized_id: None,
+            memo: Some("attach"),
+        }
+        .emit();
+    }
+
+    /// Detach `child_token_id`, with everything attached to it, from its parent and give it
+    /// to the caller, who must own the composition tree. Refunds the storage released.
+    #[payable]
+    pub fn nft_detach(&mut self, child_token_id: TokenId) {
+        assert_one_yocto();
+        self.assert_transfers_not_paused();
+        let owner_id = env::predecessor_account_id();
+        let parent_token_id = self.token_parents.get(&child_token_id).expect("FireFly: Token is not attached");
+        assert_eq!(
+            self.nft_root_owner_of(parent_token_id.clone()),
+            Some(owner_id.clone()),
+            "FireFly: Parent token owner only"
+        );
+        self.assert_not_banned(&owner_id);
+        self.assert_not_locked(&parent_token_id);
+
+        let initial_storage_usage = env::storage_usage();
+        let mut children = self.token_children.get(&parent_token_id).unwrap_or_default();
+        children.retain(|token_id| token_id != &child_token_id);
+        if children.is_empty() {
+            self.token_children.remove(&parent_token_id);
+        } else {
+            self.token_children.insert(&parent_token_id, &children);
+        }
+        self.token_parents.remove(&child_token_id);
+        let contract_id = env::current_account_id();
+        self.tokens.internal_transfer_unguarded(&child_token_id, &contract_id, &owner_id);
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(owner_id.clone())
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+
+        NftTransfer {
+            old_owner_id: &contract_id,
+            new_owner_id: &owner_id,
+            token_ids: &[&child_token_id],
+            authorized_id: None,
+            memo: Some("detach"),
+        }
+        .emit();
+    }
+
+    pub fn nft_parent_of(&self, token_id: TokenId) -> Option<TokenId> {
+        self.token_parents.get(&token_id)
+    }
+
+    pub fn nft_children_of(&self, token_id: TokenId) -> Vec<TokenId> {
+        self.token_children.get(&token_id).unwrap_or_default()
+    }
+
+    /// Owner of the root of the composition tree of `token_id`, the token's own owner when
+    /// it isn't attached.
+    pub fn nft_root_owner_of(&self, token_id: TokenId) -> Option<AccountId> {
+        let root_token_id = self.ancestors_of(&token_id).pop().unwrap_or(token_id);
+        self.tokens.core.owner_by_id.get(&root_token_id)
+    }
+
+    /// `token_id` with everything attached to it, recursively.
+    pub fn nft_composition(&self, token_id: TokenId) -> TokenCompositionJson {
+        let children = self
+            .token_children
+            .get(&token_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|child_token_id| self.nft_composition(child_token_id))
+            .collect();
+        TokenCompositionJson { token_id, children }
+    }
+}
+
+impl Contract {
+    /// Panics when tokens are attached to `token_id`.
+    pub(crate) fn assert_no_children(&self, token_id: &TokenId) {
+        assert!(
+            self.token_children.get(token_id).is_none(),
+            "FireFly: Detach the tokens attached to this token first"
+        );
+    }
+
+    /// Parent, grandparent and so on of `token_id`, nearest first.
+    fn ancestors_of(&self, token_id: &TokenId) -> Vec<TokenId> {
+        let mut ancestors = vec![];
+        let mut current = token_id.clone();
+        while let Some(parent_token_id) = self.token_parents.get(&current) {
+            ancestors.push(parent_token_id.clone());
+            current = parent_token_id;
+        }
+        ancestors
+    }
+
+    /// Levels of the composition tree below and including `token_id`.
+    fn composition_depth(&self, token_id: &TokenId) -> u32 {
+        1 + self
+            .token_children
+            .get(token_id)
+            .unwrap_or_default()
+            .iter()
+            .map(|child_token_id| self.composition_depth(child_token_id))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn setup_composition(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+        contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_attach("1:2".to_string(), "1:1".to_string());
+        contract
+    }
+
+    #[test]
+    fn test_children_follow_parent() {
+        let mut context = get_context(accounts(0));
+        let mut contract = setup_composition(&mut context);
+        assert_eq!(contract.nft_children_of("1:1".to_string()), vec!["1:2".to_string()]);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.nft_transfer(accounts(3), "1:1".to_string(), None, None);
+        assert_eq!(contract.nft_root_owner_of("1:2".to_string()), Some(accounts(3)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.nft_detach("1:2".to_string());
+        assert_eq!(contract.nft_token("1:2".to_string()).unwrap().owner_id, accounts(3));
+        assert_eq!(
+            contract.nft_composition("1:1".to_string()),
+            TokenCompositionJson { token_id: "1:1".to_string(), children: vec![] }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token cannot be attached to itself or its descendants")]
+    fn test_attach_parent_to_child() {
+        let mut context = get_context(accounts(0));
+        let mut contract = setup_composition(&mut context);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_attach("1:1".to_string(), "1:2".to_string());
+    }
+}
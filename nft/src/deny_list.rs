@@ -0,0 +1,138 @@
+use crate::Contract;
+use crate::ContractExt;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+
+#[near_bindgen]
+impl Contract {
+    /// Refuse transfers to `account_id`, e.g. a known phishing contract. Owners and
+    /// moderators only, takes effect immediately so scams can be cut off without the
+    /// admin delay. The caller pays for the storage.
+    #[payable]
+    pub fn add_denied_receiver(&mut self, account_id: AccountId) {
+        assert_at_least_one_yocto();
+        self.assert_owner_or_moderator();
+        let initial_storage_usage = env::storage_usage();
+        if self.denied_receivers.insert(&account_id) {
+            env::log_str(
+                json!({
+                    "type": "add_denied_receiver",
+                    "params": {
+                        "account_id": account_id,
+                    }
+                }).to_string().as_str()
+            );
+        }
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    #[payable]
+    pub fn remove_denied_receiver(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner_or_moderator();
+        let initial_storage_usage = env::storage_usage();
+        if self.denied_receivers.remove(&account_id) {
+            env::log_str(
+                json!({
+                    "type": "remove_denied_receiver",
+                    "params": {
+                        "account_id": account_id,
+                    }
+                }).to_string().as_str()
+            );
+        }
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(env::predecessor_account_id())
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+    }
+
+    pub fn is_receiver_denied(&self, account_id: AccountId) -> bool {
+        self.denied_receivers.contains(&account_id)
+    }
+
+    pub fn get_denied_receivers(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<AccountId> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        self.denied_receivers
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_receiver_allowed(&self, receiver_id: &AccountId) {
+        assert!(
+            !self.denied_receivers.contains(receiver_id),
+            "FireFly: Receiver {} is on the deny-list",
+            receiver_id
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "FireFly: Receiver charlie is on the deny-list")]
+    fn test_transfer_to_denied_receiver() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+        contract.add_denied_receiver(accounts(2));
+        assert!(contract.is_receiver_denied(accounts(2)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_transfer(accounts(2), token.token_id, None, None);
+    }
+
+    #[test]
+    fn test_remove_denied_receiver() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.add_denied_receiver(accounts(2));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.remove_denied_receiver(accounts(2));
+        assert!(!contract.is_receiver_denied(accounts(2)));
+        assert!(contract.get_denied_receivers(None, None).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Owner or moderator only")]
+    fn test_add_denied_receiver_not_moderator() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(MINT_STORAGE_COST).build());
+        contract.add_denied_receiver(accounts(2));
+    }
+}
@@ -0,0 +1,328 @@
+//! Drop campaigns: multi-phase launches of a series. A creator lists phases by start time,
+//! each with its own price, optional allowlist and mint limits; a phase runs until the next
+//! one starts and the last one until the series sells out. While a series has a drop it is
+//! bought with `nft_mint_drop` only. Allowlists use the Merkle trees of `allowlist`.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::allowlist::verify_proof;
+use crate::series::{assert_valid_price, TokenSeriesId};
+use crate::token::Token;
+use crate::utils::refund_deposit;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+
+/// Most phases a drop can have.
+pub const MAX_DROP_PHASES: usize = 8;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DropPhase {
+    pub name: String,
+    pub starts_at: U64,
+    pub price: U128,
+    /// Merkle root of the accounts that may buy in this phase, `None` for a public phase
+    pub allowlist_root: Option<Base64VecU8>,
+    /// Most editions one receiver can get in this phase
+    pub max_per_wallet: Option<u64>,
+    /// Most editions sold in this phase
+    pub max_mints: Option<u64>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
+pub struct DropCampaign {
+    pub phases: Vec<DropPhase>,
+    /// Editions sold in each phase
+    pub minted: Vec<u64>,
+}
+
+impl DropCampaign {
+    /// Index of the phase running now, `None` before the first one starts.
+    pub fn current_phase(&self) -> Option<usize> {
+        let now = env::block_timestamp();
+        self.phases.iter().rposition(|phase| phase.starts_at.0 <= now)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DropStateJson {
+    pub token_series_id: TokenSeriesId,
+    pub phases: Vec<DropPhase>,
+    pub minted: Vec<u64>,
+    pub current_phase: Option<u32>,
+    pub next_phase_starts_at: Option<U64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set, or with `None` remove, the drop of a series. Phases must be in start order and
+    /// can't be changed once the first one has started, only removed. Creator only.
+    #[payable]
+    pub fn nft_set_series_drop(&mut self, token_series_id: TokenSeriesId, phases: Option<Vec<DropPhase>>) {
+        let initial_storage_usage = env::storage_usage();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+
+        match &phases {
+            Some(phases) => {
+                if let Some(drop) = self.drops.get(&token_series_id) {
+                    assert!(drop.current_phase().is_none(), "FireFly: Drop has started");
+                }
+                assert!(
+                    !phases.is_empty() && phases.len() <= MAX_DROP_PHASES,
+                    "FireFly: Drop must have between 1 and {} phases",
+                    MAX_DROP_PHASES
+                );
+                for (index, phase) in phases.iter().enumerate() {
+                    if index > 0 {
+                        assert!(
+                            phase.starts_at.0 > phases[index - 1].starts_at.0,
+                            "FireFly: Phases must be in start order"
+                        );
+                    }
+                    if let Some(root) = &phase.allowlist_root {
+                        assert_eq!(root.0.len(), 32, "FireFly: Allowlist root must be 32 bytes");
+                    }
+                    assert_valid_price(Some(phase.price), self.max_price);
+                }
+                let drop = DropCampaign { phases: phases.clone(), minted: vec![0; phases.len()] };
+                self.drops.insert(&token_series_id, &drop);
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+            }
+            None => {
+                self.drops.remove(&token_series_id);
+                let released = initial_storage_usage.saturating_sub(env::storage_usage());
+                let refund = Balance::from(released) * env::storage_byte_cost() + env::attached_deposit();
+                if refund > 0 {
+                    Promise::new(token_series.creator_id).transfer(refund);
+                }
+            }
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_set_series_drop",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "phases": phases,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    pub fn get_drop_state(&self, token_series_id: TokenSeriesId) -> Option<DropStateJson> {
+        self.drops.get(&token_series_id).map(|drop| {
+            let current_phase = drop.current_phase();
+            let next_phase = current_phase.map(|index| index + 1).unwrap_or(0);
+            DropStateJson {
+                token_series_id,
+                next_phase_starts_at: drop.phases.get(next_phase).map(|phase| phase.starts_at),
+                current_phase: current_phase.map(|index| index as u32),
+                phases: drop.phases,
+                minted: drop.minted,
+            }
+        })
+    }
+
+    /// Buy the next edition of a series in the running phase of its drop, for `receiver_id`.
+    /// `proof` shows the caller is on the allowlist of the phase, if it has one. The attached
+    /// deposit must cover the phase price plus storage, like `nft_buy`.
+    #[payable]
+    pub fn nft_mint_drop(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        receiver_id: AccountId,
+        max_price: U128,
+        proof: Option<Vec<Base64VecU8>>,
+    ) -> Token {
+        let initial_storage_usage = env::storage_usage();
+        let buyer_id = env::predecessor_account_id();
+        self.assert_minting_not_paused();
+        self.assert_not_banned(&buyer_id);
+        self.assert_not_banned(&receiver_id);
+        self.assert_open_edition_window(&token_series_id);
+        self.assert_not_auctioned(&token_series_id);
+
+        let mut drop = self.drops.get(&token_series_id).expect("FireFly: Token series has no drop");
+        let phase_index = drop.current_phase().expect("FireFly: Drop has not started");
+        let phase = &drop.phases[phase_index];
+        if let Some(root) = &phase.allowlist_root {
+            assert!(
+                verify_proof(&root.0, &buyer_id, &proof.unwrap_or_default()),
+                "FireFly: Not on the allowlist of phase {}",
+                phase.name
+            );
+        }
+        let price = phase.price.0;
+        assert!(
+            price <= max_price.0,
+            "FireFly: price {} is higher than max_price {}",
+            price,
+            max_price.0
+        );
+        if let Some(max_mints) = phase.max_mints {
+            assert!(drop.minted[phase_index] < max_mints, "FireFly: Phase {} is sold out", phase.name);
+        }
+        if let Some(max_per_wallet) = phase.max_per_wallet {
+            let key = (token_series_id.clone(), phase_index as u32, receiver_id.clone());
+            let minted = self.drop_mints.get(&key).unwrap_or(0);
+            assert!(
+                minted < max_per_wallet,
+                "FireFly: {} already has the maximum of {} editions in phase {}",
+                receiver_id,
+                max_per_wallet,
+                phase.name
+            );
+            self.drop_mints.insert(&key, &(minted + 1));
+        }
+        drop.minted[phase_index] += 1;
+        self.drops.insert(&token_series_id, &drop);
+
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        self.internal_record_wallet_mint(&token_series_id, &token_series, &receiver_id);
+        let for_treasury = self.internal_transaction_fee(&token_series_id, price);
+        let token = self.tokens.internal_mint(&mut self.token_series_by_id, token_series_id.clone(), receiver_id.clone(), None);
+        self.internal_add_purchase_receipt(
+            &buyer_id,
+            token_series_id.clone(),
+            token.token_id.clone(),
+            receiver_id.clone(),
+            price,
+        );
+
+        self.internal_charge_storage(price, env::storage_usage() - initial_storage_usage);
+        if price > 0 {
+            let proceeds = self.internal_take_fees(price, for_treasury);
+            self.internal_record_series_sale(&token_series_id, price, proceeds);
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_mint_drop",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "token_id": token.token_id,
+                    "receiver_id": receiver_id,
+                    "phase": drop.phases[phase_index].name,
+                    "price": U128(price),
+                }
+            }).to_string().as_str()
+        );
+        token
+    }
+}
+
+impl Contract {
+    /// Panics when `token_series_id` is sold through a drop.
+    pub(crate) fn assert_no_drop(&self, token_series_id: &TokenSeriesId) {
+        assert!(
+            self.drops.get(token_series_id).is_none(),
+            "FireFly: Token series is sold in a drop, use nft_mint_drop"
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn setup_drop(context: &mut VMContextBuilder) -> (Contract, TokenSeriesId) {
+        testing_env!(context.block_timestamp(10).build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(1)), None);
+        let phases = vec![
+            DropPhase {
+                name: "allowlist".to_string(),
+                starts_at: U64(50),
+                price: U128(100),
+                // a tree with the single leaf `accounts(2)`
+                allowlist_root: Some(Base64VecU8(env::sha256(accounts(2).as_bytes()))),
+                max_per_wallet: None,
+                max_mints: Some(1),
+            },
+            DropPhase {
+                name: "public".to_string(),
+                starts_at: U64(100),
+                price: U128(200),
+                allowlist_root: None,
+                max_per_wallet: Some(1),
+                max_mints: None,
+            },
+        ];
+        contract.nft_set_series_drop(token_series.token_series_id.clone(), Some(phases));
+        (contract, token_series.token_series_id)
+    }
+
+    #[test]
+    fn test_drop_phases() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_series_id) = setup_drop(&mut context);
+        let state = contract.get_drop_state(token_series_id.clone()).unwrap();
+        assert_eq!((state.current_phase, state.next_phase_starts_at), (None, Some(U64(50))));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(50)
+            .build());
+        contract.nft_mint_drop(token_series_id.clone(), accounts(2), U128(100), Some(vec![]));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(100)
+            .build());
+        let token = contract.nft_mint_drop(token_series_id.clone(), accounts(3), U128(200), None);
+        assert_eq!(token.owner_id, accounts(3));
+        let state = contract.get_drop_state(token_series_id).unwrap();
+        assert_eq!((state.current_phase, state.next_phase_starts_at), (Some(1), None));
+        assert_eq!(state.minted, vec![1, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Not on the allowlist of phase allowlist")]
+    fn test_drop_allowlist_phase() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_series_id) = setup_drop(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(50)
+            .build());
+        contract.nft_mint_drop(token_series_id, accounts(3), U128(100), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token series is sold in a drop, use nft_mint_drop")]
+    fn test_buy_drop_series() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_series_id) = setup_drop(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.nft_buy(token_series_id, accounts(3), U128(1), None);
+    }
+}
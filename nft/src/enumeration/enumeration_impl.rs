@@ -1,4 +1,5 @@
 use super::NonFungibleTokenEnumeration;
+use crate::approval::Approvals;
 use crate::token::Token;
 use crate::NonFungibleToken;
 use near_sdk::json_types::U128;
@@ -10,20 +11,43 @@ impl NonFungibleToken {
     /// Helper function used by a enumerations methods
     /// Note: this method is not exposed publicly to end users
     fn enum_get_token(&self, owner_id: AccountId, token_id: TokenId) -> Token {
-        let metadata = self.token_metadata_by_id.get(&token_id).unwrap();
+        let metadata = self.token_metadata(&token_id).unwrap();
         let approved_account_ids = self
             .approvals_by_id
             .as_ref()
-            .map(|approvals_by_id| approvals_by_id.get(&token_id.to_string()).unwrap_or_default());
+            .map(|approvals_by_id| {
+                approvals_by_id.get(&token_id).map(Approvals::into_map).unwrap_or_default()
+            });
 
         Token { token_id, owner_id, metadata, approved_account_ids }
     }
+
+    /// Whether the owner hid `token_id` from their enumeration views
+    pub fn is_token_hidden(&self, token_id: &TokenId) -> bool {
+        self.hidden_token_ids.as_ref().map(|hidden| hidden.contains(token_id)).unwrap_or(false)
+    }
+
+    /// Hide or unhide a token from `nft_tokens_for_owner`. The caller must be the token owner.
+    pub fn internal_set_token_hidden(&mut self, token_id: &TokenId, hidden: bool) {
+        let owner_id =
+            self.core.owner_by_id.get(token_id).unwrap_or_else(|| env::panic_str("Token not found"));
+        require!(env::predecessor_account_id() == owner_id, "Predecessor must be token owner.");
+
+        let hidden_token_ids = self.hidden_token_ids.as_mut().unwrap_or_else(|| {
+            env::panic_str("NFT does not support Enumeration")
+        });
+        if hidden {
+            hidden_token_ids.insert(token_id);
+        } else {
+            hidden_token_ids.remove(token_id);
+        }
+    }
 }
 
 impl NonFungibleTokenEnumeration for NonFungibleToken {
     fn nft_total_supply(&self) -> U128 {
         // An unfortunate cast from the max of TreeMap to the spec
-        (self.owner_by_id.len() as u128).into()
+        (self.core.owner_by_id.len() as u128).into()
     }
 
     fn nft_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token> {
@@ -32,12 +56,12 @@ impl NonFungibleTokenEnumeration for NonFungibleToken {
         // https://nomicon.io/Standards/NonFungibleToken/Enumeration.html#interface
         let start_index: u128 = from_index.map(From::from).unwrap_or_default();
         require!(
-            (self.owner_by_id.len() as u128) >= start_index,
+            (self.core.owner_by_id.len() as u128) >= start_index,
             "Out of bounds, please use a smaller from_index."
         );
         let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
         require!(limit != 0, "Cannot provide limit of 0.");
-        self.owner_by_id
+        self.core.owner_by_id
             .iter()
             .skip(start_index as usize)
             .take(limit)
@@ -46,16 +70,13 @@ impl NonFungibleTokenEnumeration for NonFungibleToken {
     }
 
     fn nft_supply_for_owner(&self, account_id: AccountId) -> U128 {
-        let tokens_per_owner = self.tokens_per_owner.as_ref().unwrap_or_else(|| {
+        let token_count_per_owner = self.token_count_per_owner.as_ref().unwrap_or_else(|| {
             env::panic_str(
-                "Could not find tokens_per_owner when calling a method on the \
+                "Could not find token_count_per_owner when calling a method on the \
                 enumeration standard.",
             )
         });
-        tokens_per_owner
-            .get(&account_id)
-            .map(|account_tokens| U128::from(account_tokens.len() as u128))
-            .unwrap_or(U128(0))
+        U128::from(token_count_per_owner.get(&account_id).unwrap_or(0) as u128)
     }
 
     fn nft_tokens_for_owner(
@@ -64,7 +85,7 @@ impl NonFungibleTokenEnumeration for NonFungibleToken {
         from_index: Option<U128>,
         limit: Option<u64>,
     ) -> Vec<Token> {
-        let tokens_per_owner = self.tokens_per_owner.as_ref().unwrap_or_else(|| {
+        let tokens_per_owner = self.core.tokens_per_owner.as_ref().unwrap_or_else(|| {
             env::panic_str(
                 "Could not find tokens_per_owner when calling a method on the \
                 enumeration standard.",
@@ -89,6 +110,7 @@ impl NonFungibleTokenEnumeration for NonFungibleToken {
         );
         token_set
             .iter()
+            .filter(|token_id| !self.is_token_hidden(token_id))
             .skip(start_index as usize)
             .take(limit)
             .map(|token_id| self.enum_get_token(account_id.clone(), token_id))
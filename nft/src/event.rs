@@ -7,6 +7,9 @@ use serde::Serialize;
 #[serde(rename_all = "snake_case")]
 pub(crate) enum NearEvent<'a> {
     Nep171(crate::events::Nep171Event<'a>),
+    Nep245(crate::mt_events::Nep245Event<'a>),
+    FireflySeries(crate::series_events::SeriesEvent<'a>),
+    FireflyApproval(crate::approval_events::ApprovalEvent<'a>),
     // Nep141(crate::events::Nep141Event<'a>),
 }
 
@@ -8,6 +8,7 @@
 //! <https://github.com/near/NEPs/blob/master/specs/Standards/EventsFormat.md>
 //!
 //! The three events in this standard are [`NftMint`], [`NftTransfer`], and [`NftBurn`].
+//! Approval changes are logged under the contract's own standard, see `approval_events`.
 //!
 //! These events can be logged by calling `.emit()` on them if a single event, or calling
 //! [`NftMint::emit_many`], [`NftTransfer::emit_many`],
@@ -95,6 +96,38 @@ impl NftBurn<'_> {
     }
 }
 
+/// Data to log when the metadata of minted tokens changes, so indexers refresh them, an event
+/// from version 1.1.0 of the standard. To log this event, call
+/// [`.emit()`](NftMetadataUpdate::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct NftMetadataUpdate<'a> {
+    pub token_ids: &'a [&'a str],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl NftMetadataUpdate<'_> {
+    pub fn emit(self) {
+        new_171("1.1.0", Nep171EventKind::NftMetadataUpdate(&[self])).emit()
+    }
+}
+
+/// Data to log when the contract metadata changes, an event from version 1.1.0 of the
+/// standard. To log this event, call [`.emit()`](ContractMetadataUpdate::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct ContractMetadataUpdate<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl ContractMetadataUpdate<'_> {
+    pub fn emit(self) {
+        new_171("1.1.0", Nep171EventKind::ContractMetadataUpdate(&[self])).emit()
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub(crate) struct Nep171Event<'a> {
     version: &'static str,
@@ -110,6 +143,8 @@ enum Nep171EventKind<'a> {
     NftMint(&'a [NftMint<'a>]),
     NftTransfer(&'a [NftTransfer<'a>]),
     NftBurn(&'a [NftBurn<'a>]),
+    NftMetadataUpdate(&'a [NftMetadataUpdate<'a>]),
+    ContractMetadataUpdate(&'a [ContractMetadataUpdate<'a>]),
 }
 
 fn new_171<'a>(version: &'static str, event_kind: Nep171EventKind<'a>) -> NearEvent<'a> {
@@ -222,4 +257,22 @@ mod tests {
             r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"alice","new_owner_id":"bob","token_ids":["2","3"],"authorized_id":"bob","memo":"has memo"},{"old_owner_id":"bob","new_owner_id":"alice","token_ids":["0","1"]}]}"#
         );
     }
+
+    #[test]
+    fn nft_metadata_update() {
+        NftMetadataUpdate { token_ids: &["0", "1"], memo: Some("reveal") }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.1.0","event":"nft_metadata_update","data":[{"token_ids":["0","1"],"memo":"reveal"}]}"#
+        );
+    }
+
+    #[test]
+    fn contract_metadata_update() {
+        ContractMetadataUpdate { memo: None }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.1.0","event":"contract_metadata_update","data":[{}]}"#
+        );
+    }
 }
@@ -0,0 +1,322 @@
+//! Fractionalization. An owner locks a token in the contract and gets a fixed number of
+//! shares of it, which can be transferred like a fungible balance of that token. Whoever
+//! holds every share can redeem the token. If the owner set a buyout price, anyone can buy
+//! the token for it, paying only for the shares they don't hold; the other holders then claim
+//! their part of the price with `claim_buyout_proceeds`.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::events::NftTransfer;
+use crate::token::TokenId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+
+/// Most shares a token can be split into, keeping `share_of` from overflowing.
+pub const MAX_SHARES: Balance = 1_000_000_000_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Fraction {
+    pub fractionalized_by: AccountId,
+    pub total_shares: U128,
+    /// Price at which anyone may buy the whole token, `None` if only redeeming is possible
+    pub buyout_price: Option<U128>,
+    pub bought_out_by: Option<AccountId>,
+    /// Shares still held, after a buyout the shares not yet claimed
+    pub outstanding_shares: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Lock one of the caller's tokens in the contract and give the caller `total_shares`
+    /// shares of it. The caller pays for the storage.
+    #[payable]
+    pub fn nft_fractionalize(&mut self, token_id: TokenId, total_shares: U128, buyout_price: Option<U128>) {
+        assert_at_least_one_yocto();
+        self.assert_transfers_not_paused();
+        let owner_id = env::predecessor_account_id();
+        let token_owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        assert_eq!(token_owner_id, owner_id, "FireFly: Token owner only");
+        assert!(
+            total_shares.0 > 0 && total_shares.0 <= MAX_SHARES,
+            "FireFly: total_shares must be between 1 and {}",
+            MAX_SHARES
+        );
+        if let Some(buyout_price) = buyout_price {
+            assert!(buyout_price.0 < self.max_price, "FireFly: price higher than {}", self.max_price);
+        }
+        self.assert_not_banned(&owner_id);
+        self.assert_not_soulbound(&token_id);
+        self.assert_not_locked(&token_id);
+
+        let initial_storage_usage = env::storage_usage();
+        self.internal_clear_rental(&token_id);
+        self.internal_record_transfer(&token_id);
+        self.tokens.internal_remove_approvals(&token_id);
+        let contract_id = env::current_account_id();
+        self.tokens.internal_transfer_unguarded(&token_id, &owner_id, &contract_id);
+        let fraction = Fraction {
+            fractionalized_by: owner_id.clone(),
+            total_shares,
+            buyout_price,
+            bought_out_by: None,
+            outstanding_shares: total_shares,
+        };
+        self.fractions.insert(&token_id, &fraction);
+        self.share_balances.insert(&(token_id.clone(), owner_id.clone()), &total_shares.0);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        NftTransfer {
+            old_owner_id: &owner_id,
+            new_owner_id: &contract_id,
+            token_ids: &[&token_id],
+            authorized_id: None,
+            memo: Some("fractionalize"),
+        }
+        .emit();
+    }
+
+    /// Transfer `amount` shares of `token_id` to `receiver_id`. The caller pays for the
+    /// storage of a new holder.
+    #[payable]
+    pub fn shares_transfer(&mut self, token_id: TokenId, receiver_id: AccountId, amount: U128) {
+        assert_at_least_one_yocto();
+        self.assert_transfers_not_paused();
+        let sender_id = env::predecessor_account_id();
+        let fraction = self.fractions.get(&token_id).expect("FireFly: Token is not fractionalized");
+        assert!(fraction.bought_out_by.is_none(), "FireFly: Token was bought out");
+        assert_ne!(sender_id, receiver_id, "FireFly: Sender and receiver must differ");
+        assert!(amount.0 > 0, "FireFly: amount must be positive");
+        self.assert_not_banned(&sender_id);
+        self.assert_not_banned(&receiver_id);
+
+        let initial_storage_usage = env::storage_usage();
+        let sender_balance = self.internal_share_balance(&token_id, &sender_id);
+        assert!(sender_balance >= amount.0, "FireFly: Not enough shares");
+        self.internal_set_share_balance(&token_id, &sender_id, sender_balance - amount.0);
+        let receiver_balance = self.internal_share_balance(&token_id, &receiver_id);
+        self.internal_set_share_balance(&token_id, &receiver_id, receiver_balance + amount.0);
+
+        env::log_str(
+            json!({
+                "type": "shares_transfer",
+                "params": {
+                    "token_id": token_id,
+                    "sender_id": sender_id,
+                    "receiver_id": receiver_id,
+                    "amount": amount,
+                }
+            }).to_string().as_str()
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Give `token_id` back to the caller, who must hold every share of it.
+    #[payable]
+    pub fn nft_redeem_shares(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let fraction = self.fractions.get(&token_id).expect("FireFly: Token is not fractionalized");
+        assert!(fraction.bought_out_by.is_none(), "FireFly: Token was bought out");
+        assert_eq!(
+            self.internal_share_balance(&token_id, &account_id),
+            fraction.total_shares.0,
+            "FireFly: Every share is needed to redeem"
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        self.fractions.remove(&token_id);
+        self.share_balances.remove(&(token_id.clone(), account_id.clone()));
+        self.internal_release_fractionalized(&token_id, &account_id, "redeem");
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(account_id)
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+    }
+
+    /// Buy the whole of `token_id` at its buyout price. Shares the caller holds count toward
+    /// the price; the attached deposit must cover the rest plus storage.
+    #[payable]
+    pub fn nft_buyout(&mut self, token_id: TokenId) {
+        let initial_storage_usage = env::storage_usage();
+        let buyer_id = env::predecessor_account_id();
+        self.assert_transfers_not_paused();
+        self.assert_not_banned(&buyer_id);
+        let mut fraction = self.fractions.get(&token_id).expect("FireFly: Token is not fractionalized");
+        assert!(fraction.bought_out_by.is_none(), "FireFly: Token was bought out");
+        let buyout_price = fraction.buyout_price.expect("FireFly: Token has no buyout price").0;
+
+        let buyer_shares = self.internal_share_balance(&token_id, &buyer_id);
+        let total_shares = fraction.total_shares.0;
+        let price = share_of(buyout_price, total_shares - buyer_shares, total_shares);
+        self.share_balances.remove(&(token_id.clone(), buyer_id.clone()));
+        fraction.outstanding_shares = U128(fraction.outstanding_shares.0 - buyer_shares);
+        fraction.bought_out_by = Some(buyer_id.clone());
+        if fraction.outstanding_shares.0 == 0 {
+            self.fractions.remove(&token_id);
+        } else {
+            self.fractions.insert(&token_id, &fraction);
+        }
+        self.internal_release_fractionalized(&token_id, &buyer_id, "buyout");
+        self.internal_charge_storage(price, env::storage_usage().saturating_sub(initial_storage_usage));
+
+        env::log_str(
+            json!({
+                "type": "nft_buyout",
+                "params": {
+                    "token_id": token_id,
+                    "buyer_id": buyer_id,
+                    "price": U128(price),
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Pay the caller their part of the buyout price of `token_id` for the shares they hold.
+    #[payable]
+    pub fn claim_buyout_proceeds(&mut self, token_id: TokenId) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut fraction = self.fractions.get(&token_id).expect("FireFly: Token is not fractionalized");
+        assert!(fraction.bought_out_by.is_some(), "FireFly: Token was not bought out");
+        let shares = self.internal_share_balance(&token_id, &account_id);
+        assert!(shares > 0, "FireFly: No shares");
+
+        let initial_storage_usage = env::storage_usage();
+        // a bought out fraction always has a buyout price
+        let amount = share_of(fraction.buyout_price.unwrap().0, shares, fraction.total_shares.0);
+        self.share_balances.remove(&(token_id.clone(), account_id.clone()));
+        fraction.outstanding_shares = U128(fraction.outstanding_shares.0 - shares);
+        if fraction.outstanding_shares.0 == 0 {
+            self.fractions.remove(&token_id);
+        } else {
+            self.fractions.insert(&token_id, &fraction);
+        }
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        self.internal_payout(account_id.clone(), amount + Balance::from(storage_released) * env::storage_byte_cost());
+
+        env::log_str(
+            json!({
+                "type": "claim_buyout_proceeds",
+                "params": {
+                    "token_id": token_id,
+                    "account_id": account_id,
+                    "shares": U128(shares),
+                    "amount": U128(amount),
+                }
+            }).to_string().as_str()
+        );
+        U128(amount)
+    }
+
+    pub fn nft_fraction(&self, token_id: TokenId) -> Option<Fraction> {
+        self.fractions.get(&token_id)
+    }
+
+    pub fn shares_balance_of(&self, token_id: TokenId, account_id: AccountId) -> U128 {
+        U128(self.internal_share_balance(&token_id, &account_id))
+    }
+}
+
+impl Contract {
+    fn internal_share_balance(&self, token_id: &TokenId, account_id: &AccountId) -> Balance {
+        self.share_balances.get(&(token_id.clone(), account_id.clone())).unwrap_or(0)
+    }
+
+    fn internal_set_share_balance(&mut self, token_id: &TokenId, account_id: &AccountId, balance: Balance) {
+        let key = (token_id.clone(), account_id.clone());
+        if balance == 0 {
+            self.share_balances.remove(&key);
+        } else {
+            self.share_balances.insert(&key, &balance);
+        }
+    }
+
+    /// Move a fractionalized token out of the contract to `receiver_id`.
+    fn internal_release_fractionalized(&mut self, token_id: &TokenId, receiver_id: &AccountId, memo: &str) {
+        let contract_id = env::current_account_id();
+        self.tokens.internal_transfer_unguarded(token_id, &contract_id, receiver_id);
+        NftTransfer {
+            old_owner_id: &contract_id,
+            new_owner_id: receiver_id,
+            token_ids: &[token_id],
+            authorized_id: None,
+            memo: Some(memo),
+        }
+        .emit();
+    }
+}
+
+/// `amount * shares / total_shares` rounded down, without overflowing for any
+/// `total_shares` up to `MAX_SHARES`.
+fn share_of(amount: Balance, shares: Balance, total_shares: Balance) -> Balance {
+    amount / total_shares * shares + amount % total_shares * shares / total_shares
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn setup_fractionalized_token(context: &mut VMContextBuilder) -> (Contract, TokenId) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_fractionalize(token.token_id.clone(), U128(100), Some(U128(1000)));
+        contract.shares_transfer(token.token_id.clone(), accounts(2), U128(40));
+        assert_eq!(contract.shares_balance_of(token.token_id.clone(), accounts(1)), U128(60));
+        (contract, token.token_id)
+    }
+
+    #[test]
+    fn test_buyout() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_fractionalized_token(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_buyout(token_id.clone());
+        assert_eq!(contract.nft_token(token_id.clone()).unwrap().owner_id, accounts(2));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        assert_eq!(contract.claim_buyout_proceeds(token_id.clone()), U128(600));
+        assert_eq!(contract.nft_fraction(token_id), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Every share is needed to redeem")]
+    fn test_redeem_without_every_share() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_fractionalized_token(&mut context);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_redeem_shares(token_id);
+    }
+}
@@ -0,0 +1,326 @@
+//! Minting against fungible tokens. The owner whitelists FT contracts, a creator prices a
+//! series in one of them, and buyers pay with `ft_transfer_call` on the FT contract using a
+//! msg like `{"token_series_id": "1", "receiver_id": "bob.near"}`. `ft_on_transfer` mints the
+//! edition, forwards the price to the creator and hands the rest back to the FT contract to be
+//! refunded. Any failure panics, so the FT contract refunds the whole amount. The minted token's
+//! storage is paid from the buyer's storage balance, see `storage_deposit`.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::TokenSeriesId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, near_bindgen, AccountId, Balance, Gas, Promise,
+    PromiseOrValue, PromiseResult,
+};
+use serde_json::json;
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_RESOLVE_FT_PROCEEDS: Gas = Gas(5_000_000_000_000);
+
+#[ext_contract(ext_ft)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtPrice {
+    pub ft_contract_id: AccountId,
+    pub price: U128,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtPurchaseMsg {
+    token_series_id: TokenSeriesId,
+    /// Defaults to the sender of the FT
+    receiver_id: Option<AccountId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Accept `ft_contract_id` as a payment token for series. Owner only, the caller pays for
+    /// the storage. Owner sets requiring several confirmations use `AdminAction::AddPaymentFt`.
+    #[payable]
+    pub fn add_payment_ft(&mut self, ft_contract_id: AccountId) {
+        assert_at_least_one_yocto();
+        self.assert_owner();
+        let initial_storage_usage = env::storage_usage();
+        self.internal_add_payment_ft(&ft_contract_id);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Stop accepting `ft_contract_id`. Series priced in it can't be bought until their
+    /// creator prices them again. Owner only, or `AdminAction::RemovePaymentFt` for owner
+    /// sets requiring several confirmations.
+    #[payable]
+    pub fn remove_payment_ft(&mut self, ft_contract_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_remove_payment_ft(&ft_contract_id);
+    }
+
+    pub fn get_payment_fts(&self) -> Vec<AccountId> {
+        self.payment_fts.to_vec()
+    }
+
+    /// Price a series in a whitelisted FT, or with `None` stop selling it for FTs. This is
+    /// independent of the NEAR price. Creator only, the caller pays for the storage.
+    #[payable]
+    pub fn nft_set_series_ft_price(&mut self, token_series_id: TokenSeriesId, ft_price: Option<FtPrice>) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+
+        match &ft_price {
+            Some(ft_price) => {
+                assert!(token_series.is_mintable, "FireFly: Token series is not mintable");
+                assert!(
+                    self.payment_fts.contains(&ft_price.ft_contract_id),
+                    "FireFly: {} is not a payment token",
+                    ft_price.ft_contract_id
+                );
+                self.ft_prices.insert(&token_series_id, ft_price);
+            }
+            None => {
+                self.ft_prices.remove(&token_series_id);
+            }
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_set_series_ft_price",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "ft_price": ft_price,
+                }
+            }).to_string().as_str()
+        );
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(token_series.creator_id)
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+    }
+
+    pub fn nft_series_ft_price(&self, token_series_id: TokenSeriesId) -> Option<FtPrice> {
+        self.ft_prices.get(&token_series_id)
+    }
+
+    /// NEP-141 receiver, called by the FT contract on `ft_transfer_call`. Buys one edition of
+    /// the series named in `msg` and returns the part of `amount` that wasn't needed.
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        let ft_contract_id = env::predecessor_account_id();
+        assert!(
+            self.payment_fts.contains(&ft_contract_id),
+            "FireFly: {} is not a payment token",
+            ft_contract_id
+        );
+        let FtPurchaseMsg { token_series_id, receiver_id } =
+            serde_json::from_str(&msg).unwrap_or_else(|_| env::panic_str("FireFly: Invalid msg"));
+        let receiver_id = receiver_id.unwrap_or_else(|| sender_id.clone());
+
+        let ft_price = self.ft_prices.get(&token_series_id).expect("FireFly: Token series is not for sale in FT");
+        assert_eq!(ft_price.ft_contract_id, ft_contract_id, "FireFly: Token series is priced in another FT");
+        let price = ft_price.price.0;
+        assert!(amount.0 >= price, "FireFly: amount is less than price {}", price);
+
+        self.assert_minting_not_paused();
+        self.assert_not_banned(&sender_id);
+        self.assert_not_banned(&receiver_id);
+        self.assert_allowlist_over(&token_series_id);
+        self.assert_no_drop(&token_series_id);
+        self.assert_open_edition_window(&token_series_id);
+        self.assert_not_auctioned(&token_series_id);
+
+        let initial_storage_usage = env::storage_usage();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        self.internal_record_wallet_mint(&token_series_id, &token_series, &receiver_id);
+        let token = self.tokens.internal_mint(&mut self.token_series_by_id, token_series_id.clone(), receiver_id, None);
+        if self.token_series_by_id.get(&token_series_id).map(|series| series.is_mintable) == Some(false) {
+            self.ft_prices.remove(&token_series_id);
+        }
+        self.internal_use_storage_balance(&sender_id, env::storage_usage().saturating_sub(initial_storage_usage));
+
+        if price > 0 {
+            self.internal_ft_payout(ft_contract_id.clone(), token_series.creator_id, price);
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_buy_with_ft",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "token_id": token.token_id,
+                    "owner_id": token.owner_id,
+                    "buyer_id": sender_id,
+                    "ft_contract_id": ft_contract_id,
+                    "price": ft_price.price,
+                }
+            }).to_string().as_str()
+        );
+        PromiseOrValue::Value(U128(amount.0 - price))
+    }
+
+    /// Callback of every FT payout. A failed transfer (e.g. the creator isn't registered with
+    /// the FT contract) is kept for the creator to claim with `claim_ft_proceeds`.
+    #[private]
+    pub fn ft_resolve_proceeds(&mut self, ft_contract_id: AccountId, account_id: AccountId, amount: U128) {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+        let key = (ft_contract_id, account_id);
+        let pending = self.pending_ft_proceeds.get(&key).unwrap_or(0);
+        self.pending_ft_proceeds.insert(&key, &(pending + amount.0));
+
+        env::log_str(
+            json!({
+                "type": "ft_payout_failed",
+                "params": {
+                    "ft_contract_id": key.0,
+                    "account_id": key.1,
+                    "amount": amount,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Retry every failed FT payout of `ft_contract_id` to the caller.
+    #[payable]
+    pub fn claim_ft_proceeds(&mut self, ft_contract_id: AccountId) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .pending_ft_proceeds
+            .remove(&(ft_contract_id.clone(), account_id.clone()))
+            .unwrap_or(0);
+        assert!(amount > 0, "FireFly: No pending proceeds");
+        self.internal_ft_payout(ft_contract_id, account_id, amount);
+        amount.into()
+    }
+
+    pub fn get_pending_ft_proceeds(&self, ft_contract_id: AccountId, account_id: AccountId) -> U128 {
+        self.pending_ft_proceeds.get(&(ft_contract_id, account_id)).unwrap_or(0).into()
+    }
+}
+
+impl Contract {
+    /// Transfer `amount` of `ft_contract_id` to `account_id`, keeping it claimable if the
+    /// transfer fails.
+    pub(crate) fn internal_ft_payout(&self, ft_contract_id: AccountId, account_id: AccountId, amount: Balance) -> Promise {
+        ext_ft::ext(ft_contract_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(account_id.clone(), amount.into(), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_FT_PROCEEDS)
+                    .ft_resolve_proceeds(ft_contract_id, account_id, amount.into()),
+            )
+    }
+
+    pub(crate) fn internal_add_payment_ft(&mut self, ft_contract_id: &AccountId) {
+        if self.payment_fts.insert(ft_contract_id) {
+            self.log_payment_ft("add_payment_ft", ft_contract_id);
+        }
+    }
+
+    pub(crate) fn internal_remove_payment_ft(&mut self, ft_contract_id: &AccountId) {
+        if self.payment_fts.remove(ft_contract_id) {
+            self.log_payment_ft("remove_payment_ft", ft_contract_id);
+        }
+    }
+
+    fn log_payment_ft(&self, event_type: &str, ft_contract_id: &AccountId) {
+        env::log_str(
+            json!({
+                "type": event_type,
+                "params": {
+                    "ft_contract_id": ft_contract_id,
+                }
+            }).to_string().as_str()
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn setup_ft_priced_series(context: &mut VMContextBuilder) -> (Contract, TokenSeriesId) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.add_payment_ft(accounts(4));
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_set_series_ft_price(
+            token_series.token_series_id.clone(),
+            Some(FtPrice { ft_contract_id: accounts(4), price: U128(1000) }),
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.storage_deposit(None, None);
+        (contract, token_series.token_series_id)
+    }
+
+    #[test]
+    fn test_ft_on_transfer() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_series_id) = setup_ft_priced_series(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(4))
+            .build());
+        let msg = json!({ "token_series_id": token_series_id, "receiver_id": accounts(2) }).to_string();
+        match contract.ft_on_transfer(accounts(1), U128(1500), msg) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128(500)),
+            PromiseOrValue::Promise(_) => panic!("expected the unused amount"),
+        }
+        let token_id = format!("{}:1", token_series_id);
+        assert_eq!(contract.nft_token(token_id).unwrap().owner_id, accounts(2));
+        assert!(contract.storage_balance_of(accounts(1)).unwrap().available.0 < MINT_STORAGE_COST);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token series is priced in another FT")]
+    fn test_ft_on_transfer_wrong_token() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_series_id) = setup_ft_priced_series(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.add_payment_ft(accounts(3));
+
+        testing_env!(context.attached_deposit(0).predecessor_account_id(accounts(3)).build());
+        let msg = json!({ "token_series_id": token_series_id }).to_string();
+        contract.ft_on_transfer(accounts(1), U128(1000), msg);
+    }
+}
@@ -0,0 +1,421 @@
+//! Generative series. The creator stores weighted trait tables on a series; when a token is
+//! minted the contract rolls one value per table from the block random seed and writes the
+//! result into the token's `extra` as `{"attributes": [{"trait_type", "value"}]}`, the layout
+//! marketplaces already read, so no off-chain generator has to be trusted. The attributes of
+//! every token, rolled or not, are indexed by trait so tokens can be looked up by attribute
+//! and ranked by rarity.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::nft_core::NonFungibleTokenCore;
+use crate::series::TokenSeriesId;
+use crate::token::{Token, TokenId};
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen};
+use serde_json::json;
+
+/// Most trait tables a series can have.
+pub const MAX_TRAIT_TABLES: usize = 16;
+/// Most values a single trait table can have.
+pub const MAX_TRAIT_VALUES: usize = 64;
+/// Longest trait type or value.
+pub const MAX_TRAIT_LEN: usize = 64;
+/// Rarity scores are fixed point with this many units per point.
+pub const RARITY_SCORE_SCALE: u128 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TraitValue {
+    pub value: String,
+    /// Odds of this value are `weight` over the sum of the weights of the table
+    pub weight: u32,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TraitTable {
+    pub trait_type: String,
+    pub values: Vec<TraitValue>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenAttribute {
+    pub trait_type: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenRarityJson {
+    pub token_id: TokenId,
+    /// In units of `RARITY_SCORE_SCALE`
+    pub score: U128,
+    /// 1 for the rarest token of the series
+    pub rank: U64,
+}
+
+fn assert_valid_trait_tables(trait_tables: &[TraitTable]) {
+    assert!(
+        !trait_tables.is_empty() && trait_tables.len() <= MAX_TRAIT_TABLES,
+        "FireFly: Must have between 1 and {} trait tables",
+        MAX_TRAIT_TABLES
+    );
+    for (index, trait_table) in trait_tables.iter().enumerate() {
+        assert!(
+            !trait_table.trait_type.is_empty() && trait_table.trait_type.len() <= MAX_TRAIT_LEN,
+            "FireFly: trait_type must be between 1 and {} characters",
+            MAX_TRAIT_LEN
+        );
+        assert!(
+            trait_tables[..index].iter().all(|other| other.trait_type != trait_table.trait_type),
+            "FireFly: trait_type {} is listed more than once",
+            trait_table.trait_type
+        );
+        assert!(
+            !trait_table.values.is_empty() && trait_table.values.len() <= MAX_TRAIT_VALUES,
+            "FireFly: A trait table must have between 1 and {} values",
+            MAX_TRAIT_VALUES
+        );
+        for trait_value in trait_table.values.iter() {
+            assert!(
+                !trait_value.value.is_empty() && trait_value.value.len() <= MAX_TRAIT_LEN,
+                "FireFly: Trait value must be between 1 and {} characters",
+                MAX_TRAIT_LEN
+            );
+            assert!(trait_value.weight > 0, "FireFly: Trait weight must be positive");
+        }
+    }
+}
+
+/// One value from each table, drawn by weight. The random seed is mixed with `token_id` and
+/// the table index so tokens minted in the same block, and tables with the same weights,
+/// roll independently.
+pub(crate) fn roll_attributes(trait_tables: &[TraitTable], token_id: &TokenId) -> Vec<TokenAttribute> {
+    trait_tables
+        .iter()
+        .enumerate()
+        .map(|(index, trait_table)| {
+            let mut payload = env::random_seed();
+            payload.extend(token_id.as_bytes());
+            payload.extend((index as u32).to_le_bytes());
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&env::sha256(&payload)[..8]);
+            let total_weight: u64 = trait_table.values.iter().map(|trait_value| u64::from(trait_value.weight)).sum();
+            let mut roll = u64::from_le_bytes(bytes) % total_weight;
+            let trait_value = trait_table
+                .values
+                .iter()
+                .find(|trait_value| {
+                    let weight = u64::from(trait_value.weight);
+                    if roll < weight {
+                        true
+                    } else {
+                        roll -= weight;
+                        false
+                    }
+                })
+                .expect("FireFly: Trait roll out of range");
+            TokenAttribute { trait_type: trait_table.trait_type.clone(), value: trait_value.value.clone() }
+        })
+        .collect()
+}
+
+/// The `attributes` in a token's `extra`, `None` when there are none.
+pub(crate) fn attributes_of(extra: Option<&str>) -> Option<Vec<TokenAttribute>> {
+    let extra: serde_json::Value = serde_json::from_str(extra?).ok()?;
+    serde_json::from_value(extra.get("attributes")?.clone()).ok()
+}
+
+/// `extra` with its `attributes` set. An `extra` that isn't a JSON object is replaced.
+pub(crate) fn extra_with_attributes(extra: Option<String>, attributes: &[TokenAttribute]) -> String {
+    let mut extra = extra
+        .and_then(|extra| serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&extra).ok())
+        .unwrap_or_default();
+    extra.insert("attributes".to_string(), json!(attributes));
+    serde_json::Value::Object(extra).to_string()
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Roll traits from `trait_tables` for every token minted from a series from now on, or
+    /// stop with `None`. Can only change before the first mint and while the metadata isn't
+    /// frozen. Creator only, the caller pays for the storage.
+    #[payable]
+    pub fn nft_set_series_traits(&mut self, token_series_id: TokenSeriesId, trait_tables: Option<Vec<TraitTable>>) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(!token_series.is_multi_token, "FireFly: Token series is multi-token");
+        assert!(!token_series.metadata_frozen, "FireFly: Token series metadata is frozen");
+        assert_eq!(
+            self.internal_minted_count(&token_series_id, &token_series),
+            0,
+            "FireFly: Token series already has minted tokens"
+        );
+        if let Some(trait_tables) = &trait_tables {
+            assert_valid_trait_tables(trait_tables);
+        }
+
+        token_series.trait_tables = trait_tables.clone();
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        env::log_str(
+            json!({
+                "type": "nft_set_series_traits",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "trait_tables": trait_tables,
+                }
+            }).to_string().as_str()
+        );
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn nft_series_traits(&self, token_series_id: TokenSeriesId) -> Option<Vec<TraitTable>> {
+        self.token_series_by_id
+            .get(&token_series_id)
+            .expect("FireFly: Token series not exist")
+            .trait_tables
+    }
+
+    /// Tokens of a series whose `attributes` have `trait_type` set to `value`.
+    pub fn nft_tokens_by_trait(
+        &self,
+        token_series_id: TokenSeriesId,
+        trait_type: String,
+        value: String,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        let token_ids = match self.tokens.tokens_by_trait.get(&(token_series_id, trait_type, value)) {
+            Some(token_ids) => token_ids,
+            None => return vec![],
+        };
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        let token_ids = token_ids.as_vector();
+        (start_index as u64..token_ids.len())
+            .take(limit)
+            .filter_map(|index| self.tokens.nft_token(token_ids.get(index).unwrap()))
+            .collect()
+    }
+
+    /// Number of tokens of a series whose `attributes` have `trait_type` set to `value`.
+    pub fn nft_trait_supply(&self, token_series_id: TokenSeriesId, trait_type: String, value: String) -> U64 {
+        U64(self
+            .tokens
+            .tokens_by_trait
+            .get(&(token_series_id, trait_type, value))
+            .map_or(0, |token_ids| token_ids.len()))
+    }
+
+    /// Statistical rarity of a token: for each of its attributes, the number of tokens in the
+    /// series over the number sharing that attribute, summed. `None` for unknown tokens and
+    /// tokens without attributes.
+    pub fn nft_rarity_score(&self, token_id: TokenId) -> Option<U128> {
+        let token_series_id = self.tokens.token_id_format.token_series_id_of(&token_id).to_string();
+        let supply = self.token_series_by_id.get(&token_series_id)?.tokens.len();
+        self.internal_rarity_score(&token_series_id, &token_id, supply).map(U128)
+    }
+
+    /// Tokens of a series with attributes, rarest first, ties in token id order. Scores every
+    /// token of the series, so very large series can run out of view gas.
+    pub fn nft_series_rarity_ranking(
+        &self,
+        token_series_id: TokenSeriesId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenRarityJson> {
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        let supply = token_series.tokens.len();
+        let mut scores: Vec<(TokenId, u128)> = token_series
+            .tokens
+            .iter()
+            .filter_map(|token_id| {
+                let score = self.internal_rarity_score(&token_series_id, &token_id, supply)?;
+                Some((token_id, score))
+            })
+            .collect();
+        scores.sort_by(|(a_id, a_score), (b_id, b_score)| b_score.cmp(a_score).then_with(|| a_id.cmp(b_id)));
+        scores
+            .into_iter()
+            .enumerate()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|(index, (token_id, score))| TokenRarityJson {
+                token_id,
+                score: U128(score),
+                rank: U64(index as u64 + 1),
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    fn internal_rarity_score(&self, token_series_id: &TokenSeriesId, token_id: &TokenId, supply: u64) -> Option<u128> {
+        let token_metadata = self.tokens.token_metadata(token_id)?;
+        let attributes = attributes_of(token_metadata.extra.as_deref())?;
+        let score = attributes
+            .into_iter()
+            .map(|attribute| {
+                let key = (token_series_id.clone(), attribute.trait_type, attribute.value);
+                let count = self.tokens.tokens_by_trait.get(&key).map_or(1, |token_ids| token_ids.len().max(1));
+                u128::from(supply) * RARITY_SCORE_SCALE / u128::from(count)
+            })
+            .sum();
+        Some(score)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::TokenMetadata;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn sample_trait_tables() -> Vec<TraitTable> {
+        vec![
+            TraitTable {
+                trait_type: "Background".into(),
+                values: vec![
+                    TraitValue { value: "Red".into(), weight: 1 },
+                    TraitValue { value: "Blue".into(), weight: 3 },
+                ],
+            },
+            TraitTable {
+                trait_type: "Hat".into(),
+                values: vec![TraitValue { value: "Crown".into(), weight: 1 }],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_mint_rolls_traits() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_metadata = TokenMetadata { extra: Some("{\"artist\":\"alice\"}".into()), ..sample_token_metadata() };
+        let token_series = contract.nft_create_series(token_metadata, None, None);
+        contract.nft_set_series_traits(token_series.token_series_id.clone(), Some(sample_trait_tables()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .build());
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+        let token = contract.nft_token(token.token_id).unwrap();
+        let extra: serde_json::Value = serde_json::from_str(&token.metadata.extra.unwrap()).unwrap();
+        assert_eq!(extra["artist"], "alice");
+        let attributes: Vec<TokenAttribute> = serde_json::from_value(extra["attributes"].clone()).unwrap();
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(attributes[0].trait_type, "Background".to_string());
+        assert!(["Red", "Blue"].contains(&attributes[0].value.as_str()));
+        assert_eq!(attributes[1], TokenAttribute { trait_type: "Hat".into(), value: "Crown".into() });
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Trait weight must be positive")]
+    fn test_set_traits_zero_weight() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let mut trait_tables = sample_trait_tables();
+        trait_tables[1].values[0].weight = 0;
+        contract.nft_set_series_traits(token_series.token_series_id, Some(trait_tables));
+    }
+
+    #[test]
+    fn test_tokens_by_trait() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_set_series_traits(token_series.token_series_id.clone(), Some(sample_trait_tables()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(3 * MINT_STORAGE_COST)
+            .build());
+        for _ in 0..3 {
+            contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+        }
+        let crowns = |contract: &Contract| {
+            contract.nft_trait_supply(token_series.token_series_id.clone(), "Hat".into(), "Crown".into())
+        };
+        assert_eq!(crowns(&contract), U64(3));
+        let tokens = contract.nft_tokens_by_trait(
+            token_series.token_series_id.clone(),
+            "Hat".into(),
+            "Crown".into(),
+            Some(U128(1)),
+            Some(10),
+        );
+        assert_eq!(tokens.len(), 2);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.nft_burn(tokens[0].token_id.clone());
+        assert_eq!(crowns(&contract), U64(2));
+    }
+
+    #[test]
+    fn test_rarity_ranking() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(4 * MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        for color in ["Red", "Blue", "Blue"].iter() {
+            let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+            let extra = format!("{{\"attributes\":[{{\"trait_type\":\"Background\",\"value\":\"{}\"}}]}}", color);
+            let metadata = TokenMetadata { extra: Some(extra), ..token.metadata };
+            contract.tokens.internal_set_token_metadata(&token.token_id, &metadata);
+        }
+
+        assert_eq!(contract.nft_rarity_score("1:1".to_string()), Some(U128(3 * RARITY_SCORE_SCALE)));
+        assert_eq!(contract.nft_rarity_score("1:2".to_string()), Some(U128(3 * RARITY_SCORE_SCALE / 2)));
+        let ranking = contract.nft_series_rarity_ranking(token_series.token_series_id, Some(U128(1)), None);
+        assert_eq!(
+            ranking,
+            vec![
+                TokenRarityJson { token_id: "1:2".to_string(), score: U128(15_000), rank: U64(2) },
+                TokenRarityJson { token_id: "1:3".to_string(), score: U128(15_000), rank: U64(3) },
+            ]
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Lazy minting: a series creator signs mint vouchers off-chain with an ed25519 key they
+//! registered on the contract, and whoever holds a voucher redeems it with
+//! `nft_redeem_voucher`, paying the voucher price. Nothing is minted, and the creator pays
+//! no gas, until a voucher is sold.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::TokenSeriesId;
+use crate::token::Token;
+use crate::utils::refund_deposit;
+use ed25519_dalek::Verifier;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, CurveType, Promise, PublicKey};
+use serde_json::json;
+
+/// Off-chain promise of the creator of `token_series_id` to mint one token to `receiver_id`
+/// for `price`. `nonce` makes each voucher of a series single use.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintVoucher {
+    pub token_series_id: TokenSeriesId,
+    pub price: U128,
+    pub receiver_id: AccountId,
+    pub nonce: U64,
+}
+
+impl MintVoucher {
+    /// Bytes the creator signs: this contract's account id followed by the Borsh encoded
+    /// voucher, so a voucher can't be replayed on another deployment.
+    pub fn signing_payload(&self, contract_id: &AccountId) -> Vec<u8> {
+        let mut payload = contract_id.as_bytes().to_vec();
+        payload.extend(self.try_to_vec().expect("FireFly: Cannot serialize voucher"));
+        payload
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Register, or with `None` remove, the ed25519 key whose signatures on vouchers for the
+    /// caller's series are accepted.
+    #[payable]
+    pub fn nft_set_voucher_key(&mut self, public_key: Option<PublicKey>) {
+        let initial_storage_usage = env::storage_usage();
+        let creator_id = env::predecessor_account_id();
+        match public_key {
+            Some(public_key) => {
+                assert!(
+                    matches!(public_key.curve_type(), CurveType::ED25519),
+                    "FireFly: Voucher key must be ed25519"
+                );
+                self.voucher_keys.insert(&creator_id, &public_key);
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+            }
+            None => {
+                assert_one_yocto();
+                if self.voucher_keys.remove(&creator_id).is_some() {
+                    let released = initial_storage_usage.saturating_sub(env::storage_usage());
+                    Promise::new(creator_id).transfer(Balance::from(released) * env::storage_byte_cost());
+                }
+            }
+        }
+    }
+
+    pub fn nft_voucher_key(&self, creator_id: AccountId) -> Option<PublicKey> {
+        self.voucher_keys.get(&creator_id)
+    }
+
+    pub fn nft_is_voucher_redeemed(&self, token_series_id: TokenSeriesId, nonce: U64) -> bool {
+        self.redeemed_vouchers.contains(&(token_series_id, nonce.0))
+    }
+
+    /// Mint the token promised by `voucher`, checking `signature` against the voucher key
+    /// of the series creator. The caller pays the voucher price, which goes to the creator,
+    /// plus storage.
+    #[payable]
+    pub fn nft_redeem_voucher(&mut self, voucher: MintVoucher, signature: Base64VecU8) -> Token {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_minting_not_paused();
+        let token_series = self
+            .token_series_by_id
+            .get(&voucher.token_series_id)
+            .expect("FireFly: Token series not exist");
+        let public_key = self
+            .voucher_keys
+            .get(&token_series.creator_id)
+            .expect("FireFly: Creator has no voucher key");
+        assert_valid_voucher_signature(&public_key, &voucher, &signature.0);
+        assert!(
+            self.redeemed_vouchers.insert(&(voucher.token_series_id.clone(), voucher.nonce.0)),
+            "FireFly: Voucher already redeemed"
+        );
+        self.assert_open_edition_window(&voucher.token_series_id);
+        self.assert_not_auctioned(&voucher.token_series_id);
+        self.assert_receiver_allowed(&voucher.receiver_id);
+        self.assert_not_banned(&env::predecessor_account_id());
+        self.assert_not_banned(&voucher.receiver_id);
+
+        let price = voucher.price.0;
+        let attached_deposit = env::attached_deposit();
+        self.internal_record_wallet_mint(&voucher.token_series_id, &token_series, &voucher.receiver_id);
+        let token = self.tokens.internal_mint(
+            &mut self.token_series_by_id,
+            voucher.token_series_id.clone(),
+            voucher.receiver_id.clone(),
+            None,
+        );
+
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert!(
+            attached_deposit >= price + storage_cost,
+            "FireFly: Must attach {} yoctoNEAR to cover price and storage",
+            price + storage_cost
+        );
+        if price > 0 {
+            let proceeds = self.internal_take_fees(price, 0);
+            self.internal_record_series_sale(&voucher.token_series_id, price, proceeds);
+        }
+        let refund = attached_deposit - price - storage_cost;
+        if refund > 1 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_redeem_voucher",
+                "params": {
+                    "token_series_id": voucher.token_series_id,
+                    "token_id": token.token_id,
+                    "receiver_id": voucher.receiver_id,
+                    "price": voucher.price,
+                    "nonce": voucher.nonce,
+                }
+            }).to_string().as_str()
+        );
+
+        token
+    }
+}
+
+fn assert_valid_voucher_signature(public_key: &PublicKey, voucher: &MintVoucher, signature: &[u8]) {
+    // the first byte of a near `PublicKey` is its curve type
+    let public_key = ed25519_dalek::PublicKey::from_bytes(&public_key.as_bytes()[1..])
+        .unwrap_or_else(|_| env::panic_str("FireFly: Invalid voucher key"));
+    let signature = ed25519_dalek::Signature::from_bytes(signature)
+        .unwrap_or_else(|_| env::panic_str("FireFly: Invalid voucher signature"));
+    assert!(
+        public_key.verify(&voucher.signing_payload(&env::current_account_id()), &signature).is_ok(),
+        "FireFly: Invalid voucher signature"
+    );
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+    use std::convert::TryFrom;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn keypair() -> Keypair {
+        let secret = SecretKey::from_bytes(&[7u8; 32]).unwrap();
+        let public = (&secret).into();
+        Keypair { secret, public }
+    }
+
+    fn setup_contract() -> (VMContextBuilder, Contract, TokenSeriesId) {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let public_key = PublicKey::try_from([vec![0u8], keypair().public.to_bytes().to_vec()].concat()).unwrap();
+        contract.nft_set_voucher_key(Some(public_key));
+        (context, contract, token_series.token_series_id)
+    }
+
+    fn sign(voucher: &MintVoucher) -> Base64VecU8 {
+        Base64VecU8(keypair().sign(&voucher.signing_payload(&accounts(0))).to_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_redeem_voucher() {
+        let (mut context, mut contract, token_series_id) = setup_contract();
+        let voucher = MintVoucher {
+            token_series_id: token_series_id.clone(),
+            price: U128(10u128.pow(24)),
+            receiver_id: accounts(3),
+            nonce: U64(1),
+        };
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(10u128.pow(24) + 2 * MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let signature = sign(&voucher);
+        let token = contract.nft_redeem_voucher(voucher, signature);
+        assert_eq!(token.owner_id, accounts(3));
+        assert!(contract.nft_is_voucher_redeemed(token_series_id, U64(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Invalid voucher signature")]
+    fn test_redeem_tampered_voucher() {
+        let (mut context, mut contract, token_series_id) = setup_contract();
+        let mut voucher = MintVoucher {
+            token_series_id,
+            price: U128(10u128.pow(24)),
+            receiver_id: accounts(3),
+            nonce: U64(1),
+        };
+        let signature = sign(&voucher);
+        voucher.price = U128(0);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_redeem_voucher(voucher, signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Voucher already redeemed")]
+    fn test_redeem_voucher_twice() {
+        let (mut context, mut contract, token_series_id) = setup_contract();
+        let voucher = MintVoucher {
+            token_series_id,
+            price: U128(10u128.pow(24)),
+            receiver_id: accounts(3),
+            nonce: U64(1),
+        };
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * (10u128.pow(24) + 2 * MINT_STORAGE_COST))
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_redeem_voucher(voucher.clone(), sign(&voucher));
+        contract.nft_redeem_voucher(voucher.clone(), sign(&voucher));
+    }
+}
@@ -8,28 +8,152 @@ pub mod metadata;
 pub mod events;
 pub mod event;
 pub mod series;
+pub mod admin;
+pub mod receipt;
+pub mod refund;
+pub mod ownership_proof;
+pub mod tags;
+pub mod royalty;
+pub mod deny_list;
+pub mod multi_token;
+pub mod mt_events;
+pub mod series_events;
+pub mod approval_events;
+pub mod open_edition;
+pub mod mint_auction;
+pub mod lazy_mint;
+pub mod allowlist;
+pub mod transaction_fee;
+pub mod treasury;
+pub mod minters;
+pub mod pause;
+pub mod banned;
+pub mod lock;
+pub mod rental;
+pub mod staking;
+pub mod reveal;
+pub mod migrate;
+pub mod upgrade;
+pub mod storage;
+pub mod ft_payment;
+pub mod claims;
+pub mod drops;
+pub mod composable;
+pub mod fractions;
+pub mod auction;
+pub mod revenue;
+pub mod generative;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) mod test_utils;
 
+use approval::Approvals;
 use enumeration::NonFungibleTokenEnumeration;
 use metadata::{
-    NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
+    MetadataHashPolicy, NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
 };
-use series::{TokenSeriesId, TokenSeries};
+use series::{MetadataRefresh, TokenSeriesId, TokenSeries};
+use admin::AdminState;
+use receipt::PurchaseReceipt;
+use ownership_proof::OwnershipChallenge;
+use open_edition::OpenEdition;
+use events::{ContractMetadataUpdate, NftBurn, NftMint, NftTransfer};
+use mint_auction::MintAuction;
+use allowlist::Allowlist;
+use transaction_fee::TransactionFee;
+use pause::PauseFlags;
+use rental::{Rental, RentalListing};
+use staking::Stake;
+use ft_payment::FtPrice;
+use claims::Claim;
+use drops::DropCampaign;
+use fractions::Fraction;
+use auction::TokenAuction;
+use revenue::SeriesEarnings;
 // use utils::*;
+use utils::{assert_at_least_one_yocto, refund_deposit};
 use token::{Token, TokenId};
-use nft_core::{NonFungibleToken, NonFungibleTokenCore};
+use nft_core::{NonFungibleToken, NonFungibleTokenCore, NonFungibleTokenResolver, TokenIdFormat};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, UnorderedMap};
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, TreeMap, UnorderedSet, Vector};
+use near_sdk::json_types::{Base64VecU8, U128};
 use near_sdk::{
-    env, near_bindgen, AccountId, BorshStorageKey, PanicOnDefault, PromiseOrValue
+    assert_one_yocto, env, near_bindgen, AccountId, Balance, BorshStorageKey, PanicOnDefault,
+    Promise, PromiseOrValue, PublicKey,
 };
+use std::collections::HashMap;
+
+/// Most tokens `nft_mint_batch` mints in one call, keeping it within the gas limit.
+pub const MAX_BATCH_MINT: usize = 50;
+/// Most tokens `nft_transfer_batch` moves in one call.
+pub const MAX_BATCH_TRANSFER: usize = 50;
+/// Most tokens `nft_tokens_batch` looks up in one call.
+pub const MAX_BATCH_LOOKUP: usize = 100;
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     tokens: NonFungibleToken,
     metadata: LazyOption<NFTContractMetadata>,
-    token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeries>,
-    market_data_transaction_fee: UnorderedMap<TokenSeriesId, u128>,
+    token_series_by_id: LookupMap<TokenSeriesId, TokenSeries>,
+    market_data_transaction_fee: LookupMap<TokenSeriesId, u128>,
+    transaction_fee: TransactionFee,
+    treasury_id: AccountId,
+    protocol_fee: u16,
+    admin: AdminState,
+    max_price: Balance,
+    default_royalty: u32,
+    purchase_receipts: LookupMap<AccountId, Vec<PurchaseReceipt>>,
+    pending_refunds: LookupMap<AccountId, Balance>,
+    ownership_challenges: LookupMap<String, OwnershipChallenge>,
+    token_series_by_title: TreeMap<String, TokenSeriesId>,
+    tags_by_series: LookupMap<TokenSeriesId, Vec<String>>,
+    series_by_tag: LookupMap<String, UnorderedSet<TokenSeriesId>>,
+    denied_receivers: UnorderedSet<AccountId>,
+    mt_balances: LookupMap<(TokenSeriesId, AccountId), Balance>,
+    mt_supply: LookupMap<TokenSeriesId, Balance>,
+    open_editions: LookupMap<TokenSeriesId, OpenEdition>,
+    mint_auctions: LookupMap<TokenSeriesId, MintAuction>,
+    voucher_keys: LookupMap<AccountId, PublicKey>,
+    redeemed_vouchers: LookupSet<(TokenSeriesId, u64)>,
+    allowlists: LookupMap<TokenSeriesId, Allowlist>,
+    mints_per_wallet: LookupMap<(TokenSeriesId, AccountId), u64>,
+    minters: UnorderedSet<AccountId>,
+    paused: PauseFlags,
+    banned_accounts: UnorderedSet<AccountId>,
+    locked_tokens: LookupMap<TokenId, AccountId>,
+    rental_listings: LookupMap<TokenId, RentalListing>,
+    rentals: LookupMap<TokenId, Rental>,
+    staking_reward_rates: LookupMap<TokenSeriesId, Balance>,
+    stakes: LookupMap<TokenId, Stake>,
+    staking_pool: Balance,
+    max_royalty: u32,
+    max_royalty_accounts: u32,
+    storage_deposits: LookupMap<AccountId, Balance>,
+    payment_fts: UnorderedSet<AccountId>,
+    ft_prices: LookupMap<TokenSeriesId, FtPrice>,
+    pending_ft_proceeds: LookupMap<(AccountId, AccountId), Balance>,
+    claims: LookupMap<PublicKey, Claim>,
+    drops: LookupMap<TokenSeriesId, DropCampaign>,
+    drop_mints: LookupMap<(TokenSeriesId, u32, AccountId), u64>,
+    token_parents: LookupMap<TokenId, TokenId>,
+    token_children: LookupMap<TokenId, Vec<TokenId>>,
+    fractions: LookupMap<TokenId, Fraction>,
+    share_balances: LookupMap<(TokenId, AccountId), Balance>,
+    auctions: LookupMap<TokenId, TokenAuction>,
+    token_royalties: LookupMap<TokenId, HashMap<AccountId, u32>>,
+    referral_fee: u16,
+    claimable_revenue: LookupMap<AccountId, Balance>,
+    series_earnings: LookupMap<TokenSeriesId, SeriesEarnings>,
+    deleted_series: u64,
+    metadata_hash_policy: MetadataHashPolicy,
+    // ids of `token_series_by_id`, only needed by the views listing series
+    token_series_ids: Vector<TokenSeriesId>,
+    // position of each series in `token_series_ids`, so deleting one doesn't scan the ids
+    token_series_index: LookupMap<TokenSeriesId, u64>,
+    // series whose tokens are still being rewritten to replaced metadata, see `series`
+    series_metadata_refresh: LookupMap<TokenSeriesId, MetadataRefresh>,
+    // tokens that ever left the account they were minted to, their royalty can't change
+    transferred_token_ids: LookupSet<TokenId>,
 }
 
 const DATA_IMAGE_SVG_NEAR_ICON: &str = "data:image/svg+xml,%3Csvg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 288 288'%3E%3Cg id='l' data-name='l'%3E%3Cpath d='M187.58,79.81l-30.1,44.69a3.2,3.2,0,0,0,4.75,4.2L191.86,103a1.2,1.2,0,0,1,2,.91v80.46a1.2,1.2,0,0,1-2.12.77L102.18,77.93A15.35,15.35,0,0,0,90.47,72.5H87.34A15.34,15.34,0,0,0,72,87.84V201.16A15.34,15.34,0,0,0,87.34,216.5h0a15.35,15.35,0,0,0,13.08-7.31l30.1-44.69a3.2,3.2,0,0,0-4.75-4.2L96.14,186a1.2,1.2,0,0,1-2-.91V104.61a1.2,1.2,0,0,1,2.12-.77l89.55,107.23a15.35,15.35,0,0,0,11.71,5.43h3.13A15.34,15.34,0,0,0,216,201.16V87.84A15.34,15.34,0,0,0,200.66,72.5h0A15.35,15.35,0,0,0,187.58,79.81Z'/%3E%3C/g%3E%3C/svg%3E";
@@ -41,9 +165,60 @@ enum StorageKey {
     TokenMetadata,
     Enumeration,
     Approval,
+    // prefixes of the former `UnorderedMap`s, kept so the variants after them keep their tags
+    #[allow(dead_code)]
     TokenSeriesById,
     TokensBySeriesInner { token_series: String },
+    #[allow(dead_code)]
     MarketDataTransactionFee,
+    PendingAdminActions,
+    PurchaseReceipts,
+    PendingRefunds,
+    OwnershipChallenges,
+    TokenSeriesByTitle,
+    TagsBySeries,
+    SeriesByTag,
+    SeriesByTagInner { tag_hash: Vec<u8> },
+    DeniedReceivers,
+    MultiTokenBalances,
+    MultiTokenSupply,
+    OpenEditions,
+    MintAuctions,
+    VoucherKeys,
+    RedeemedVouchers,
+    Allowlists,
+    MintsPerWallet,
+    Minters,
+    BannedAccounts,
+    LockedTokens,
+    RentalListings,
+    Rentals,
+    StakingRewardRates,
+    Stakes,
+    StorageDeposits,
+    PaymentFts,
+    FtPrices,
+    PendingFtProceeds,
+    Claims,
+    Drops,
+    DropMints,
+    TokenParents,
+    TokenChildren,
+    Fractions,
+    ShareBalances,
+    Auctions,
+    TokenRoyalties,
+    ClaimableRevenue,
+    SeriesEarnings,
+    EditionPoolInner { token_series: String },
+    TokenSeriesIds,
+    TokenSeriesIndex,
+    // `token_series_by_id` and `market_data_transaction_fee` as `LookupMap`s, they used to be
+    // `UnorderedMap`s under `TokenSeriesById` and `MarketDataTransactionFee`
+    TokenSeriesByIdLookup,
+    MarketDataTransactionFeeLookup,
+    SeriesMetadataRefresh,
+    TransferredTokenIds,
 }
 
 #[near_bindgen]
@@ -63,25 +238,27 @@ impl Contract {
                 reference: None,
                 reference_hash: None,
             },
+            None,
         )
     }
 
+    /// `token_id_format` defaults to ids like `"1:42"`, it can't be changed later.
     #[init]
-    pub fn new(owner_id: AccountId, metadata: NFTContractMetadata) -> Self {
+    pub fn new(owner_id: AccountId, metadata: NFTContractMetadata, token_id_format: Option<TokenIdFormat>) -> Self {
         assert!(!env::state_exists(), "Already initialized");
         metadata.assert_valid();
-        Self {
-            tokens: NonFungibleToken::new(
-                StorageKey::NonFungibleToken,
-                owner_id,
-                StorageKey::TokenMetadata,
-                Some(StorageKey::Enumeration),
-                Some(StorageKey::Approval),
-            ),
-            metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
-            token_series_by_id: UnorderedMap::new(StorageKey::TokenSeriesById),
-            market_data_transaction_fee: UnorderedMap::new(StorageKey::MarketDataTransactionFee),
-        }
+        let token_id_format = token_id_format.unwrap_or_default();
+        token_id_format.assert_valid();
+        migrate::write_state_version();
+        let tokens = NonFungibleToken::new(
+            StorageKey::NonFungibleToken,
+            owner_id,
+            StorageKey::TokenMetadata,
+            Some(StorageKey::Enumeration),
+            Some(StorageKey::Approval),
+            token_id_format,
+        );
+        Self::internal_new(tokens, &metadata)
     }
 
     /// Mint a new token with ID=`token_id` belonging to `receiver_id`.
@@ -90,19 +267,393 @@ impl Contract {
     /// in this call. `self.tokens.mint` will also require it to be Some, since
     /// `StorageKey::TokenMetadata` was provided at initialization.
     ///
-    /// `self.tokens.mint` will enforce `predecessor_account_id` to equal the `owner_id` given in
-    /// initialization call to `new`.
+    /// Only the series creator and the minters may mint this way, e.g. for giveaways;
+    /// everyone else goes through `nft_buy`, which charges the series price.
     #[payable]
     pub fn nft_mint(
         &mut self,
         token_series_id: TokenSeriesId,
         receiver_id: AccountId,
     ) -> Token {
-        self.tokens.internal_mint(&mut self.token_series_by_id, token_series_id, receiver_id)
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        self.assert_can_mint(&token_series);
+        self.assert_minting_not_paused();
+        self.assert_not_banned(&env::predecessor_account_id());
+        self.assert_not_banned(&receiver_id);
+        self.assert_open_edition_window(&token_series_id);
+        self.assert_not_auctioned(&token_series_id);
+        let initial_storage_usage = env::storage_usage();
+        let token = self.tokens.internal_mint(&mut self.token_series_by_id, token_series_id, receiver_id, None);
+        self.internal_charge_storage(0, env::storage_usage() - initial_storage_usage);
+        token
+    }
+
+    /// `nft_mint` with a token id of `"{token_series_id}:{edition_name}"`, e.g. `"42:gold"`,
+    /// instead of the next edition number. The name must be unique within the series.
+    #[payable]
+    pub fn nft_mint_named(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        receiver_id: AccountId,
+        edition_name: String,
+    ) -> Token {
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        self.assert_can_mint(&token_series);
+        self.assert_minting_not_paused();
+        self.assert_not_banned(&env::predecessor_account_id());
+        self.assert_not_banned(&receiver_id);
+        self.assert_open_edition_window(&token_series_id);
+        self.assert_not_auctioned(&token_series_id);
+        let initial_storage_usage = env::storage_usage();
+        let token = self.tokens.internal_mint_edition(
+            &mut self.token_series_by_id,
+            token_series_id,
+            receiver_id,
+            Some(&edition_name),
+            None,
+        );
+        self.internal_charge_storage(0, env::storage_usage() - initial_storage_usage);
+        token
+    }
+
+    /// Mint one edition of a series to each of `receiver_ids`, an account listed several times
+    /// gets several editions. Creator or minter only, like `nft_mint`. Storage is charged once
+    /// for the whole batch and a single mint event lists every token.
+    #[payable]
+    pub fn nft_mint_batch(&mut self, token_series_id: TokenSeriesId, receiver_ids: Vec<AccountId>) -> Vec<Token> {
+        self.internal_mint_batch(token_series_id, receiver_ids, None)
+    }
+
+    /// Airdrop one edition of a series to each of `receivers`, which must all be different.
+    /// Same rules as `nft_mint_batch`, the mint event carries the memo `airdrop`.
+    #[payable]
+    pub fn nft_airdrop(&mut self, token_series_id: TokenSeriesId, receivers: Vec<AccountId>) -> Vec<Token> {
+        for (index, receiver_id) in receivers.iter().enumerate() {
+            assert!(
+                !receivers[..index].contains(receiver_id),
+                "FireFly: {} is listed more than once",
+                receiver_id
+            );
+        }
+        self.internal_mint_batch(token_series_id, receivers, Some("airdrop"))
+    }
+
+    /// Transfer several of the caller's tokens, each `(receiver_id, token_id)` pair like an
+    /// `nft_transfer` by the owner. All transfers are logged in a single transfer event.
+    #[payable]
+    pub fn nft_transfer_batch(&mut self, receivers: Vec<(AccountId, TokenId)>, memo: Option<String>) {
+        assert_one_yocto();
+        self.assert_transfers_not_paused();
+        assert!(
+            !receivers.is_empty() && receivers.len() <= MAX_BATCH_TRANSFER,
+            "FireFly: Batch must transfer between 1 and {} tokens",
+            MAX_BATCH_TRANSFER
+        );
+        let sender_id = env::predecessor_account_id();
+        self.assert_not_banned(&sender_id);
+        for (receiver_id, token_id) in receivers.iter() {
+            self.assert_receiver_allowed(receiver_id);
+            self.assert_not_banned(receiver_id);
+            self.assert_not_soulbound(token_id);
+            self.assert_not_locked(token_id);
+            self.internal_clear_rental(token_id);
+            self.internal_record_transfer(token_id);
+            let owner_id = self.tokens.core.owner_by_id.get(token_id).expect("FireFly: Token not found");
+            assert_eq!(owner_id, sender_id, "FireFly: Token owner only");
+            assert_ne!(receiver_id, &sender_id, "FireFly: Current and next owner must differ");
+            if let Some(approvals) = self.tokens.approvals_by_id.as_mut().and_then(|by_id| by_id.remove(token_id)) {
+                self.tokens.internal_refund_approvals(token_id, sender_id.clone(), &approvals);
+            }
+            self.tokens.internal_transfer_unguarded(token_id, &sender_id, receiver_id);
+        }
+
+        let mut token_ids_by_receiver: Vec<(&AccountId, Vec<&str>)> = vec![];
+        for (receiver_id, token_id) in receivers.iter() {
+            match token_ids_by_receiver.iter_mut().find(|(id, _)| *id == receiver_id) {
+                Some((_, token_ids)) => token_ids.push(token_id),
+                None => token_ids_by_receiver.push((receiver_id, vec![token_id])),
+            }
+        }
+        let transfer_events: Vec<NftTransfer> = token_ids_by_receiver
+            .iter()
+            .map(|(receiver_id, token_ids)| NftTransfer {
+                old_owner_id: &sender_id,
+                new_owner_id: receiver_id,
+                token_ids,
+                authorized_id: None,
+                memo: memo.as_deref(),
+            })
+            .collect();
+        NftTransfer::emit_many(&transfer_events);
+    }
+
+    /// Destroy one of the caller's tokens, refunding the storage it used.
+    #[payable]
+    pub fn nft_burn(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        assert_eq!(env::predecessor_account_id(), owner_id, "FireFly: Token owner only");
+        self.assert_not_locked(&token_id);
+        self.assert_no_children(&token_id);
+
+        let initial_storage_usage = env::storage_usage();
+        self.internal_clear_rental(&token_id);
+        self.token_royalties.remove(&token_id);
+        self.transferred_token_ids.remove(&token_id);
+        self.tokens.internal_burn(&mut self.token_series_by_id, &token_id, &owner_id);
+        self.internal_restart_series_metadata_refresh(
+            &self.tokens.token_id_format.token_series_id_of(&token_id).to_string(),
+        );
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(owner_id.clone())
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+
+        NftBurn { owner_id: &owner_id, token_ids: &[&token_id], authorized_id: None, memo: None }.emit();
+    }
+
+    /// Page through the tokens of `owner_id`. Tokens come in the order they were received in,
+    /// except that sending one away moves the owner's most recently received token into its
+    /// place, so pages stay consistent as long as the owner's tokens don't change.
+    pub fn nft_tokens_owner(
+        &self,
+        owner_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        self.tokens.nft_tokens_for_owner(owner_id, from_index, limit)
+    }
+
+    /// Page through the tokens of `owner_id` from one series, e.g. "your items from this
+    /// collection". Walks whichever is smaller of the owner's tokens and the series' tokens.
+    pub fn nft_tokens_for_owner_by_series(
+        &self,
+        owner_id: AccountId,
+        token_series_id: TokenSeriesId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        let owner_tokens = match self.tokens.core.tokens_per_owner.as_ref().and_then(|by_owner| by_owner.get(&owner_id)) {
+            Some(owner_tokens) => owner_tokens,
+            None => return vec![],
+        };
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let token_ids: Box<dyn Iterator<Item = TokenId>> = if owner_tokens.len() <= token_series.tokens.len() {
+            Box::new(
+                owner_tokens
+                    .iter()
+                    .filter(|token_id| self.tokens.token_id_format.token_series_id_of(token_id) == token_series_id),
+            )
+        } else {
+            Box::new(token_series.tokens.iter().filter(|token_id| owner_tokens.contains(token_id)))
+        };
+        token_ids
+            .filter(|token_id| !self.tokens.is_token_hidden(token_id))
+            .skip(start_index as usize)
+            .take(limit)
+            .filter_map(|token_id| self.tokens.nft_token(token_id))
+            .collect()
+    }
+
+    /// Hide one of the caller's tokens from their enumeration views, e.g. unsolicited spam.
+    /// The token is not burned and can still be looked up with `nft_token`.
+    #[payable]
+    pub fn nft_hide_token(&mut self, token_id: TokenId) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        self.tokens.internal_set_token_hidden(&token_id, true);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Make a previously hidden token show up in enumeration views again.
+    #[payable]
+    pub fn nft_unhide_token(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        self.tokens.internal_set_token_hidden(&token_id, false);
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(env::predecessor_account_id())
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+    }
+
+    /// Whether `account_id` owns `token_id`. Only reads the owner entry, without loading the
+    /// metadata and approvals a full `Token` needs.
+    pub fn nft_is_owner(&self, token_id: TokenId, account_id: AccountId) -> bool {
+        self.tokens.core.owner_by_id.get(&token_id) == Some(account_id)
+    }
+
+    /// Look up several tokens at once, e.g. a page of marketplace listings. Each entry is
+    /// `None` when that token doesn't exist.
+    pub fn nft_tokens_batch(&self, token_ids: Vec<TokenId>) -> Vec<Option<Token>> {
+        assert!(
+            token_ids.len() <= MAX_BATCH_LOOKUP,
+            "FireFly: At most {} tokens can be looked up at once",
+            MAX_BATCH_LOOKUP
+        );
+        token_ids.into_iter().map(|token_id| self.tokens.nft_token(token_id)).collect()
+    }
+
+    /// Hash committed for the metadata of `token_id`, see `TokenMetadata::canonical_hash`.
+    pub fn nft_metadata_hash(&self, token_id: TokenId) -> Option<Base64VecU8> {
+        self.tokens.token_metadata_hash_by_id.get(&token_id).map(Base64VecU8)
+    }
+
+    /// Whether `metadata_json` is the metadata committed for `token_id`, so archives and
+    /// marketplaces can detect tampered caches. Malformed JSON never matches.
+    pub fn nft_verify_metadata(&self, token_id: TokenId, metadata_json: String) -> bool {
+        let committed_hash = match self.tokens.token_metadata_hash_by_id.get(&token_id) {
+            Some(hash) => hash,
+            None => return false,
+        };
+        match serde_json::from_str::<TokenMetadata>(&metadata_json) {
+            Ok(metadata) => metadata.canonical_hash() == committed_hash,
+            Err(_) => false,
+        }
     }
 
-    pub fn nft_tokens_owner(&self, owner_id: AccountId) -> Vec<Token> {
-        self.tokens.nft_tokens_for_owner(owner_id, None, None)
+    pub fn nft_is_token_hidden(&self, token_id: TokenId) -> bool {
+        self.tokens.is_token_hidden(&token_id)
+    }
+
+    /// Replace the contract metadata, e.g. to move to another `base_uri` gateway. Owner only,
+    /// the caller pays for extra storage. Owner sets requiring several confirmations use
+    /// `AdminAction::SetContractMetadata`.
+    #[payable]
+    pub fn set_contract_metadata(&mut self, metadata: NFTContractMetadata) {
+        assert_at_least_one_yocto();
+        self.assert_owner();
+        metadata.assert_valid();
+        let initial_storage_usage = env::storage_usage();
+        self.internal_set_contract_metadata(&metadata);
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn get_token_id_format(&self) -> TokenIdFormat {
+        self.tokens.token_id_format.clone()
+    }
+}
+
+impl Contract {
+    /// State around `tokens` with the given contract `metadata`, every parameter at its default
+    /// and the other collections empty.
+    pub(crate) fn internal_new(tokens: NonFungibleToken, metadata: &NFTContractMetadata) -> Self {
+        let treasury_id = tokens.core.owner_id.clone();
+        Self {
+            tokens,
+            metadata: LazyOption::new(StorageKey::Metadata, Some(metadata)),
+            token_series_by_id: LookupMap::new(StorageKey::TokenSeriesByIdLookup),
+            market_data_transaction_fee: LookupMap::new(StorageKey::MarketDataTransactionFeeLookup),
+            transaction_fee: TransactionFee::default(),
+            treasury_id,
+            protocol_fee: 0,
+            admin: AdminState::new(),
+            max_price: series::MAX_PRICE,
+            default_royalty: 0,
+            purchase_receipts: LookupMap::new(StorageKey::PurchaseReceipts),
+            pending_refunds: LookupMap::new(StorageKey::PendingRefunds),
+            ownership_challenges: LookupMap::new(StorageKey::OwnershipChallenges),
+            token_series_by_title: TreeMap::new(StorageKey::TokenSeriesByTitle),
+            tags_by_series: LookupMap::new(StorageKey::TagsBySeries),
+            series_by_tag: LookupMap::new(StorageKey::SeriesByTag),
+            denied_receivers: UnorderedSet::new(StorageKey::DeniedReceivers),
+            mt_balances: multi_token::new_mt_balances(),
+            mt_supply: LookupMap::new(StorageKey::MultiTokenSupply),
+            open_editions: LookupMap::new(StorageKey::OpenEditions),
+            mint_auctions: LookupMap::new(StorageKey::MintAuctions),
+            voucher_keys: LookupMap::new(StorageKey::VoucherKeys),
+            redeemed_vouchers: LookupSet::new(StorageKey::RedeemedVouchers),
+            allowlists: LookupMap::new(StorageKey::Allowlists),
+            mints_per_wallet: LookupMap::new(StorageKey::MintsPerWallet),
+            minters: UnorderedSet::new(StorageKey::Minters),
+            paused: PauseFlags::default(),
+            banned_accounts: UnorderedSet::new(StorageKey::BannedAccounts),
+            locked_tokens: LookupMap::new(StorageKey::LockedTokens),
+            rental_listings: LookupMap::new(StorageKey::RentalListings),
+            rentals: LookupMap::new(StorageKey::Rentals),
+            staking_reward_rates: LookupMap::new(StorageKey::StakingRewardRates),
+            stakes: LookupMap::new(StorageKey::Stakes),
+            staking_pool: 0,
+            max_royalty: series::MAX_ROYALTY,
+            max_royalty_accounts: series::MAX_ROYALTY_ACCOUNTS,
+            storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
+            payment_fts: UnorderedSet::new(StorageKey::PaymentFts),
+            ft_prices: LookupMap::new(StorageKey::FtPrices),
+            pending_ft_proceeds: LookupMap::new(StorageKey::PendingFtProceeds),
+            claims: LookupMap::new(StorageKey::Claims),
+            drops: LookupMap::new(StorageKey::Drops),
+            drop_mints: LookupMap::new(StorageKey::DropMints),
+            token_parents: LookupMap::new(StorageKey::TokenParents),
+            token_children: LookupMap::new(StorageKey::TokenChildren),
+            fractions: LookupMap::new(StorageKey::Fractions),
+            share_balances: LookupMap::new(StorageKey::ShareBalances),
+            auctions: LookupMap::new(StorageKey::Auctions),
+            token_royalties: LookupMap::new(StorageKey::TokenRoyalties),
+            referral_fee: 0,
+            claimable_revenue: LookupMap::new(StorageKey::ClaimableRevenue),
+            series_earnings: LookupMap::new(StorageKey::SeriesEarnings),
+            deleted_series: 0,
+            metadata_hash_policy: MetadataHashPolicy::Strict,
+            token_series_ids: Vector::new(StorageKey::TokenSeriesIds),
+            token_series_index: LookupMap::new(StorageKey::TokenSeriesIndex),
+            series_metadata_refresh: LookupMap::new(StorageKey::SeriesMetadataRefresh),
+            transferred_token_ids: LookupSet::new(StorageKey::TransferredTokenIds),
+        }
+    }
+
+    pub(crate) fn internal_set_contract_metadata(&mut self, metadata: &NFTContractMetadata) {
+        self.metadata.set(metadata);
+        ContractMetadataUpdate { memo: None }.emit();
+    }
+
+    fn internal_mint_batch(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        receiver_ids: Vec<AccountId>,
+        memo: Option<&str>,
+    ) -> Vec<Token> {
+        assert!(
+            !receiver_ids.is_empty() && receiver_ids.len() <= MAX_BATCH_MINT,
+            "FireFly: Batch must mint between 1 and {} tokens",
+            MAX_BATCH_MINT
+        );
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        self.assert_can_mint(&token_series);
+        self.assert_minting_not_paused();
+        self.assert_not_banned(&env::predecessor_account_id());
+        receiver_ids.iter().for_each(|receiver_id| self.assert_not_banned(receiver_id));
+        self.assert_open_edition_window(&token_series_id);
+        self.assert_not_auctioned(&token_series_id);
+
+        let initial_storage_usage = env::storage_usage();
+        let tokens: Vec<Token> = receiver_ids
+            .into_iter()
+            .map(|receiver_id| {
+                self.tokens.internal_mint(&mut self.token_series_by_id, token_series_id.clone(), receiver_id, None)
+            })
+            .collect();
+        self.internal_charge_storage(0, env::storage_usage() - initial_storage_usage);
+
+        let mut token_ids_by_owner: Vec<(&AccountId, Vec<&str>)> = vec![];
+        for token in tokens.iter() {
+            match token_ids_by_owner.iter_mut().find(|(owner_id, _)| *owner_id == &token.owner_id) {
+                Some((_, token_ids)) => token_ids.push(&token.token_id),
+                None => token_ids_by_owner.push((&token.owner_id, vec![&token.token_id])),
+            }
+        }
+        let mint_events: Vec<NftMint> = token_ids_by_owner
+            .iter()
+            .map(|(owner_id, token_ids)| NftMint { owner_id, token_ids, memo })
+            .collect();
+        NftMint::emit_many(&mint_events);
+
+        tokens
     }
 }
 
@@ -116,6 +667,13 @@ impl NonFungibleTokenCore for Contract {
         approval_id: Option<u64>,
         memo: Option<String>,
     ) {
+        self.assert_transfers_not_paused();
+        self.assert_receiver_allowed(&receiver_id);
+        self.assert_transfer_not_banned(&token_id, &receiver_id);
+        self.assert_not_soulbound(&token_id);
+        self.assert_not_locked(&token_id);
+        self.internal_clear_rental(&token_id);
+        self.internal_record_transfer(&token_id);
         self.tokens.nft_transfer(receiver_id, token_id, approval_id, memo)
     }
 
@@ -128,6 +686,13 @@ impl NonFungibleTokenCore for Contract {
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<bool> {
+        self.assert_transfers_not_paused();
+        self.assert_receiver_allowed(&receiver_id);
+        self.assert_transfer_not_banned(&token_id, &receiver_id);
+        self.assert_not_soulbound(&token_id);
+        self.assert_not_locked(&token_id);
+        self.internal_clear_rental(&token_id);
+        self.internal_record_transfer(&token_id);
         self.tokens.nft_transfer_call(receiver_id, token_id, approval_id, memo, msg)
     }
 
@@ -136,6 +701,22 @@ impl NonFungibleTokenCore for Contract {
     }
 }
 
+#[near_bindgen]
+impl NonFungibleTokenResolver for Contract {
+    /// Returns the token to `previous_owner_id`, with the approvals it had, when the receiver
+    /// of an `nft_transfer_call` asks for it or fails.
+    #[private]
+    fn nft_resolve_transfer(
+        &mut self,
+        previous_owner_id: AccountId,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approvals: Option<Approvals>,
+    ) -> bool {
+        self.tokens.nft_resolve_transfer(previous_owner_id, receiver_id, token_id, approvals)
+    }
+}
+
 #[near_bindgen]
 impl NonFungibleTokenMetadataProvider for Contract {
     fn nft_metadata(&self) -> NFTContractMetadata {
@@ -143,44 +724,40 @@ impl NonFungibleTokenMetadataProvider for Contract {
     }
 }
 
+#[near_bindgen]
+impl NonFungibleTokenEnumeration for Contract {
+    fn nft_total_supply(&self) -> U128 {
+        self.tokens.nft_total_supply()
+    }
+
+    fn nft_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token> {
+        self.tokens.nft_tokens(from_index, limit)
+    }
+
+    fn nft_supply_for_owner(&self, account_id: AccountId) -> U128 {
+        self.tokens.nft_supply_for_owner(account_id)
+    }
+
+    fn nft_tokens_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        self.tokens.nft_tokens_for_owner(account_id, from_index, limit)
+    }
+}
+
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
-    use near_sdk::json_types::U128;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::json_types::{U128, U64};
+    use near_sdk::test_utils::{self, accounts};
     use near_sdk::testing_env;
     use std::collections::HashMap;
 
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
     use super::*;
 
-    // const MINT_STORAGE_COST: u128 = 5870000000000000000000;
-    // const MINT_STORAGE_COST: u128 = 5910000000000000000000;
-
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .current_account_id(accounts(0))
-            .signer_account_id(predecessor_account_id.clone())
-            .predecessor_account_id(predecessor_account_id);
-        builder
-    }
-
-    fn sample_token_metadata() -> TokenMetadata {
-        TokenMetadata {
-            title: Some("Olympus Mons".into()),
-            description: Some("The tallest mountain in the charted solar system".into()),
-            media: None,
-            media_hash: None,
-            copies: Some(1u64),
-            issued_at: None,
-            expires_at: None,
-            starts_at: None,
-            updated_at: None,
-            extra: None,
-            reference: None,
-            reference_hash: None,
-        }
-    }
-
     #[test]
     fn test_new() {
         let mut context = get_context(accounts(1));
@@ -200,37 +777,198 @@ mod tests {
 
     #[test]
     fn test_mint() {
-        const MINT_STORAGE_COST: u128 = 6010000000000000000000;
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
             .build());
-        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128::from(0u128)));
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128::from(0u128)), None);
         let token = contract.nft_mint(token_series.token_series_id, accounts(2));
         assert_eq!(token.token_id, "1:1".to_string());
         assert_eq!(token.owner_id.to_string(), accounts(2).to_string());
         assert_eq!(token.metadata, sample_token_metadata());
         assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
+        assert!(contract.nft_is_owner(token.token_id.clone(), accounts(2)));
+        assert!(!contract.nft_is_owner(token.token_id, accounts(1)));
+        assert!(!contract.nft_is_owner("1:2".to_string(), accounts(2)));
+    }
+
+    #[test]
+    fn test_burn() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let metadata = TokenMetadata { copies: Some(2), ..sample_token_metadata() };
+        let token_series = contract.nft_create_series(metadata, None, None);
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_burn(token.token_id.clone());
+        assert!(contract.nft_token(token.token_id.clone()).is_none());
+        assert!(contract.nft_tokens_owner(accounts(2), None, None).is_empty());
+        assert_eq!(contract.nft_supply_for_owner(accounts(2)), U128(0));
+        assert_eq!(contract.nft_supply_for_series(token_series.token_series_id.clone()).0, 0);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+        assert_eq!(token.token_id, "1:2".to_string());
+        assert!(!contract.nft_get_series_single(token_series.token_series_id).is_mintable);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token owner only")]
+    fn test_burn_not_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_burn(token.token_id);
+    }
+
+    #[test]
+    fn test_mint_batch() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let metadata = TokenMetadata { copies: Some(3), ..sample_token_metadata() };
+        let token_series = contract.nft_create_series(metadata, None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(4 * MINT_STORAGE_COST)
+            .build());
+        let tokens = contract.nft_mint_batch(token_series.token_series_id, vec![accounts(1), accounts(2), accounts(1)]);
+        let token_ids: Vec<String> = tokens.into_iter().map(|token| token.token_id).collect();
+        assert_eq!(token_ids, vec!["1:1".to_string(), "1:2".to_string(), "1:3".to_string()]);
+        assert_eq!(contract.nft_tokens_owner(accounts(1), None, None).len(), 2);
+        assert_eq!(
+            test_utils::get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_mint","data":[{"owner_id":"bob","token_ids":["1:1","1:3"]},{"owner_id":"charlie","token_ids":["1:2"]}]}"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: bob is listed more than once")]
+    fn test_airdrop_duplicate_receiver() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_airdrop(token_series.token_series_id, vec![accounts(1), accounts(2), accounts(1)]);
+    }
+
+    #[test]
+    fn test_transfer_batch() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let metadata = TokenMetadata { copies: Some(3), ..sample_token_metadata() };
+        let token_series = contract.nft_create_series(metadata, None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(4 * MINT_STORAGE_COST)
+            .build());
+        contract.nft_mint_batch(token_series.token_series_id, vec![accounts(1), accounts(1), accounts(1)]);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_transfer_batch(
+            vec![(accounts(2), "1:1".to_string()), (accounts(3), "1:2".to_string()), (accounts(2), "1:3".to_string())],
+            None,
+        );
+        assert!(contract.nft_tokens_owner(accounts(1), None, None).is_empty());
+        assert_eq!(contract.nft_tokens_owner(accounts(2), None, None).len(), 2);
+        assert!(contract.nft_is_owner("1:2".to_string(), accounts(3)));
+        assert_eq!(
+            test_utils::get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"charlie","token_ids":["1:1","1:3"]},{"old_owner_id":"bob","new_owner_id":"danny","token_ids":["1:2"]}]}"#
+        );
+    }
+
+    #[test]
+    fn test_verify_metadata() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        let metadata_json = serde_json::to_string(&sample_token_metadata()).unwrap();
+        assert!(contract.nft_verify_metadata(token.token_id.clone(), metadata_json));
+
+        let mut tampered = sample_token_metadata();
+        tampered.title = Some("Olympus Mons (fake)".into());
+        let tampered_json = serde_json::to_string(&tampered).unwrap();
+        assert!(!contract.nft_verify_metadata(token.token_id.clone(), tampered_json));
+        assert!(!contract.nft_verify_metadata(token.token_id, "not json".to_string()));
     }
 
     #[test]
     fn test_transfer() {
-        const MINT_STORAGE_COST: u128 = 6010000000000000000000;
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
             .build());
-        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128::from(0u128)));
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128::from(0u128)), None);
         let token = contract.nft_mint(token_series.token_series_id, accounts(1));
 
         testing_env!(context
@@ -255,4 +993,183 @@ mod tests {
             panic!("token not correctly created, or not found by nft_token");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_hide_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let mut metadata = sample_token_metadata();
+        metadata.copies = Some(2);
+        let token_series = contract.nft_create_series(metadata, None, None);
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+        contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_hide_token(token.token_id.clone());
+
+        let tokens = contract.nft_tokens_owner(accounts(1), None, None);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_id, "1:2".to_string());
+        assert!(contract.nft_token(token.token_id.clone()).is_some());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_transfer(accounts(2), token.token_id.clone(), None, None);
+        assert!(!contract.nft_is_token_hidden(token.token_id));
+        assert_eq!(contract.nft_tokens_owner(accounts(2), None, None).len(), 1);
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(1));
+        assert_eq!(contract.nft_supply_for_owner(accounts(2)), U128(1));
+    }
+    #[test]
+    fn test_enumeration() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let mut metadata = sample_token_metadata();
+        metadata.copies = Some(3);
+        let token_series = contract.nft_create_series(metadata, None, None);
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+        contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        assert_eq!(contract.nft_total_supply(), U128(3));
+        assert_eq!(contract.nft_tokens(Some(U128(1)), Some(1))[0].token_id, "1:2".to_string());
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(2));
+        let tokens = contract.nft_tokens_for_owner(accounts(1), Some(U128(1)), None);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_id, "1:2".to_string());
+        let tokens = contract.nft_tokens_owner(accounts(1), None, Some(1));
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_id, "1:1".to_string());
+
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+        let tokens = contract.nft_tokens_for_owner_by_series(accounts(1), token_series.token_series_id, None, None);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_id, "2:1".to_string());
+        assert_eq!(contract.nft_tokens_for_owner_by_series(accounts(1), "1".to_string(), Some(U128(1)), None).len(), 1);
+
+        let tokens = contract.nft_tokens_batch(vec!["1:3".to_string(), "1:4".to_string()]);
+        assert_eq!(tokens[0].as_ref().map(|token| token.owner_id.clone()), Some(accounts(2)));
+        assert!(tokens[1].is_none());
+    }
+
+    #[test]
+    fn test_mint_named() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let mut metadata = sample_token_metadata();
+        metadata.copies = Some(3);
+        let token_series = contract.nft_create_series(metadata, None, None);
+        let token = contract.nft_mint_named(token_series.token_series_id.clone(), accounts(1), "gold".to_string());
+        assert_eq!(token.token_id, "1:gold".to_string());
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+        assert_eq!(token.token_id, "1:2".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token 1:gold already exists")]
+    fn test_mint_named_twice() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let mut metadata = sample_token_metadata();
+        metadata.copies = Some(3);
+        let token_series = contract.nft_create_series(metadata, None, None);
+        contract.nft_mint_named(token_series.token_series_id.clone(), accounts(1), "gold".to_string());
+        contract.nft_mint_named(token_series.token_series_id, accounts(2), "gold".to_string());
+    }
+
+    #[test]
+    fn test_padded_token_ids() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let metadata = NFTContractMetadata {
+            spec: NFT_METADATA_SPEC.to_string(),
+            name: "Olympus".to_string(),
+            symbol: "OLY".to_string(),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        };
+        let token_id_format = TokenIdFormat { delimiter: "#".to_string(), edition_padding: Some(4) };
+        let mut contract = Contract::new(accounts(0), metadata, Some(token_id_format.clone()));
+        assert_eq!(contract.get_token_id_format(), token_id_format);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+        assert_eq!(token.token_id, "1#0001".to_string());
+        let detail = contract.nft_token_detail(token.token_id).unwrap();
+        assert_eq!(detail.token_series_id, token_series.token_series_id);
+        assert_eq!(detail.edition, Some(U64(1)));
+    }
+
+    #[test]
+    fn test_set_contract_metadata() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(10000000000000000000000)
+            .build());
+        let metadata = NFTContractMetadata {
+            base_uri: Some("https://gateway.example/ipfs".to_string()),
+            ..contract.nft_metadata()
+        };
+        contract.set_contract_metadata(metadata.clone());
+        assert_eq!(contract.nft_metadata(), metadata);
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"nep171","version":"1.1.0","event":"contract_metadata_update","data":[{}]}"#]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Owner only")]
+    fn test_set_contract_metadata_not_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        let metadata = contract.nft_metadata();
+        contract.set_contract_metadata(metadata);
+    }
+}
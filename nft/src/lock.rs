@@ -0,0 +1,142 @@
+//! Token locking for game integrations. An account approved for a token, per token or as an
+//! operator of its owner, can lock it while it is in use, e.g. equipped in a game. A locked
+//! token stays with its owner but can't be transferred or burned until the same locker
+//! unlocks it.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::approval::NonFungibleTokenApproval;
+use crate::token::TokenId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+
+#[near_bindgen]
+impl Contract {
+    /// Lock `token_id` on behalf of the caller, who must be approved for it. The caller pays
+    /// for the storage.
+    #[payable]
+    pub fn nft_lock(&mut self, token_id: TokenId) {
+        assert_at_least_one_yocto();
+        let locker_id = env::predecessor_account_id();
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        assert!(
+            self.nft_is_approved(token_id.clone(), locker_id.clone(), None)
+                || self.tokens.is_operator(&owner_id, &locker_id),
+            "FireFly: Approved account only"
+        );
+        assert!(self.locked_tokens.get(&token_id).is_none(), "FireFly: Token is locked");
+
+        let initial_storage_usage = env::storage_usage();
+        self.locked_tokens.insert(&token_id, &locker_id);
+        self.log_lock("nft_lock", &token_id, &owner_id, &locker_id);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Release a lock taken by the caller, refunding its storage.
+    #[payable]
+    pub fn nft_unlock(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let locker_id = env::predecessor_account_id();
+        assert_eq!(
+            self.locked_tokens.get(&token_id),
+            Some(locker_id.clone()),
+            "FireFly: Locker only"
+        );
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+
+        let initial_storage_usage = env::storage_usage();
+        self.locked_tokens.remove(&token_id);
+        self.log_lock("nft_unlock", &token_id, &owner_id, &locker_id);
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(locker_id)
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+    }
+
+    /// Account holding the lock on `token_id`, `None` when it is not locked.
+    pub fn nft_locked_by(&self, token_id: TokenId) -> Option<AccountId> {
+        self.locked_tokens.get(&token_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_not_locked(&self, token_id: &TokenId) {
+        assert!(self.locked_tokens.get(token_id).is_none(), "FireFly: Token is locked");
+    }
+
+    fn log_lock(&self, event_type: &str, token_id: &TokenId, owner_id: &AccountId, locker_id: &AccountId) {
+        env::log_str(
+            json!({
+                "type": event_type,
+                "params": {
+                    "token_id": token_id,
+                    "owner_id": owner_id,
+                    "locker_id": locker_id,
+                }
+            }).to_string().as_str()
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn setup_locked_token(context: &mut VMContextBuilder) -> (Contract, TokenId) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_approve(token.token_id.clone(), accounts(3), None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.nft_lock(token.token_id.clone());
+        assert_eq!(contract.nft_locked_by(token.token_id.clone()), Some(accounts(3)));
+        (contract, token.token_id)
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token is locked")]
+    fn test_locked_token_cannot_transfer() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_locked_token(&mut context);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.nft_transfer(accounts(2), token_id, None, None);
+    }
+
+    #[test]
+    fn test_unlock() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_locked_token(&mut context);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.nft_unlock(token_id.clone());
+        assert_eq!(contract.nft_locked_by(token_id.clone()), None);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.nft_transfer(accounts(2), token_id.clone(), None, None);
+        assert_eq!(contract.nft_token(token_id).unwrap().owner_id, accounts(2));
+    }
+}
@@ -1,6 +1,7 @@
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata as StandardTokenMetadata;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::json_types::Base64VecU8;
-use near_sdk::require;
+use near_sdk::{env, require};
 use near_sdk::serde::{Deserialize, Serialize};
 
 /// This spec can be treated like a version of the standard.
@@ -41,6 +42,17 @@ pub struct TokenMetadata {
     pub reference_hash: Option<Base64VecU8>, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
 }
 
+/// How strictly the contract checks `media_hash` and `reference_hash` on series and token
+/// metadata. A hash without the field it covers is rejected either way.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum MetadataHashPolicy {
+    /// Hashes are optional, but one that is given must be 32 bytes.
+    Lenient,
+    /// `media` and `reference` each need their 32 byte hash, as NEP-177 asks.
+    Strict,
+}
+
 /// Offers details on the contract-level metadata.
 pub trait NonFungibleTokenMetadataProvider {
     fn nft_metadata(&self) -> NFTContractMetadata;
@@ -60,6 +72,19 @@ impl NFTContractMetadata {
 }
 
 impl TokenMetadata {
+    /// sha256 of the JSON serialization, with fields in declaration order so the same
+    /// metadata always hashes the same regardless of how the input JSON was laid out.
+    pub fn canonical_hash(&self) -> Vec<u8> {
+        let json = serde_json::to_vec(self).unwrap_or_else(|_| env::abort());
+        env::sha256(&json)
+    }
+
+    /// Panics unless the hashes of `media` and `reference` follow `policy`.
+    pub fn assert_valid_hashes(&self, policy: MetadataHashPolicy) {
+        assert_valid_hash("media", self.media.is_some(), self.media_hash.as_ref(), policy);
+        assert_valid_hash("reference", self.reference.is_some(), self.reference_hash.as_ref(), policy);
+    }
+
     pub fn assert_valid(&self) {
         require!(self.media.is_some() == self.media_hash.is_some());
         if let Some(media_hash) = &self.media_hash {
@@ -72,3 +97,86 @@ impl TokenMetadata {
         }
     }
 }
+
+/// Token metadata is stored by the `near_contract_standards` core, which has its own copy of
+/// this struct with the same fields.
+impl From<StandardTokenMetadata> for TokenMetadata {
+    fn from(metadata: StandardTokenMetadata) -> Self {
+        let StandardTokenMetadata {
+            title,
+            description,
+            media,
+            media_hash,
+            copies,
+            issued_at,
+            expires_at,
+            starts_at,
+            updated_at,
+            extra,
+            reference,
+            reference_hash,
+        } = metadata;
+        TokenMetadata {
+            title,
+            description,
+            media,
+            media_hash,
+            copies,
+            issued_at,
+            expires_at,
+            starts_at,
+            updated_at,
+            extra,
+            reference,
+            reference_hash,
+        }
+    }
+}
+
+impl From<TokenMetadata> for StandardTokenMetadata {
+    fn from(metadata: TokenMetadata) -> Self {
+        let TokenMetadata {
+            title,
+            description,
+            media,
+            media_hash,
+            copies,
+            issued_at,
+            expires_at,
+            starts_at,
+            updated_at,
+            extra,
+            reference,
+            reference_hash,
+        } = metadata;
+        StandardTokenMetadata {
+            title,
+            description,
+            media,
+            media_hash,
+            copies,
+            issued_at,
+            expires_at,
+            starts_at,
+            updated_at,
+            extra,
+            reference,
+            reference_hash,
+        }
+    }
+}
+
+fn assert_valid_hash(field: &str, is_set: bool, hash: Option<&Base64VecU8>, policy: MetadataHashPolicy) {
+    match hash {
+        Some(hash) => {
+            assert!(is_set, "FireFly: {}_hash is set without {}", field, field);
+            assert!(hash.0.len() == 32, "FireFly: {}_hash has to be 32 bytes", field);
+        }
+        None => assert!(
+            !is_set || policy == MetadataHashPolicy::Lenient,
+            "FireFly: {} requires {}_hash",
+            field,
+            field
+        ),
+    }
+}
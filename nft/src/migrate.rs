@@ -0,0 +1,685 @@
+//! State migrations. The layout version of the stored `Contract` is kept under its own
+//! storage key, outside the struct, so new code can tell which layout it is reading. When a
+//! release changes the layout: keep the old struct around, add a `VersionedContract` variant
+//! reading it, convert it in `into_current` and bump `STATE_VERSION`. Then deploy and call
+//! `migrate`.
+//!
+//! Version 0 is the layout deployed before versioning, which stored no version. Version 1
+//! was only stamped by pre-release builds whose layout kept changing, so it is refused
+//! rather than guessed at. Version 2 kept the token core in this crate, version 3 moved it
+//! onto `near_contract_standards`, version 4 added `series_metadata_refresh` and version 5
+//! `transferred_token_ids`. Fields are
+//! only appended to `Contract`, so a layout that lacks the latest ones is read as its stored
+//! bytes and gets their empty encoding appended. Work that grows with the number of tokens
+//! or series doesn't fit in `migrate`, it is carried out by the owner in bounded steps with
+//! `migrate_step` while the contract stays paused.
+
+use crate::approval::Approvals;
+use crate::metadata::{NFTContractMetadata, TokenMetadata};
+use crate::nft_core::{NonFungibleToken, TokenIdFormat};
+use crate::pause::PauseFlags;
+use crate::series::{title_index_key, MetadataRefresh, TokenSeries, TokenSeriesId};
+use crate::token::TokenId;
+use crate::{Contract, StorageKey};
+use crate::ContractExt;
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata as StandardTokenMetadata;
+use near_contract_standards::non_fungible_token::NonFungibleToken as StandardNonFungibleToken;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, TreeMap, UnorderedMap, UnorderedSet};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, StorageUsage};
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+/// Layout version of the current `Contract`.
+pub const STATE_VERSION: u32 = 5;
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+/// Key `env::state_read` reads the contract struct from.
+const STATE_KEY: &[u8] = b"STATE";
+/// Key of the `V0Backfill` while `migrate_step` runs.
+const V0_BACKFILL_KEY: &[u8] = b"V0_BACKFILL";
+
+/// Every layout the contract state has been stored in.
+#[allow(clippy::large_enum_variant)]
+pub enum VersionedContract {
+    V0(ContractV0),
+    V2(ContractV2),
+    V3(ContractV3),
+    V4(ContractV4),
+    V5(Contract),
+}
+
+impl VersionedContract {
+    /// Read the stored state, assuming it was written with layout `version`.
+    fn read(version: u32) -> Self {
+        match version {
+            0 => VersionedContract::V0(env::state_read().expect("FireFly: Contract is not initialized")),
+            2 => VersionedContract::V2(ContractV2::read()),
+            3 => VersionedContract::V3(ContractV3::read()),
+            4 => VersionedContract::V4(ContractV4::read()),
+            5 => VersionedContract::V5(env::state_read().expect("FireFly: Contract is not initialized")),
+            _ => env::panic_str(&format!("FireFly: Unknown state version {}", version)),
+        }
+    }
+
+    fn into_current(self) -> Contract {
+        match self {
+            VersionedContract::V0(contract) => contract.into_current(),
+            VersionedContract::V2(contract) => contract.into_v3().into_v4().into_current(),
+            VersionedContract::V3(contract) => contract.into_v4().into_current(),
+            VersionedContract::V4(contract) => contract.into_current(),
+            VersionedContract::V5(contract) => contract,
+        }
+    }
+}
+
+/// `Contract` as first deployed.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV0 {
+    pub tokens: NonFungibleTokenV0,
+    pub metadata: LazyOption<NFTContractMetadata>,
+    pub token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeriesV0>,
+    pub market_data_transaction_fee: UnorderedMap<TokenSeriesId, u128>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct NonFungibleTokenV0 {
+    pub owner_id: AccountId,
+    pub extra_storage_in_bytes_per_token: StorageUsage,
+    pub owner_by_id: TreeMap<TokenId, AccountId>,
+    pub token_metadata_by_id: LookupMap<TokenId, StandardTokenMetadata>,
+    pub tokens_per_owner: Option<LookupMap<AccountId, UnorderedSet<TokenId>>>,
+    pub approvals_by_id: Option<LookupMap<TokenId, HashMap<AccountId, u64>>>,
+    pub next_approval_id_by_id: Option<LookupMap<TokenId, u64>>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TokenSeriesV0 {
+    pub metadata: TokenMetadata,
+    pub creator_id: AccountId,
+    pub tokens: UnorderedSet<TokenId>,
+    pub price: Option<Balance>,
+    pub is_mintable: bool,
+}
+
+impl ContractV0 {
+    /// Token, metadata and approval maps are read in place, they kept their prefixes and
+    /// encoding. Moving the series to their `LookupMap`s and filling in the owner counts and
+    /// metadata hashes the old layout didn't keep takes a call per token or series, so it is
+    /// left to `migrate_step`. Minting, transfers and approvals stay paused until it is done.
+    fn into_current(self) -> Contract {
+        let metadata = self.metadata.get().expect("FireFly: Contract metadata missing");
+        let core = StandardNonFungibleToken {
+            owner_id: self.tokens.owner_id,
+            extra_storage_in_bytes_per_token: self.tokens.extra_storage_in_bytes_per_token,
+            owner_by_id: self.tokens.owner_by_id,
+            token_metadata_by_id: Some(self.tokens.token_metadata_by_id),
+            tokens_per_owner: self.tokens.tokens_per_owner,
+            approvals_by_id: None,
+            next_approval_id_by_id: None,
+        };
+        // the approval maps keep their prefix, their `HashMap`s read as `Approvals`
+        let mut tokens = NonFungibleToken::with_core(
+            core,
+            StorageKey::TokenMetadata,
+            Some(StorageKey::Enumeration),
+            Some(StorageKey::Approval),
+            TokenIdFormat::default(),
+        );
+        tokens.measure_min_token_storage_cost();
+
+        let mut contract = Contract::internal_new(tokens, &metadata);
+        contract.paused = PauseFlags { minting: true, transfers: true, approvals: true };
+        V0Backfill {
+            next_series_index: 0,
+            last_token_id: None,
+            token_series_by_id: self.token_series_by_id,
+            market_data_transaction_fee: self.market_data_transaction_fee,
+        }
+        .write();
+        contract
+    }
+}
+
+/// Version 0 state `migrate_step` still has to carry over.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct V0Backfill {
+    /// Position in `token_series_by_id` of the next series to copy.
+    pub next_series_index: u64,
+    /// Last token whose owner count and metadata hash were filled in.
+    pub last_token_id: Option<TokenId>,
+    pub token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeriesV0>,
+    pub market_data_transaction_fee: UnorderedMap<TokenSeriesId, u128>,
+}
+
+impl V0Backfill {
+    fn read() -> Option<Self> {
+        env::storage_read(V0_BACKFILL_KEY).map(|bytes| {
+            Self::try_from_slice(&bytes)
+                .unwrap_or_else(|_| env::panic_str("FireFly: Cannot deserialize the migration state"))
+        })
+    }
+
+    fn write(&self) {
+        env::storage_write(V0_BACKFILL_KEY, &self.try_to_vec().unwrap());
+    }
+
+    /// Copy the next series with its transaction fee, `false` once every series was copied.
+    fn copy_series(&mut self, contract: &mut Contract) -> bool {
+        let token_series_id = match self.token_series_by_id.keys_as_vector().get(self.next_series_index) {
+            Some(token_series_id) => token_series_id,
+            None => return false,
+        };
+        let token_series = self.token_series_by_id.values_as_vector().get(self.next_series_index).unwrap();
+        self.next_series_index += 1;
+        if let Some(title) = &token_series.metadata.title {
+            contract.token_series_by_title.insert(&title_index_key(title, &token_series_id), &token_series_id);
+        }
+        contract.token_series_index.insert(&token_series_id, &contract.token_series_ids.len());
+        contract.token_series_ids.push(&token_series_id);
+        contract.token_series_by_id.insert(&token_series_id, &TokenSeries {
+            metadata: token_series.metadata,
+            creator_id: token_series.creator_id,
+            tokens: token_series.tokens,
+            price: token_series.price,
+            is_mintable: token_series.is_mintable,
+            royalty: HashMap::new(),
+            is_multi_token: false,
+            burned: 0,
+            mint_start: None,
+            mint_end: None,
+            max_mints_per_wallet: None,
+            is_soulbound: false,
+            metadata_frozen: false,
+            is_revealed: false,
+            provenance_hash: None,
+            pending_creator_id: None,
+            price_curve: None,
+            edition_pool: None,
+            trait_tables: None,
+            base_uri: None,
+        });
+        if let Some(transaction_fee) = self.market_data_transaction_fee.get(&token_series_id) {
+            contract.market_data_transaction_fee.insert(&token_series_id, &transaction_fee);
+        }
+        true
+    }
+
+    /// Count the next token for its owner and store its metadata hash, `false` once every
+    /// token was counted.
+    fn backfill_token(&mut self, contract: &mut Contract) -> bool {
+        let tokens = &mut contract.tokens;
+        let next = match &self.last_token_id {
+            Some(last_token_id) => tokens.core.owner_by_id.iter_from(last_token_id.clone()).next(),
+            None => tokens.core.owner_by_id.iter().next(),
+        };
+        let (token_id, owner_id) = match next {
+            Some(next) => next,
+            None => return false,
+        };
+        tokens.internal_change_owner_count(&owner_id, true);
+        if let Some(token_metadata) = tokens.token_metadata(&token_id) {
+            tokens.token_metadata_hash_by_id.insert(&token_id, &token_metadata.canonical_hash());
+        }
+        self.last_token_id = Some(token_id);
+        true
+    }
+
+    /// Drop the last entry of the old series maps, `false` once both are empty. Removing
+    /// the last entry moves no other.
+    fn drop_last_series(&mut self) -> bool {
+        let series_ids = self.token_series_by_id.keys_as_vector();
+        if let Some(token_series_id) = series_ids.len().checked_sub(1).and_then(|index| series_ids.get(index)) {
+            // the tokens set now belongs to the copied series
+            self.token_series_by_id.remove(&token_series_id);
+            return true;
+        }
+        let fee_series_ids = self.market_data_transaction_fee.keys_as_vector();
+        if let Some(token_series_id) = fee_series_ids.len().checked_sub(1).and_then(|index| fee_series_ids.get(index)) {
+            self.market_data_transaction_fee.remove(&token_series_id);
+            return true;
+        }
+        false
+    }
+}
+
+/// `Contract` while the token core was kept in this crate. Only `tokens` changed since, the
+/// fields after it are carried over as they were stored.
+pub struct ContractV2 {
+    pub tokens: NonFungibleTokenV2,
+    pub rest: Vec<u8>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct NonFungibleTokenV2 {
+    pub owner_id: AccountId,
+    pub extra_storage_in_bytes_per_token: StorageUsage,
+    pub owner_by_id: TreeMap<TokenId, AccountId>,
+    pub token_metadata_by_id: LookupMap<TokenId, StandardTokenMetadata>,
+    pub token_metadata_hash_by_id: LookupMap<TokenId, Vec<u8>>,
+    pub tokens_by_trait: LookupMap<(TokenSeriesId, String, String), UnorderedSet<TokenId>>,
+    pub tokens_per_owner: Option<LookupMap<AccountId, UnorderedSet<TokenId>>>,
+    pub hidden_token_ids: Option<LookupSet<TokenId>>,
+    pub token_count_per_owner: Option<LookupMap<AccountId, u64>>,
+    pub approvals_by_id: Option<LookupMap<TokenId, Approvals>>,
+    pub next_approval_id_by_id: Option<LookupMap<TokenId, u64>>,
+    pub operators_by_owner: Option<LookupMap<AccountId, Vec<AccountId>>>,
+    pub approval_expires_at: Option<LookupMap<(TokenId, u64), u64>>,
+    pub token_id_format: TokenIdFormat,
+}
+
+impl ContractV2 {
+    fn read() -> Self {
+        let state = env::storage_read(STATE_KEY).expect("FireFly: Contract is not initialized");
+        let mut rest = state.as_slice();
+        let tokens = NonFungibleTokenV2::deserialize(&mut rest)
+            .unwrap_or_else(|_| env::panic_str("FireFly: Cannot deserialize the contract state"));
+        Self { tokens, rest: rest.to_vec() }
+    }
+
+    /// Every map keeps its prefix, so this only rewrites the struct.
+    fn into_v3(self) -> ContractV3 {
+        let NonFungibleTokenV2 {
+            owner_id,
+            extra_storage_in_bytes_per_token,
+            owner_by_id,
+            token_metadata_by_id,
+            token_metadata_hash_by_id,
+            tokens_by_trait,
+            tokens_per_owner,
+            hidden_token_ids,
+            token_count_per_owner,
+            approvals_by_id,
+            next_approval_id_by_id,
+            operators_by_owner,
+            approval_expires_at,
+            token_id_format,
+        } = self.tokens;
+        let tokens = NonFungibleToken {
+            core: StandardNonFungibleToken {
+                owner_id,
+                extra_storage_in_bytes_per_token,
+                owner_by_id,
+                token_metadata_by_id: Some(token_metadata_by_id),
+                tokens_per_owner,
+                approvals_by_id: None,
+                next_approval_id_by_id: None,
+            },
+            token_metadata_hash_by_id,
+            tokens_by_trait,
+            hidden_token_ids,
+            token_count_per_owner,
+            approvals_by_id,
+            next_approval_id_by_id,
+            operators_by_owner,
+            approval_expires_at,
+            token_id_format,
+        };
+        ContractV3 { state: [tokens.try_to_vec().unwrap(), self.rest].concat() }
+    }
+}
+
+/// `Contract` before `series_metadata_refresh`, as stored.
+pub struct ContractV3 {
+    pub state: Vec<u8>,
+}
+
+impl ContractV3 {
+    fn read() -> Self {
+        Self { state: env::storage_read(STATE_KEY).expect("FireFly: Contract is not initialized") }
+    }
+
+    fn into_v4(self) -> ContractV4 {
+        let series_metadata_refresh: LookupMap<TokenSeriesId, MetadataRefresh> =
+            LookupMap::new(StorageKey::SeriesMetadataRefresh);
+        ContractV4 { state: [self.state, series_metadata_refresh.try_to_vec().unwrap()].concat() }
+    }
+}
+
+/// `Contract` before `transferred_token_ids`, as stored.
+pub struct ContractV4 {
+    pub state: Vec<u8>,
+}
+
+impl ContractV4 {
+    fn read() -> Self {
+        Self { state: env::storage_read(STATE_KEY).expect("FireFly: Contract is not initialized") }
+    }
+
+    fn into_current(self) -> Contract {
+        let transferred_token_ids: LookupSet<TokenId> = LookupSet::new(StorageKey::TransferredTokenIds);
+        let state = [self.state, transferred_token_ids.try_to_vec().unwrap()].concat();
+        Contract::try_from_slice(&state)
+            .unwrap_or_else(|_| env::panic_str("FireFly: Cannot deserialize the contract state"))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Upgrade the stored state to the layout of the deployed code. Called by the contract
+    /// itself right after a deploy; running it on current state changes nothing.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let from_version = stored_state_version();
+        let contract = VersionedContract::read(from_version).into_current();
+        write_state_version();
+
+        env::log_str(
+            json!({
+                "type": "migrate",
+                "params": {
+                    "from_version": from_version,
+                    "to_version": STATE_VERSION,
+                }
+            }).to_string().as_str()
+        );
+        contract
+    }
+
+    /// Carry over up to `limit` more series or tokens of version 0 state, see
+    /// `ContractV0::into_current`. Returns whether the migration is done, minting, transfers
+    /// and approvals resume then. Owner only.
+    #[payable]
+    pub fn migrate_step(&mut self, limit: u64) -> bool {
+        assert_one_yocto();
+        self.assert_owner();
+        let mut backfill = V0Backfill::read().expect("FireFly: No migration in progress");
+
+        let mut done = false;
+        for _ in 0..limit {
+            if !backfill.copy_series(self) && !backfill.backfill_token(self) && !backfill.drop_last_series() {
+                done = true;
+                break;
+            }
+        }
+        if done {
+            env::storage_remove(V0_BACKFILL_KEY);
+            self.internal_unpause(PauseFlags { minting: true, transfers: true, approvals: true });
+        } else {
+            backfill.write();
+        }
+
+        env::log_str(
+            json!({
+                "type": "migrate_step",
+                "params": {
+                    "done": done,
+                }
+            }).to_string().as_str()
+        );
+        done
+    }
+
+    pub fn get_state_version(&self) -> u32 {
+        stored_state_version()
+    }
+}
+
+/// Panics while `migrate_step` still has version 0 state to carry over.
+pub(crate) fn assert_not_migrating() {
+    assert!(!env::storage_has_key(V0_BACKFILL_KEY), "FireFly: Migration in progress");
+}
+
+/// Version of the stored state, state written before versioning was introduced is version 0.
+fn stored_state_version() -> u32 {
+    env::storage_read(STATE_VERSION_KEY)
+        .map(|bytes| {
+            let bytes: [u8; 4] = bytes
+                .try_into()
+                .unwrap_or_else(|_| env::panic_str("FireFly: Corrupt state version"));
+            u32::from_le_bytes(bytes)
+        })
+        .unwrap_or(0)
+}
+
+pub(crate) fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &STATE_VERSION.to_le_bytes());
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::{U128, U64};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, IntoStorageKey};
+    use crate::approval::NonFungibleTokenApproval;
+    use crate::enumeration::NonFungibleTokenEnumeration;
+    use crate::metadata::NonFungibleTokenMetadataProvider;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn write_v0_state() {
+        let token_id = "1:1".to_string();
+        let mut tokens = NonFungibleTokenV0 {
+            owner_id: accounts(0),
+            extra_storage_in_bytes_per_token: 0,
+            owner_by_id: TreeMap::new(StorageKey::NonFungibleToken),
+            token_metadata_by_id: LookupMap::new(StorageKey::TokenMetadata),
+            tokens_per_owner: Some(LookupMap::new(StorageKey::Enumeration)),
+            approvals_by_id: Some(LookupMap::new(StorageKey::Approval)),
+            next_approval_id_by_id: Some(LookupMap::new(
+                [StorageKey::Approval.into_storage_key(), "n".into()].concat(),
+            )),
+        };
+        tokens.owner_by_id.insert(&token_id, &accounts(2));
+        tokens.token_metadata_by_id.insert(&token_id, &sample_token_metadata().into());
+        let mut owner_tokens = UnorderedSet::new(crate::nft_core::StorageKey::TokensPerOwner {
+            account_hash: env::sha256(accounts(2).as_bytes()),
+        });
+        owner_tokens.insert(&token_id);
+        tokens.tokens_per_owner.as_mut().unwrap().insert(&accounts(2), &owner_tokens);
+        tokens.approvals_by_id.as_mut().unwrap().insert(&token_id, &HashMap::from([(accounts(3), 1)]));
+        tokens.next_approval_id_by_id.as_mut().unwrap().insert(&token_id, &2);
+
+        let mut series_tokens = UnorderedSet::new(
+            StorageKey::TokensBySeriesInner { token_series: "1".to_string() }.try_to_vec().unwrap(),
+        );
+        series_tokens.insert(&token_id);
+        let mut contract = ContractV0 {
+            tokens,
+            metadata: LazyOption::new(
+                StorageKey::Metadata,
+                Some(&NFTContractMetadata {
+                    spec: crate::metadata::NFT_METADATA_SPEC.to_string(),
+                    name: "FireFly".to_string(),
+                    symbol: "FIREFLY".to_string(),
+                    icon: None,
+                    base_uri: None,
+                    reference: None,
+                    reference_hash: None,
+                }),
+            ),
+            token_series_by_id: UnorderedMap::new(StorageKey::TokenSeriesById),
+            market_data_transaction_fee: UnorderedMap::new(StorageKey::MarketDataTransactionFee),
+        };
+        contract.token_series_by_id.insert(
+            &"1".to_string(),
+            &TokenSeriesV0 {
+                metadata: sample_token_metadata(),
+                creator_id: accounts(1),
+                tokens: series_tokens,
+                price: Some(5),
+                is_mintable: true,
+            },
+        );
+        contract.market_data_transaction_fee.insert(&"1".to_string(), &500);
+        env::state_write(&contract);
+    }
+
+    /// Store `contract` with the layout of version 4.
+    fn write_v4_state(contract: &Contract) {
+        let state = contract.try_to_vec().unwrap();
+        let v4_len = state.len() - contract.transferred_token_ids.try_to_vec().unwrap().len();
+        env::storage_write(STATE_KEY, &state[..v4_len]);
+        env::storage_write(STATE_VERSION_KEY, &4u32.to_le_bytes());
+    }
+
+    /// Store `contract` with the layout of version 3.
+    fn write_v3_state(contract: &Contract) {
+        write_v4_state(contract);
+        let state = env::storage_read(STATE_KEY).unwrap();
+        let v3_len = state.len() - contract.series_metadata_refresh.try_to_vec().unwrap().len();
+        env::storage_write(STATE_KEY, &state[..v3_len]);
+        env::storage_write(STATE_VERSION_KEY, &3u32.to_le_bytes());
+    }
+
+    /// Store `contract` with the `tokens` layout of version 2.
+    fn write_v2_state(contract: Contract) {
+        write_v3_state(&contract);
+        let state = env::storage_read(STATE_KEY).unwrap();
+        let rest = state[contract.tokens.try_to_vec().unwrap().len()..].to_vec();
+        let NonFungibleToken {
+            core,
+            token_metadata_hash_by_id,
+            tokens_by_trait,
+            hidden_token_ids,
+            token_count_per_owner,
+            approvals_by_id,
+            next_approval_id_by_id,
+            operators_by_owner,
+            approval_expires_at,
+            token_id_format,
+        } = contract.tokens;
+        let tokens = NonFungibleTokenV2 {
+            owner_id: core.owner_id,
+            extra_storage_in_bytes_per_token: core.extra_storage_in_bytes_per_token,
+            owner_by_id: core.owner_by_id,
+            token_metadata_by_id: core.token_metadata_by_id.unwrap(),
+            token_metadata_hash_by_id,
+            tokens_by_trait,
+            tokens_per_owner: core.tokens_per_owner,
+            hidden_token_ids,
+            token_count_per_owner,
+            approvals_by_id,
+            next_approval_id_by_id,
+            operators_by_owner,
+            approval_expires_at,
+            token_id_format,
+        };
+        env::storage_write(STATE_KEY, &[tokens.try_to_vec().unwrap(), rest].concat());
+        env::storage_write(STATE_VERSION_KEY, &2u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id(accounts(0)).predecessor_account_id(accounts(0));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1));
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        env::state_write(&contract);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.tokens.core.owner_id, accounts(1));
+    }
+
+    #[test]
+    fn test_migrate_from_v0() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        write_v0_state();
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert!(contract.get_paused().transfers);
+        assert!(contract.nft_series(None, None).is_empty());
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        assert!(!contract.migrate_step(2));
+        assert_eq!(contract.nft_series(None, None).len(), 1);
+        assert!(contract.migrate_step(10));
+        assert_eq!(contract.get_paused(), PauseFlags::default());
+        assert_eq!(contract.nft_metadata().name, "FireFly".to_string());
+        let series = contract.nft_series(None, None);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].token_series_id, "1".to_string());
+        assert_eq!(series[0].creator_id, accounts(1));
+        assert_eq!(contract.get_market_data_transaction_fee("1".to_string()), U128(500));
+        assert_eq!(contract.nft_token("1:1".to_string()).unwrap().owner_id, accounts(2));
+        assert_eq!(contract.nft_supply_for_owner(accounts(2)), U128(1));
+        assert!(contract.nft_is_approved("1:1".to_string(), accounts(3), Some(1)));
+        assert!(contract.nft_metadata_hash("1:1".to_string()).is_some());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token = contract.nft_mint("1".to_string(), accounts(4));
+        assert_eq!(token.token_id, "1:2".to_string());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        assert_eq!(token_series.token_series_id, "2".to_string());
+    }
+
+    #[test]
+    fn test_migrate_from_v4() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(0));
+        write_v4_state(&contract);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.nft_set_token_royalty(token.token_id.clone(), Some(HashMap::from([(accounts(4), 2_000)])));
+        assert_eq!(contract.nft_token_royalty(token.token_id), HashMap::from([(accounts(4), 2_000)]));
+    }
+
+    #[test]
+    fn test_migrate_from_v3() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        write_v3_state(&contract);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.nft_series_metadata_refresh_remaining(token_series.token_series_id), U64(0));
+    }
+
+    #[test]
+    fn test_migrate_from_v2() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(170000000000000000000)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_approve(token.token_id.clone(), accounts(3), None, None);
+        let metadata_hash = contract.nft_metadata_hash(token.token_id.clone());
+        write_v2_state(contract);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.get_treasury(), accounts(0));
+        assert_eq!(contract.nft_token(token.token_id.clone()).unwrap().owner_id, accounts(2));
+        assert_eq!(contract.nft_supply_for_owner(accounts(2)), U128(1));
+        assert!(contract.nft_is_approved(token.token_id.clone(), accounts(3), Some(1)));
+        assert_eq!(contract.nft_metadata_hash(token.token_id), metadata_hash);
+    }
+}
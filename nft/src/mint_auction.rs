@@ -0,0 +1,222 @@
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::TokenSeriesId;
+use crate::token::Token;
+use crate::utils::refund_deposit;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance};
+use serde_json::json;
+
+/// A new bid must beat the highest one by at least 5%.
+pub const MIN_BID_INCREMENT_BPS: u128 = 500;
+
+/// English auction selling edition #1 of a 1/1 series before it is minted.
+/// Bids are escrowed on the contract, an outbid bidder is refunded right away.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintAuction {
+    pub reserve_price: U128,
+    pub ends_at: U64,
+    pub bidder_id: Option<AccountId>,
+    pub bid: U128,
+}
+
+impl MintAuction {
+    /// Smallest deposit `nft_bid` currently accepts.
+    pub fn min_bid(&self) -> Balance {
+        if self.bidder_id.is_none() {
+            self.reserve_price.0
+        } else {
+            self.bid.0 + self.bid.0 * MIN_BID_INCREMENT_BPS / 10_000
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Put edition #1 of a `copies = 1` series up for auction until `ends_at` (nanoseconds).
+    /// Creator only. Regular minting and buying are blocked while the auction runs.
+    #[payable]
+    pub fn nft_start_mint_auction(&mut self, token_series_id: TokenSeriesId, reserve_price: U128, ends_at: U64) {
+        let initial_storage_usage = env::storage_usage();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert_eq!(token_series.metadata.copies, Some(1), "FireFly: Only 1/1 series can be auctioned");
+        assert!(
+            token_series.is_mintable && token_series.tokens.is_empty(),
+            "FireFly: Token series already minted"
+        );
+        assert!(self.mint_auctions.get(&token_series_id).is_none(), "FireFly: Auction already exists");
+        assert!(reserve_price.0 > 0, "FireFly: reserve_price must be positive");
+        assert!(ends_at.0 > env::block_timestamp(), "FireFly: ends_at must be in the future");
+
+        let auction = MintAuction { reserve_price, ends_at, bidder_id: None, bid: U128(0) };
+        self.mint_auctions.insert(&token_series_id, &auction);
+
+        env::log_str(
+            json!({
+                "type": "nft_start_mint_auction",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "reserve_price": reserve_price,
+                    "ends_at": ends_at,
+                }
+            }).to_string().as_str()
+        );
+
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+    }
+
+    /// Bid the attached deposit. The previous highest bidder is refunded.
+    #[payable]
+    pub fn nft_bid(&mut self, token_series_id: TokenSeriesId) {
+        let mut auction = self.mint_auctions.get(&token_series_id).expect("FireFly: No auction for token series");
+        assert!(env::block_timestamp() < auction.ends_at.0, "FireFly: Auction has ended");
+        let bid = env::attached_deposit();
+        let min_bid = auction.min_bid();
+        assert!(bid >= min_bid, "FireFly: Bid must be at least {}", min_bid);
+
+        let bidder_id = env::predecessor_account_id();
+        self.assert_not_banned(&bidder_id);
+        if let Some(previous_bidder_id) = auction.bidder_id.replace(bidder_id.clone()) {
+            self.internal_payout(previous_bidder_id, auction.bid.0);
+        }
+        auction.bid = U128(bid);
+        self.mint_auctions.insert(&token_series_id, &auction);
+
+        env::log_str(
+            json!({
+                "type": "nft_bid",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "bidder_id": bidder_id,
+                    "bid": U128(bid),
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Close an auction that has ended. Mints edition #1 to the winner and adds the winning
+    /// bid, minus the token's storage, to the series earnings. Without bids the series is
+    /// released for regular minting. Callable by anyone.
+    pub fn nft_settle_mint_auction(&mut self, token_series_id: TokenSeriesId) -> Option<Token> {
+        let auction = self.mint_auctions.get(&token_series_id).expect("FireFly: No auction for token series");
+        assert!(env::block_timestamp() >= auction.ends_at.0, "FireFly: Auction is still running");
+        self.assert_minting_not_paused();
+        self.mint_auctions.remove(&token_series_id);
+
+        let token = auction.bidder_id.clone().map(|bidder_id| {
+            let initial_storage_usage = env::storage_usage();
+            let token = self.tokens.internal_mint(&mut self.token_series_by_id, token_series_id.clone(), bidder_id, None);
+            let storage_cost = env::storage_byte_cost()
+                * Balance::from(env::storage_usage().saturating_sub(initial_storage_usage));
+            let proceeds = self.internal_take_fees(auction.bid.0, 0);
+            self.internal_record_series_sale(&token_series_id, auction.bid.0, proceeds.saturating_sub(storage_cost));
+            token
+        });
+
+        env::log_str(
+            json!({
+                "type": "nft_settle_mint_auction",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "token_id": token.as_ref().map(|token| token.token_id.clone()),
+                    "owner_id": token.as_ref().map(|token| token.owner_id.clone()),
+                    "price": auction.bid,
+                }
+            }).to_string().as_str()
+        );
+
+        token
+    }
+
+    pub fn nft_mint_auction(&self, token_series_id: TokenSeriesId) -> Option<MintAuction> {
+        self.mint_auctions.get(&token_series_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_not_auctioned(&self, token_series_id: &TokenSeriesId) {
+        assert!(
+            self.mint_auctions.get(token_series_id).is_none(),
+            "FireFly: Token series is being auctioned"
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::TokenMetadata;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    const ONE_NEAR: u128 = 10u128.pow(24);
+
+    fn start_auction(context: &mut VMContextBuilder, contract: &mut Contract) -> TokenSeriesId {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(0)
+            .build());
+        let token_series_id = contract.nft_create_series(TokenMetadata { copies: Some(1), ..sample_token_metadata() }, None, None).token_series_id;
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(MINT_STORAGE_COST).build());
+        contract.nft_start_mint_auction(token_series_id.clone(), U128(ONE_NEAR), U64(100));
+        token_series_id
+    }
+
+    #[test]
+    fn test_mint_auction() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token_series_id = start_auction(&mut context, &mut contract);
+
+        testing_env!(context.attached_deposit(ONE_NEAR).predecessor_account_id(accounts(2)).block_timestamp(10).build());
+        contract.nft_bid(token_series_id.clone());
+        testing_env!(context.attached_deposit(2 * ONE_NEAR).predecessor_account_id(accounts(3)).build());
+        contract.nft_bid(token_series_id.clone());
+        let auction = contract.nft_mint_auction(token_series_id.clone()).unwrap();
+        assert_eq!(auction.bidder_id, Some(accounts(3)));
+        assert_eq!(auction.min_bid(), 2 * ONE_NEAR + ONE_NEAR / 10);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(0).block_timestamp(100).build());
+        let token = contract.nft_settle_mint_auction(token_series_id.clone()).unwrap();
+        assert_eq!(token.owner_id, accounts(3));
+        assert!(contract.nft_mint_auction(token_series_id).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Bid must be at least")]
+    fn test_bid_below_increment() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token_series_id = start_auction(&mut context, &mut contract);
+
+        testing_env!(context.attached_deposit(ONE_NEAR).predecessor_account_id(accounts(2)).block_timestamp(10).build());
+        contract.nft_bid(token_series_id.clone());
+        testing_env!(context.attached_deposit(ONE_NEAR + 1).predecessor_account_id(accounts(3)).build());
+        contract.nft_bid(token_series_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token series is being auctioned")]
+    fn test_mint_during_auction() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token_series_id = start_auction(&mut context, &mut contract);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(MINT_STORAGE_COST).build());
+        contract.nft_mint(token_series_id, accounts(1));
+    }
+}
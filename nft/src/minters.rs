@@ -0,0 +1,106 @@
+//! Minters, e.g. a backend hot wallet, can mint any series through `nft_mint` and
+//! `nft_mint_batch` without holding an owner key. They are added and removed through
+//! `AdminAction::AddMinter` and `AdminAction::RemoveMinter`.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::TokenSeries;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId};
+
+#[near_bindgen]
+impl Contract {
+    pub fn is_minter(&self, account_id: AccountId) -> bool {
+        self.minters.contains(&account_id)
+    }
+
+    pub fn get_minters(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<AccountId> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        self.minters
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Free mints of a series are reserved to its creator and the minters.
+    pub(crate) fn assert_can_mint(&self, token_series: &TokenSeries) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            account_id == token_series.creator_id || self.minters.contains(&account_id),
+            "FireFly: Creator or minter only"
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::admin::{AdminAction, DEFAULT_ADMIN_DELAY};
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    fn test_minter_mints() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(0)).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::AddMinter { account_id: accounts(2) });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_minters(None, None), vec![accounts(2)]);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = contract.nft_mint(token_series.token_series_id, accounts(3));
+        assert_eq!(token.owner_id, accounts(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Creator or minter only")]
+    fn test_removed_minter_cannot_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(0)).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::AddMinter { account_id: accounts(2) });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        let action_id = contract.propose_admin_action(AdminAction::RemoveMinter { account_id: accounts(2) });
+        testing_env!(context.attached_deposit(1).block_timestamp(2 * DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert!(!contract.is_minter(accounts(2)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_mint(token_series.token_series_id, accounts(3));
+    }
+}
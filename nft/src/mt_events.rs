@@ -0,0 +1,126 @@
+//! Standard for nep245 (Multi Token) events, used by multi-token series.
+//!
+//! <https://github.com/near/NEPs/blob/master/neps/nep-0245.md>
+//!
+//! This is an extension of the events format (nep-297):
+//! <https://github.com/near/NEPs/blob/master/specs/Standards/EventsFormat.md>
+
+use crate::event::NearEvent;
+use near_sdk::json_types::U128;
+use near_sdk::AccountId;
+use serde::Serialize;
+
+/// Data to log for a multi-token mint event. To log this event, call [`.emit()`](MtMint::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct MtMint<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: &'a [&'a str],
+    pub amounts: &'a [U128],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MtMint<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[MtMint<'_>]) {
+        new_245_v1(Nep245EventKind::MtMint(data)).emit()
+    }
+}
+
+/// Data to log for a multi-token transfer event. To log this event,
+/// call [`.emit()`](MtTransfer::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct MtTransfer<'a> {
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+    pub token_ids: &'a [&'a str],
+    pub amounts: &'a [U128],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorized_id: Option<&'a AccountId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MtTransfer<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[MtTransfer<'_>]) {
+        new_245_v1(Nep245EventKind::MtTransfer(data)).emit()
+    }
+}
+
+/// Data to log for a multi-token burn event. To log this event, call [`.emit()`](MtBurn::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct MtBurn<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: &'a [&'a str],
+    pub amounts: &'a [U128],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorized_id: Option<&'a AccountId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MtBurn<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[MtBurn<'_>]) {
+        new_245_v1(Nep245EventKind::MtBurn(data)).emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Nep245Event<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: Nep245EventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+enum Nep245EventKind<'a> {
+    MtMint(&'a [MtMint<'a>]),
+    MtTransfer(&'a [MtTransfer<'a>]),
+    MtBurn(&'a [MtBurn<'a>]),
+}
+
+fn new_245_v1(event_kind: Nep245EventKind) -> NearEvent {
+    NearEvent::Nep245(Nep245Event { version: "1.0.0", event_kind })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils;
+
+    #[test]
+    fn mt_transfer() {
+        let old_owner_id = &AccountId::new_unchecked("bob".to_string());
+        let new_owner_id = &AccountId::new_unchecked("alice".to_string());
+        MtTransfer {
+            old_owner_id,
+            new_owner_id,
+            token_ids: &["1"],
+            amounts: &[U128(5)],
+            authorized_id: None,
+            memo: None,
+        }
+        .emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"nep245","version":"1.0.0","event":"mt_transfer","data":[{"old_owner_id":"bob","new_owner_id":"alice","token_ids":["1"],"amounts":["5"]}]}"#
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! Multi-token mode for open editions. A multi-token series tracks how many editions each
+//! account holds, NEP-245 style, instead of minting one unique token per copy, so a
+//! 10k-copy drop costs one balance entry per holder. The series id doubles as the
+//! multi-token `token_id`.
+
+use crate::{Contract, StorageKey};
+use crate::ContractExt;
+use crate::metadata::TokenMetadata;
+use crate::events::NftMint;
+use crate::mt_events::{MtBurn, MtMint, MtTransfer};
+use crate::series::{assert_valid_price, SeriesOptions, TokenSeriesId, TokenSeriesJson};
+use crate::token::Token;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance};
+
+#[near_bindgen]
+impl Contract {
+    /// Create a series whose editions are minted as multi-token balances.
+    #[payable]
+    pub fn mt_create_series(&mut self, token_metadata: TokenMetadata, price: Option<U128>) -> TokenSeriesJson {
+        let initial_storage_usage = env::storage_usage();
+        assert!(token_metadata.title.is_some(), "FireFly: token_metadata.title is required");
+
+        let price_res = assert_valid_price(price, self.max_price);
+        let token_series = self.internal_create_series(token_metadata, price_res, SeriesOptions::default());
+        let mut series = self.token_series_by_id.get(&token_series.token_series_id).unwrap();
+        series.is_multi_token = true;
+        self.token_series_by_id.insert(&token_series.token_series_id, &series);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+
+        token_series
+    }
+
+    /// Mint `amount` editions of a multi-token series to `receiver_id`, creator only.
+    #[payable]
+    pub fn mt_mint(&mut self, token_series_id: TokenSeriesId, receiver_id: AccountId, amount: U128) {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_minting_not_paused();
+        self.assert_not_banned(&receiver_id);
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert!(token_series.is_multi_token, "FireFly: Token series is not multi-token");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(token_series.is_mintable, "FireFly: Token series is not mintable");
+        token_series.assert_in_mint_window();
+        assert!(amount.0 > 0, "FireFly: amount must be positive");
+
+        let supply = self.mt_supply.get(&token_series_id).unwrap_or(0) + amount.0;
+        let max_copies = token_series.metadata.copies.map(Balance::from).unwrap_or(Balance::MAX);
+        assert!(supply <= max_copies, "FireFly: Series supply maxed");
+        if supply == max_copies {
+            token_series.is_mintable = false;
+            token_series.price = None;
+            self.token_series_by_id.insert(&token_series_id, &token_series);
+        }
+        self.mt_supply.insert(&token_series_id, &supply);
+        self.internal_mt_deposit(&token_series_id, &receiver_id, amount.0);
+
+        MtMint {
+            owner_id: &receiver_id,
+            token_ids: &[&token_series_id],
+            amounts: &[amount],
+            memo: None,
+        }
+        .emit();
+
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+    }
+
+    /// Move `amount` editions to `receiver_id`. Approvals are not supported for multi-token
+    /// balances, `approval` must be `None`.
+    #[payable]
+    pub fn mt_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenSeriesId,
+        amount: U128,
+        approval: Option<(AccountId, u64)>,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        self.assert_transfers_not_paused();
+        assert!(approval.is_none(), "FireFly: Multi-token approvals are not supported");
+        self.assert_receiver_allowed(&receiver_id);
+        let sender_id = env::predecessor_account_id();
+        self.assert_not_banned(&sender_id);
+        self.assert_not_banned(&receiver_id);
+        assert_ne!(sender_id, receiver_id, "FireFly: Sender and receiver must differ");
+        assert!(amount.0 > 0, "FireFly: amount must be positive");
+
+        self.internal_mt_withdraw(&token_id, &sender_id, amount.0);
+        self.internal_mt_deposit(&token_id, &receiver_id, amount.0);
+
+        MtTransfer {
+            old_owner_id: &sender_id,
+            new_owner_id: &receiver_id,
+            token_ids: &[&token_id],
+            amounts: &[amount],
+            authorized_id: None,
+            memo: memo.as_deref(),
+        }
+        .emit();
+    }
+
+    /// Turn one edition of the caller's multi-token balance into a unique token carrying the
+    /// next edition number. The caller pays for the token's storage.
+    #[payable]
+    pub fn nft_unpack(&mut self, token_series_id: TokenSeriesId) -> Token {
+        assert_at_least_one_yocto();
+        self.assert_minting_not_paused();
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+        self.assert_not_banned(&owner_id);
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert!(token_series.is_multi_token, "FireFly: Token series is not multi-token");
+
+        self.internal_mt_withdraw(&token_series_id, &owner_id, 1);
+        MtBurn {
+            owner_id: &owner_id,
+            token_ids: &[&token_series_id],
+            amounts: &[U128(1)],
+            authorized_id: None,
+            memo: Some("unpack"),
+        }
+        .emit();
+
+        let edition = token_series.tokens.len() + token_series.burned + 1;
+        let token_id = self.tokens.token_id_format.token_id(&token_series_id, edition);
+        token_series.tokens.insert(&token_id);
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+        let token = self.tokens.internal_mint_with_refund(token_id, owner_id, token_series.metadata, None);
+        NftMint { owner_id: &token.owner_id, token_ids: &[&token.token_id], memo: Some("unpack") }.emit();
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        token
+    }
+
+    pub fn mt_balance_of(&self, account_id: AccountId, token_id: TokenSeriesId) -> U128 {
+        U128(self.mt_balances.get(&(token_id, account_id)).unwrap_or(0))
+    }
+
+    pub fn mt_batch_balance_of(&self, account_id: AccountId, token_ids: Vec<TokenSeriesId>) -> Vec<U128> {
+        token_ids
+            .into_iter()
+            .map(|token_id| self.mt_balance_of(account_id.clone(), token_id))
+            .collect()
+    }
+
+    pub fn mt_supply(&self, token_id: TokenSeriesId) -> Option<U128> {
+        self.mt_supply.get(&token_id).map(U128)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_mt_deposit(&mut self, token_id: &TokenSeriesId, account_id: &AccountId, amount: Balance) {
+        let key = (token_id.clone(), account_id.clone());
+        let balance = self.mt_balances.get(&key).unwrap_or(0);
+        self.mt_balances.insert(&key, &(balance + amount));
+    }
+
+    /// Empty balances are removed, which frees the storage the receiver's entry took.
+    pub(crate) fn internal_mt_withdraw(&mut self, token_id: &TokenSeriesId, account_id: &AccountId, amount: Balance) {
+        let key = (token_id.clone(), account_id.clone());
+        let balance = self.mt_balances.get(&key).unwrap_or(0);
+        assert!(balance >= amount, "FireFly: Not enough balance");
+        if balance == amount {
+            self.mt_balances.remove(&key);
+        } else {
+            self.mt_balances.insert(&key, &(balance - amount));
+        }
+    }
+}
+
+pub(crate) fn new_mt_balances() -> LookupMap<(TokenSeriesId, AccountId), Balance> {
+    LookupMap::new(StorageKey::MultiTokenBalances)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    fn test_mt_mint_and_transfer() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.mt_create_series(TokenMetadata { copies: Some(10_000), ..sample_token_metadata() }, None);
+        let token_id = token_series.token_series_id;
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.mt_mint(token_id.clone(), accounts(2), U128(10_000));
+        assert_eq!(contract.mt_supply(token_id.clone()), Some(U128(10_000)));
+        assert!(!contract.token_series_by_id.get(&token_id).unwrap().is_mintable);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.mt_transfer(accounts(3), token_id.clone(), U128(4_000), None, None);
+        assert_eq!(
+            contract.mt_batch_balance_of(accounts(2), vec![token_id.clone()]),
+            vec![U128(6_000)]
+        );
+        assert_eq!(contract.mt_balance_of(accounts(3), token_id), U128(4_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token series is multi-token")]
+    fn test_nft_mint_multi_token_series() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.mt_create_series(TokenMetadata { copies: Some(10_000), ..sample_token_metadata() }, None);
+        contract.nft_mint(token_series.token_series_id, accounts(2));
+    }
+
+    #[test]
+    fn test_unpack() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.mt_create_series(TokenMetadata { copies: Some(10_000), ..sample_token_metadata() }, None);
+        let token_id = token_series.token_series_id;
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.mt_mint(token_id.clone(), accounts(2), U128(2));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = contract.nft_unpack(token_id.clone());
+        assert_eq!(token.token_id, format!("{}:1", token_id));
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(contract.mt_balance_of(accounts(2), token_id.clone()), U128(1));
+        assert_eq!(contract.mt_supply(token_id), Some(U128(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Not enough balance")]
+    fn test_unpack_without_balance() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.mt_create_series(TokenMetadata { copies: Some(10_000), ..sample_token_metadata() }, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_unpack(token_series.token_series_id);
+    }
+}
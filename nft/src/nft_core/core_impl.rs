@@ -1,22 +1,121 @@
 use super::resolver::NonFungibleTokenResolver;
+use crate::approval::Approvals;
 use crate::nft_core::receiver::ext_nft_receiver;
 use crate::nft_core::resolver::ext_nft_resolver;
 use crate::nft_core::NonFungibleTokenCore;
 use crate::events::{NftTransfer};
+use crate::generative::{attributes_of, extra_with_attributes, roll_attributes, TokenAttribute};
+use serde_json::json;
 use crate::metadata::TokenMetadata;
 use crate::series::{TokenSeriesId, TokenSeries};
 use crate::token::{Token, TokenId};
 use crate::utils::{refund_approved_account_ids, refund_deposit_to_account};
+use near_contract_standards::non_fungible_token::NonFungibleToken as StandardNonFungibleToken;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, TreeMap, UnorderedSet, UnorderedMap};
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedSet};
 use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    assert_one_yocto, env, require, AccountId, BorshStorageKey, Gas, IntoStorageKey,
-    PromiseOrValue, PromiseResult, StorageUsage,
+    assert_one_yocto, env, require, AccountId, Balance, BorshStorageKey, Gas, IntoStorageKey,
+    Promise, PromiseOrValue, PromiseResult,
 };
 use std::collections::HashMap;
 
 pub const TOKEN_DELIMETER: char = ':';
+/// Widest edition number padding, enough for any `u64`.
+pub const MAX_EDITION_PADDING: u8 = 20;
+
+/// How token ids are built from the series id and the edition, set when the contract is
+/// initialized. Ids are `"{token_series_id}{delimiter}{edition}"`, with the edition number
+/// zero-padded to `edition_padding` digits, if set, so ids sort like their editions.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenIdFormat {
+    /// A single character
+    pub delimiter: String,
+    pub edition_padding: Option<u8>,
+}
+
+impl Default for TokenIdFormat {
+    fn default() -> Self {
+        TokenIdFormat { delimiter: TOKEN_DELIMETER.to_string(), edition_padding: None }
+    }
+}
+
+impl TokenIdFormat {
+    pub fn assert_valid(&self) {
+        // edition names are made of a-z, 0-9, `-` and `_`, the delimiter must stand out from them
+        let mut chars = self.delimiter.chars();
+        assert!(
+            matches!((chars.next(), chars.next()), (Some(c), None) if c.is_ascii_punctuation() && c != '-' && c != '_'),
+            "FireFly: Token id delimiter must be one punctuation character other than - and _"
+        );
+        assert!(
+            self.edition_padding.unwrap_or(0) <= MAX_EDITION_PADDING,
+            "FireFly: Edition padding exceeds {}",
+            MAX_EDITION_PADDING
+        );
+    }
+
+    pub fn token_id(&self, token_series_id: &str, edition: u64) -> TokenId {
+        let width = usize::from(self.edition_padding.unwrap_or(0));
+        format!("{}{}{:0width$}", token_series_id, self.delimiter, edition, width = width)
+    }
+
+    pub fn named_token_id(&self, token_series_id: &str, edition_name: &str) -> TokenId {
+        format!("{}{}{}", token_series_id, self.delimiter, edition_name)
+    }
+
+    /// The series a token was minted from.
+    pub fn token_series_id_of<'a>(&self, token_id: &'a str) -> &'a str {
+        token_id.split(self.delimiter.as_str()).next().unwrap_or(token_id)
+    }
+
+    /// The edition number of a token, `None` for named editions.
+    pub fn edition_of(&self, token_id: &str) -> Option<u64> {
+        token_id.rsplit(self.delimiter.as_str()).next().and_then(|edition| edition.parse::<u64>().ok())
+    }
+}
+
+/// Longest name `nft_mint_named` accepts for an edition.
+pub const MAX_EDITION_NAME_LEN: usize = 32;
+
+/// Edition names are lowercase letters, digits, `-` and `_`, with at least one character that
+/// isn't a digit so they can't collide with numbered editions.
+fn assert_valid_edition_name(edition_name: &str) {
+    assert!(
+        !edition_name.is_empty() && edition_name.len() <= MAX_EDITION_NAME_LEN,
+        "FireFly: Edition name must be between 1 and {} characters",
+        MAX_EDITION_NAME_LEN
+    );
+    assert!(
+        edition_name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'),
+        "FireFly: Edition name may only contain a-z, 0-9, - and _"
+    );
+    assert!(
+        !edition_name.chars().all(|c| c.is_ascii_digit()),
+        "FireFly: Edition name cannot be a number"
+    );
+}
+
+/// Take an edition out of the `remaining` editions left in `pool`, at random. The pool is a
+/// Fisher-Yates shuffle of editions `1..=copies` that only stores the positions swapped so far.
+fn draw_edition(pool: &mut LookupMap<u64, u64>, remaining: u64, nonce: u64) -> u64 {
+    let seed = env::sha256(&[env::random_seed(), nonce.to_le_bytes().to_vec()].concat());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&seed[..8]);
+    let position = u64::from_le_bytes(bytes) % remaining;
+    let last = remaining - 1;
+    let edition = pool.get(&position).unwrap_or(position + 1);
+    if position != last {
+        pool.insert(&position, &pool.get(&last).unwrap_or(last + 1));
+    }
+    pool.remove(&last);
+    edition
+}
+
 const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
 const GAS_FOR_NFT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER.0);
 
@@ -28,32 +127,41 @@ const GAS_FOR_NFT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_
 ///     - NonFungibleTokenEnumeration -- interface for getting lists of tokens. NonFungibleToken provides methods for it.
 ///     - NonFungibleTokenMetadata -- return metadata for the token in NEP-177, up to contract to implement.
 ///
-/// For example usage, see examples/non-fungible-token/src/lib.rs.
+/// Owners, token metadata and the tokens of each owner are kept by `core`, the standard
+/// implementation with its approval extension left out. The other maps sit alongside it.
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct NonFungibleToken {
-    // owner of contract
-    pub owner_id: AccountId,
-
-    // The storage size in bytes for each new token
-    pub extra_storage_in_bytes_per_token: StorageUsage,
-
-    // always required
-    pub owner_by_id: TreeMap<TokenId, AccountId>,
-
-    // required by metadata extension
-    pub token_metadata_by_id: LookupMap<TokenId, TokenMetadata>,
-
-    // required by enumeration extension
-    pub tokens_per_owner: Option<LookupMap<AccountId, UnorderedSet<TokenId>>>,
-
-    // required by approval extension
-    pub approvals_by_id: Option<LookupMap<TokenId, HashMap<AccountId, u64>>>,
+    // owner of contract, `owner_by_id`, `token_metadata_by_id` and `tokens_per_owner`
+    pub core: StandardNonFungibleToken,
+
+    // hash committed whenever a token's metadata is written, see `TokenMetadata::canonical_hash`
+    pub token_metadata_hash_by_id: LookupMap<TokenId, Vec<u8>>,
+    // tokens by series, trait type and value of the `attributes` in their metadata `extra`
+    pub tokens_by_trait: LookupMap<(TokenSeriesId, String, String), UnorderedSet<TokenId>>,
+
+    // tokens their owner chose to hide from enumeration views
+    pub hidden_token_ids: Option<LookupSet<TokenId>>,
+    // number of tokens in `tokens_per_owner`, so the supply of an owner is read without
+    // loading their token set
+    pub token_count_per_owner: Option<LookupMap<AccountId, u64>>,
+
+    // approval extension, kept here rather than in `core` so approvals are stored as
+    // `Approvals` instead of a `HashMap`
+    pub approvals_by_id: Option<LookupMap<TokenId, Approvals>>,
     pub next_approval_id_by_id: Option<LookupMap<TokenId, u64>>,
+    // accounts approved for every token of an owner, see `approval::operator`
+    pub operators_by_owner: Option<LookupMap<AccountId, Vec<AccountId>>>,
+    // expiry timestamp of an approval, by token and approval id. Approval ids are never reused,
+    // so an entry left behind by a transfer can't apply to a later approval
+    pub approval_expires_at: Option<LookupMap<(TokenId, u64), u64>>,
+
+    pub token_id_format: TokenIdFormat,
 }
 
 #[derive(BorshStorageKey, BorshSerialize)]
 pub enum StorageKey {
     TokensPerOwner { account_hash: Vec<u8> },
+    TokensByTrait { trait_hash: Vec<u8> },
 }
 
 impl NonFungibleToken {
@@ -63,6 +171,7 @@ impl NonFungibleToken {
         token_metadata_prefix: R,
         enumeration_prefix: Option<S>,
         approval_prefix: Option<T>,
+        token_id_format: TokenIdFormat,
     ) -> Self
     where
         Q: IntoStorageKey,
@@ -70,40 +179,86 @@ impl NonFungibleToken {
         S: IntoStorageKey,
         T: IntoStorageKey,
     {
-        let (approvals_by_id, next_approval_id_by_id) = if let Some(prefix) = approval_prefix {
+        let token_metadata_prefix: Vec<u8> = token_metadata_prefix.into_storage_key();
+        let enumeration_prefix: Option<Vec<u8>> = enumeration_prefix.map(IntoStorageKey::into_storage_key);
+        let core = StandardNonFungibleToken::new(
+            owner_by_id_prefix,
+            owner_id,
+            Some(token_metadata_prefix.clone()),
+            enumeration_prefix.clone(),
+            None::<Vec<u8>>,
+        );
+        let mut this =
+            Self::with_core(core, token_metadata_prefix, enumeration_prefix, approval_prefix, token_id_format);
+        // `core` only measured its own maps
+        this.measure_min_token_storage_cost();
+        this
+    }
+
+    /// Put the maps kept alongside `core` under the prefixes `new` gives them, without
+    /// measuring the storage of a token, which would clobber a `core` already in use.
+    pub(crate) fn with_core<R, S, T>(
+        core: StandardNonFungibleToken,
+        token_metadata_prefix: R,
+        enumeration_prefix: Option<S>,
+        approval_prefix: Option<T>,
+        token_id_format: TokenIdFormat,
+    ) -> Self
+    where
+        R: IntoStorageKey,
+        S: IntoStorageKey,
+        T: IntoStorageKey,
+    {
+        let (approvals_by_id, next_approval_id_by_id, operators_by_owner, approval_expires_at) =
+            if let Some(prefix) = approval_prefix {
+                let prefix: Vec<u8> = prefix.into_storage_key();
+                (
+                    Some(LookupMap::new(prefix.clone())),
+                    Some(LookupMap::new([prefix.clone(), "n".into()].concat())),
+                    Some(LookupMap::new([prefix.clone(), "o".into()].concat())),
+                    Some(LookupMap::new([prefix, "e".into()].concat())),
+                )
+            } else {
+                (None, None, None, None)
+            };
+
+        let (hidden_token_ids, token_count_per_owner) = if let Some(prefix) = enumeration_prefix {
             let prefix: Vec<u8> = prefix.into_storage_key();
             (
-                Some(LookupMap::new(prefix.clone())),
-                Some(LookupMap::new([prefix, "n".into()].concat())),
+                Some(LookupSet::new([prefix.clone(), "h".into()].concat())),
+                Some(LookupMap::new([prefix, "c".into()].concat())),
             )
         } else {
             (None, None)
         };
 
-        let mut this = Self {
-            owner_id,
-            extra_storage_in_bytes_per_token: 0,
-            owner_by_id: TreeMap::new(owner_by_id_prefix),
-            token_metadata_by_id: LookupMap::new(token_metadata_prefix),
-            tokens_per_owner: enumeration_prefix.map(LookupMap::new),
+        let token_metadata_prefix: Vec<u8> = token_metadata_prefix.into_storage_key();
+
+        Self {
+            core,
+            token_metadata_hash_by_id: LookupMap::new([token_metadata_prefix.clone(), "h".into()].concat()),
+            tokens_by_trait: LookupMap::new([token_metadata_prefix, "t".into()].concat()),
+            hidden_token_ids,
+            token_count_per_owner,
             approvals_by_id,
             next_approval_id_by_id,
-        };
-        this.measure_min_token_storage_cost();
-        this
+            operators_by_owner,
+            approval_expires_at,
+            token_id_format,
+        }
     }
 
     // TODO: does this seem reasonable?
-    fn measure_min_token_storage_cost(&mut self) {
+    pub(crate) fn measure_min_token_storage_cost(&mut self) {
         let initial_storage_usage = env::storage_usage();
         // 64 Length because this is the max account id length
         let tmp_token_id = "a".repeat(64);
         let tmp_owner_id = AccountId::new_unchecked("a".repeat(64));
 
         // 1. set some dummy data
-        self.owner_by_id.insert(&tmp_token_id, &tmp_owner_id);
+        self.core.owner_by_id.insert(&tmp_token_id, &tmp_owner_id);
 
-        self.token_metadata_by_id.insert(
+        self.internal_set_token_metadata(
             &tmp_token_id,
             &TokenMetadata {
                 title: Some("a".repeat(64)),
@@ -121,15 +276,16 @@ impl NonFungibleToken {
             },
         );
 
-        if let Some(tokens_per_owner) = &mut self.tokens_per_owner {
+        if let Some(tokens_per_owner) = &mut self.core.tokens_per_owner {
             let u = &mut UnorderedSet::new(StorageKey::TokensPerOwner {
                 account_hash: env::sha256(tmp_owner_id.as_bytes()),
             });
             u.insert(&tmp_token_id);
             tokens_per_owner.insert(&tmp_owner_id, u);
         }
+        self.internal_change_owner_count(&tmp_owner_id, true);
         if let Some(approvals_by_id) = &mut self.approvals_by_id {
-            let mut approvals = HashMap::new();
+            let mut approvals = Approvals::default();
             approvals.insert(tmp_owner_id.clone(), 1u64);
             approvals_by_id.insert(&tmp_token_id, &approvals);
         }
@@ -138,7 +294,7 @@ impl NonFungibleToken {
         }
 
         // 2. see how much space it took
-        self.extra_storage_in_bytes_per_token = env::storage_usage() - initial_storage_usage;
+        self.core.extra_storage_in_bytes_per_token = env::storage_usage() - initial_storage_usage;
 
         // 3. roll it all back
         if let Some(next_approval_id_by_id) = &mut self.next_approval_id_by_id {
@@ -147,16 +303,88 @@ impl NonFungibleToken {
         if let Some(approvals_by_id) = &mut self.approvals_by_id {
             approvals_by_id.remove(&tmp_token_id);
         }
-        if let Some(tokens_per_owner) = &mut self.tokens_per_owner {
+        if let Some(tokens_per_owner) = &mut self.core.tokens_per_owner {
             let mut u = tokens_per_owner.remove(&tmp_owner_id).unwrap();
             u.remove(&tmp_token_id);
         }
-        self.token_metadata_by_id.remove(&tmp_token_id);
+        self.internal_change_owner_count(&tmp_owner_id, false);
+        self.token_metadata_hash_by_id.remove(&tmp_token_id);
+        if let Some(token_metadata_by_id) = &mut self.core.token_metadata_by_id {
+            token_metadata_by_id.remove(&tmp_token_id);
+        }
+
+        self.core.owner_by_id.remove(&tmp_token_id);
+    }
 
-        self.owner_by_id.remove(&tmp_token_id);
+    /// The metadata of `token_id`, `None` if no such token.
+    pub fn token_metadata(&self, token_id: &TokenId) -> Option<TokenMetadata> {
+        self.core.token_metadata_by_id.as_ref()?.get(token_id).map(TokenMetadata::from)
     }
 
-    /// Transfer token_id from `from` to `to`
+    /// Store the metadata of a token together with its hash commitment, and index its
+    /// attributes.
+    pub fn internal_set_token_metadata(
+        &mut self,
+        #[allow(clippy::ptr_arg)] token_id: &TokenId,
+        token_metadata: &TokenMetadata,
+    ) {
+        let old_metadata = self
+            .core
+            .token_metadata_by_id
+            .as_mut()
+            .and_then(|by_id| by_id.insert(token_id, &token_metadata.clone().into()))
+            .map(TokenMetadata::from);
+        self.internal_commit_token_metadata(token_id, old_metadata, token_metadata);
+    }
+
+    /// Commit the hash of `token_metadata`, just stored for `token_id` in place of
+    /// `old_metadata`, and index its attributes.
+    fn internal_commit_token_metadata(
+        &mut self,
+        #[allow(clippy::ptr_arg)] token_id: &TokenId,
+        old_metadata: Option<TokenMetadata>,
+        token_metadata: &TokenMetadata,
+    ) {
+        self.token_metadata_hash_by_id.insert(token_id, &token_metadata.canonical_hash());
+
+        let old_attributes = old_metadata.and_then(|metadata| attributes_of(metadata.extra.as_deref()));
+        let attributes = attributes_of(token_metadata.extra.as_deref());
+        if old_attributes != attributes {
+            self.internal_index_attributes(token_id, &old_attributes.unwrap_or_default(), false);
+            self.internal_index_attributes(token_id, &attributes.unwrap_or_default(), true);
+        }
+    }
+
+    /// Add `token_id` to, or remove it from, the `tokens_by_trait` entries of `attributes`.
+    fn internal_index_attributes(
+        &mut self,
+        #[allow(clippy::ptr_arg)] token_id: &TokenId,
+        attributes: &[TokenAttribute],
+        insert: bool,
+    ) {
+        let token_series_id = self.token_id_format.token_series_id_of(token_id).to_string();
+        for attribute in attributes.iter() {
+            let key = (token_series_id.clone(), attribute.trait_type.clone(), attribute.value.clone());
+            let mut token_ids = self.tokens_by_trait.get(&key).unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::TokensByTrait {
+                    trait_hash: env::sha256(&key.try_to_vec().unwrap()),
+                })
+            });
+            if insert {
+                token_ids.insert(token_id);
+            } else {
+                token_ids.remove(token_id);
+            }
+            if token_ids.is_empty() {
+                self.tokens_by_trait.remove(&key);
+            } else {
+                self.tokens_by_trait.insert(&key, &token_ids);
+            }
+        }
+    }
+
+    /// Transfer token_id from `from` to `to`, keeping the owner counts and hidden tokens in
+    /// step with `core`
     ///
     /// Do not perform any safety checks or do any logging
     pub fn internal_transfer_unguarded(
@@ -165,29 +393,127 @@ impl NonFungibleToken {
         from: &AccountId,
         to: &AccountId,
     ) {
-        // update owner
-        self.owner_by_id.insert(token_id, to);
-
-        // if using Enumeration standard, update old & new owner's token lists
-        if let Some(tokens_per_owner) = &mut self.tokens_per_owner {
-            // owner_tokens should always exist, so call `unwrap` without guard
-            let mut owner_tokens = tokens_per_owner.get(from).unwrap_or_else(|| {
-                env::panic_str("Unable to access tokens per owner in unguarded call.")
-            });
-            owner_tokens.remove(token_id);
-            if owner_tokens.is_empty() {
-                tokens_per_owner.remove(from);
+        self.core.internal_transfer_unguarded(token_id, from, to);
+        self.internal_change_owner_count(from, false);
+        self.internal_change_owner_count(to, true);
+
+        // a hidden token shows up again for its new owner
+        if let Some(hidden_token_ids) = &mut self.hidden_token_ids {
+            hidden_token_ids.remove(token_id);
+        }
+    }
+
+    /// Count one token more or less for `owner_id`, forgetting owners left without tokens
+    pub(crate) fn internal_change_owner_count(&mut self, owner_id: &AccountId, increment: bool) {
+        if let Some(token_count_per_owner) = &mut self.token_count_per_owner {
+            let count = token_count_per_owner.get(owner_id).unwrap_or(0);
+            let count = if increment { count + 1 } else { count.saturating_sub(1) };
+            if count == 0 {
+                token_count_per_owner.remove(owner_id);
             } else {
-                tokens_per_owner.insert(from, &owner_tokens);
+                token_count_per_owner.insert(owner_id, &count);
             }
+        }
+    }
 
-            let mut receiver_tokens = tokens_per_owner.get(to).unwrap_or_else(|| {
-                UnorderedSet::new(StorageKey::TokensPerOwner {
-                    account_hash: env::sha256(to.as_bytes()),
-                })
-            });
-            receiver_tokens.insert(token_id);
-            tokens_per_owner.insert(to, &receiver_tokens);
+    /// Whether `account_id` was approved for all tokens of `owner_id`
+    pub fn is_operator(&self, owner_id: &AccountId, account_id: &AccountId) -> bool {
+        self.operators_by_owner
+            .as_ref()
+            .and_then(|by_owner| by_owner.get(owner_id))
+            .map(|operators| operators.contains(account_id))
+            .unwrap_or(false)
+    }
+
+    /// Whether approval `approval_id` of `token_id` was given with an expiry that has passed
+    pub fn is_approval_expired(&self, token_id: &TokenId, approval_id: u64) -> bool {
+        self.approval_expires_at
+            .as_ref()
+            .and_then(|by_id| by_id.get(&(token_id.clone(), approval_id)))
+            .map(|expires_at| env::block_timestamp() >= expires_at)
+            .unwrap_or(false)
+    }
+
+    /// Forget the expiry of approval `approval_id` of `token_id`, returning the bytes released
+    pub fn internal_remove_approval_expiry(&mut self, token_id: &TokenId, approval_id: u64) -> u64 {
+        let initial_storage_usage = env::storage_usage();
+        if let Some(by_id) = &mut self.approval_expires_at {
+            by_id.remove(&(token_id.clone(), approval_id));
+        }
+        initial_storage_usage.saturating_sub(env::storage_usage())
+    }
+
+    /// Remove all approvals of `token_id` with their expiries, storage released isn't refunded
+    pub fn internal_remove_approvals(&mut self, #[allow(clippy::ptr_arg)] token_id: &TokenId) {
+        if let Some(approvals) = self.approvals_by_id.as_mut().and_then(|by_id| by_id.remove(token_id)) {
+            for approval_id in approvals.approval_ids() {
+                self.internal_remove_approval_expiry(token_id, approval_id);
+            }
+        }
+    }
+
+    /// Drop `approvals` of `token_id` for good, forgetting their expiries, and refund
+    /// `account_id`, who paid for both, their storage.
+    pub fn internal_refund_approvals(
+        &mut self,
+        #[allow(clippy::ptr_arg)] token_id: &TokenId,
+        account_id: AccountId,
+        approvals: &Approvals,
+    ) -> Promise {
+        let expiry_storage_released: u64 = approvals
+            .approval_ids()
+            .map(|approval_id| self.internal_remove_approval_expiry(token_id, approval_id))
+            .sum();
+        refund_approved_account_ids(account_id, approvals)
+            .transfer(Balance::from(expiry_storage_released) * env::storage_byte_cost())
+    }
+
+    /// Remove every trace of `token_id`: owner, metadata, enumeration, approvals and its
+    /// entry in the series token set. Burned editions still count against the series
+    /// `copies`. Does not check the caller or log.
+    pub fn internal_burn(
+        &mut self,
+        token_series_by_id: &mut LookupMap<TokenSeriesId, TokenSeries>,
+        #[allow(clippy::ptr_arg)] token_id: &TokenId,
+        owner_id: &AccountId,
+    ) {
+        self.core.owner_by_id.remove(token_id);
+        if let Some(token_metadata) =
+            self.core.token_metadata_by_id.as_mut().and_then(|by_id| by_id.remove(token_id))
+        {
+            let attributes = attributes_of(token_metadata.extra.as_deref()).unwrap_or_default();
+            self.internal_index_attributes(token_id, &attributes, false);
+        }
+        self.token_metadata_hash_by_id.remove(token_id);
+
+        let mut owned = false;
+        if let Some(tokens_per_owner) = &mut self.core.tokens_per_owner {
+            if let Some(mut owner_tokens) = tokens_per_owner.get(owner_id) {
+                owned = owner_tokens.remove(token_id);
+                if owner_tokens.is_empty() {
+                    tokens_per_owner.remove(owner_id);
+                } else {
+                    tokens_per_owner.insert(owner_id, &owner_tokens);
+                }
+            }
+        }
+        if owned {
+            self.internal_change_owner_count(owner_id, false);
+        }
+        if let Some(hidden_token_ids) = &mut self.hidden_token_ids {
+            hidden_token_ids.remove(token_id);
+        }
+        self.internal_remove_approvals(token_id);
+        if let Some(next_approval_id_by_id) = &mut self.next_approval_id_by_id {
+            next_approval_id_by_id.remove(token_id);
+        }
+
+        let token_series_id = self.token_id_format.token_series_id_of(token_id).to_string();
+        if let Some(mut token_series) = token_series_by_id.get(&token_series_id) {
+            if token_series.tokens.remove(token_id) {
+                token_series.burned += 1;
+                token_series_by_id.insert(&token_series_id, &token_series);
+            }
         }
     }
 
@@ -201,17 +527,19 @@ impl NonFungibleToken {
         #[allow(clippy::ptr_arg)] token_id: &TokenId,
         approval_id: Option<u64>,
         memo: Option<String>,
-    ) -> (AccountId, Option<HashMap<AccountId, u64>>) {
+    ) -> (AccountId, Option<Approvals>) {
         let owner_id =
-            self.owner_by_id.get(token_id).unwrap_or_else(|| env::panic_str("Token not found"));
+            self.core.owner_by_id.get(token_id).unwrap_or_else(|| env::panic_str("Token not found"));
 
         // clear approvals, if using Approval Management extension
         // this will be rolled back by a panic if sending fails
         let approved_account_ids =
             self.approvals_by_id.as_mut().and_then(|by_id| by_id.remove(token_id));
 
-        // check if authorized
-        let sender_id = if sender_id != &owner_id {
+        // check if authorized, an operator of the owner may transfer any of their tokens
+        let sender_id = if sender_id != &owner_id && self.is_operator(&owner_id, sender_id) {
+            Some(sender_id)
+        } else if sender_id != &owner_id {
             // if approval extension is NOT being used, or if token has no approved accounts
             let app_acc_ids =
                 approved_account_ids.as_ref().unwrap_or_else(|| env::panic_str("Unauthorized"));
@@ -223,10 +551,14 @@ impl NonFungibleToken {
             if actual_approval_id.is_none() {
                 env::panic_str("Sender not approved");
             }
+            require!(
+                !self.is_approval_expired(token_id, actual_approval_id.unwrap()),
+                "FireFly: Approval expired"
+            );
 
             // If approval_id included, check that it matches
             require!(
-                approval_id.is_none() || actual_approval_id == approval_id.as_ref(),
+                approval_id.is_none() || actual_approval_id == approval_id,
                 format!(
                     "The actual approval_id {:?} is different from the given approval_id {:?}",
                     actual_approval_id, approval_id
@@ -258,7 +590,7 @@ impl NonFungibleToken {
             old_owner_id: owner_id,
             new_owner_id: receiver_id,
             token_ids: &[token_id],
-            authorized_id: sender_id.filter(|sender_id| *sender_id == owner_id),
+            authorized_id: sender_id.filter(|sender_id| *sender_id != owner_id),
             memo: memo.as_deref(),
         }
         .emit();
@@ -289,35 +621,80 @@ impl NonFungibleToken {
 
     /// Mint a new token without checking:
     /// * Whether the caller id is equal to the `owner_id`
-    /// * Assumes there will be a refund to the predecessor after covering the storage costs
+    /// * `refund_id` will get the attached deposit left over after covering the storage costs.
+    ///   If `None`, the caller is responsible for the storage accounting.
     ///
     /// Returns the newly minted token and emits the mint event
     pub fn internal_mint(
         &mut self,
-        token_series_by_id: &mut UnorderedMap<TokenSeriesId, TokenSeries>,
+        token_series_by_id: &mut LookupMap<TokenSeriesId, TokenSeries>,
+        token_series_id: TokenSeriesId,
+        token_owner_id: AccountId,
+        refund_id: Option<AccountId>,
+    ) -> Token {
+        self.internal_mint_edition(token_series_by_id, token_series_id, token_owner_id, None, refund_id)
+    }
+
+    /// `internal_mint` with the token id ending in `edition_name` rather than the edition number.
+    pub fn internal_mint_edition(
+        &mut self,
+        token_series_by_id: &mut LookupMap<TokenSeriesId, TokenSeries>,
         token_series_id: TokenSeriesId,
         token_owner_id: AccountId,
+        edition_name: Option<&str>,
+        refund_id: Option<AccountId>,
     ) -> Token {
         let mut token_series = token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
-        assert!(token_series.is_mintable, "FireFly: Token series is not mintable");
-        let num_tokens = token_series.tokens.len();
+        assert!(!token_series.is_multi_token, "FireFly: Token series is multi-token, use mt_mint");
+        // `copies` is a hard cap, checked first so a sold out series says so
+        let num_tokens = token_series.tokens.len() + token_series.burned;
         let max_copies = token_series.metadata.copies.unwrap_or(u64::MAX);
-        assert!(num_tokens < max_copies, "Series supply maxed");
+        assert!(num_tokens < max_copies, "FireFly: Series supply maxed");
+        assert!(token_series.is_mintable, "FireFly: Token series is not mintable");
+        token_series.assert_in_mint_window();
 
         if (num_tokens + 1) >= max_copies {
             token_series.is_mintable = false;
             token_series.price = None;
         }
 
-        let token_id = format!("{}{}{}", &token_series_id, TOKEN_DELIMETER, num_tokens + 1);
-        token_series.tokens.insert(&token_id);
+        let token_id = match (edition_name, &mut token_series.edition_pool) {
+            (Some(_), Some(_)) => env::panic_str("FireFly: Editions of this series are drawn at random"),
+            (Some(edition_name), None) => {
+                assert_valid_edition_name(edition_name);
+                self.token_id_format.named_token_id(&token_series_id, edition_name)
+            }
+            (None, Some(edition_pool)) => {
+                let edition = draw_edition(edition_pool, max_copies - num_tokens, num_tokens);
+                self.token_id_format.token_id(&token_series_id, edition)
+            }
+            (None, None) => self.token_id_format.token_id(&token_series_id, num_tokens + 1),
+        };
+        assert!(token_series.tokens.insert(&token_id), "FireFly: Token {} already exists", token_id);
         token_series_by_id.insert(&token_series_id, &token_series);
+        let mut token_metadata = token_series.metadata;
+        let attributes = token_series.trait_tables.map(|trait_tables| roll_attributes(&trait_tables, &token_id));
+        if let Some(attributes) = &attributes {
+            token_metadata.extra = Some(extra_with_attributes(token_metadata.extra, attributes));
+        }
         let token = self.internal_mint_with_refund(
             token_id,
             token_owner_id,
-            token_series.metadata,
-            Some(env::predecessor_account_id()),
+            token_metadata,
+            refund_id,
         );
+        if let Some(attributes) = attributes {
+            env::log_str(
+                json!({
+                    "type": "nft_mint_traits",
+                    "params": {
+                        "token_series_id": token_series_id,
+                        "token_id": token.token_id,
+                        "attributes": attributes,
+                    }
+                }).to_string().as_str()
+            );
+        }
         // NftMint { owner_id: &token.owner_id, token_ids: &[&token.token_id], memo: None }.emit();
         token
     }
@@ -339,30 +716,17 @@ impl NonFungibleToken {
         // Remember current storage usage if refund_id is Some
         let initial_storage_usage = refund_id.map(|account_id| (account_id, env::storage_usage()));
 
-        if self.owner_by_id.get(&token_id).is_some() {
-            env::panic_str("token_id must be unique");
-        }
-
         let owner_id: AccountId = token_owner_id;
 
-        // Core behavior: every token must have an owner
-        self.owner_by_id.insert(&token_id, &owner_id);
-
-        // Metadata extension: Save metadata, keep variable around to return later.
-        // Note that check above already panicked if metadata extension in use but no metadata
-        // provided to call.
-        self.token_metadata_by_id.insert(&token_id, &token_metadata);
-
-        // Enumeration extension: Record tokens_per_owner for use with enumeration view methods.
-        if let Some(tokens_per_owner) = &mut self.tokens_per_owner {
-            let mut token_ids = tokens_per_owner.get(&owner_id).unwrap_or_else(|| {
-                UnorderedSet::new(StorageKey::TokensPerOwner {
-                    account_hash: env::sha256(owner_id.as_bytes()),
-                })
-            });
-            token_ids.insert(&token_id);
-            tokens_per_owner.insert(&owner_id, &token_ids);
-        }
+        // Owner, metadata and tokens_per_owner, refunded below together with the maps kept here
+        self.core.internal_mint_with_refund(
+            token_id.clone(),
+            owner_id.clone(),
+            Some(token_metadata.clone().into()),
+            None,
+        );
+        self.internal_commit_token_metadata(&token_id, None, &token_metadata);
+        self.internal_change_owner_count(&owner_id, true);
 
         // Approval Management extension: return empty HashMap as part of Token
         let approved_account_ids =
@@ -388,7 +752,11 @@ impl NonFungibleTokenCore for NonFungibleToken {
     ) {
         assert_one_yocto();
         let sender_id = env::predecessor_account_id();
-        self.internal_transfer(&sender_id, &receiver_id, &token_id, approval_id, memo);
+        let (previous_owner_id, approvals) =
+            self.internal_transfer(&sender_id, &receiver_id, &token_id, approval_id, memo);
+        if let Some(approvals) = approvals {
+            self.internal_refund_approvals(&token_id, previous_owner_id, &approvals);
+        }
     }
 
     fn nft_transfer_call(
@@ -417,12 +785,12 @@ impl NonFungibleTokenCore for NonFungibleToken {
     }
 
     fn nft_token(&self, token_id: TokenId) -> Option<Token> {
-        let owner_id = self.owner_by_id.get(&token_id)?;
-        let metadata = self.token_metadata_by_id.get(&token_id).unwrap();
+        let owner_id = self.core.owner_by_id.get(&token_id)?;
+        let metadata = self.token_metadata(&token_id).unwrap();
         let approved_account_ids = self
             .approvals_by_id
             .as_ref()
-            .and_then(|by_id| by_id.get(&token_id).or_else(|| Some(HashMap::new())));
+            .map(|by_id| by_id.get(&token_id).map(Approvals::into_map).unwrap_or_default());
         Some(Token { token_id, owner_id, metadata, approved_account_ids })
     }
 }
@@ -434,7 +802,7 @@ impl NonFungibleTokenResolver for NonFungibleToken {
         previous_owner_id: AccountId,
         receiver_id: AccountId,
         token_id: TokenId,
-        approved_account_ids: Option<HashMap<AccountId, u64>>,
+        approved_account_ids: Option<Approvals>,
     ) -> bool {
         // Get whether token should be returned
         let must_revert = match env::promise_result(0) {
@@ -449,24 +817,30 @@ impl NonFungibleTokenResolver for NonFungibleToken {
             PromiseResult::NotReady => todo!(),
         };
 
-        // if call succeeded, return early
+        // if call succeeded, the previous owner's approvals are gone for good, refund their storage
         if !must_revert {
+            if let Some(approved_account_ids) = approved_account_ids {
+                self.internal_refund_approvals(&token_id, previous_owner_id, &approved_account_ids);
+            }
             return true;
         }
 
         // OTHERWISE, try to set owner back to previous_owner_id and restore approved_account_ids
 
         // Check that receiver didn't already transfer it away or burn it.
-        if let Some(current_owner) = self.owner_by_id.get(&token_id) {
+        if let Some(current_owner) = self.core.owner_by_id.get(&token_id) {
             if current_owner != receiver_id {
                 // The token is not owned by the receiver anymore. Can't return it.
+                if let Some(approved_account_ids) = approved_account_ids {
+                    self.internal_refund_approvals(&token_id, previous_owner_id, &approved_account_ids);
+                }
                 return true;
             }
         } else {
             // The token was burned and doesn't exist anymore.
             // Refund storage cost for storing approvals to original owner and return early.
             if let Some(approved_account_ids) = approved_account_ids {
-                refund_approved_account_ids(previous_owner_id, &approved_account_ids);
+                self.internal_refund_approvals(&token_id, previous_owner_id, &approved_account_ids);
             }
             return true;
         };
@@ -476,15 +850,14 @@ impl NonFungibleTokenResolver for NonFungibleToken {
         // If using Approval Management extension,
         // 1. revert any approvals receiver already set, refunding storage costs
         // 2. reset approvals to what previous owner had set before call to nft_transfer_call
-        if let Some(by_id) = &mut self.approvals_by_id {
-            if let Some(receiver_approvals) = by_id.get(&token_id) {
-                refund_approved_account_ids(receiver_id.clone(), &receiver_approvals);
-            }
-            if let Some(previous_owner_approvals) = approved_account_ids {
-                by_id.insert(&token_id, &previous_owner_approvals);
-            }
+        // the expiries of the previous owner's approvals were kept for this
+        if let Some(receiver_approvals) = self.approvals_by_id.as_mut().and_then(|by_id| by_id.remove(&token_id)) {
+            self.internal_refund_approvals(&token_id, receiver_id.clone(), &receiver_approvals);
+        }
+        if let (Some(by_id), Some(previous_owner_approvals)) = (&mut self.approvals_by_id, approved_account_ids) {
+            by_id.insert(&token_id, &previous_owner_approvals);
         }
-        // NonFungibleToken::emit_transfer(&receiver_id, &previous_owner_id, &token_id, None, None);
+        NonFungibleToken::emit_transfer(&receiver_id, &previous_owner_id, &token_id, None, None);
         false
     }
 }
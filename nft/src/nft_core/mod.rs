@@ -1,3 +1,19 @@
+//! Core of the token, built on `near_contract_standards::non_fungible_token` 4.1.1. Its
+//! `NonFungibleToken` is kept as `NonFungibleToken::core` and stores the owners, the token
+//! metadata and the tokens of each owner; minting and transfers go through it. The series
+//! layer adds what the upstream struct has no room for, in maps kept alongside it:
+//!
+//! * tokens are minted from a series, with ids built by `TokenIdFormat`, editions drawn at
+//!   random and traits rolled into the metadata (`internal_mint_edition`);
+//! * every metadata write commits a hash and updates the trait index
+//!   (`internal_set_token_metadata`), and tokens can be burned (`internal_burn`);
+//! * enumeration skips tokens their owner hid, and counts the tokens of each owner;
+//! * approvals are stored as `Approvals` rather than upstream's `HashMap`, can expire, and
+//!   owners can approve operators for all their tokens, which `nft_transfer` honors;
+//! * `nft_resolve_transfer` restores the approvals of a returned token.
+//!
+//! The NEP-171, 177, 178 and 181 interfaces are unchanged.
+
 mod core_impl;
 mod receiver;
 mod resolver;
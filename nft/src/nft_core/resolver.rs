@@ -1,6 +1,6 @@
 use crate::token::TokenId;
+use crate::approval::Approvals;
 use near_sdk::{ext_contract, AccountId};
-use std::collections::HashMap;
 
 /// Used when an NFT is transferred using `nft_transfer_call`. This is the method that's called after `nft_on_transfer`. This trait is implemented on the NFT contract.
 #[ext_contract(ext_nft_resolver)]
@@ -36,6 +36,6 @@ pub trait NonFungibleTokenResolver {
         previous_owner_id: AccountId,
         receiver_id: AccountId,
         token_id: TokenId,
-        approvals: Option<HashMap<AccountId, u64>>,
+        approvals: Option<Approvals>,
     ) -> bool;
 }
@@ -0,0 +1,171 @@
+use crate::Contract;
+use crate::ContractExt;
+use crate::metadata::TokenMetadata;
+use crate::series::{assert_valid_price, SeriesOptions, TokenSeriesId, TokenSeriesJson};
+use crate::utils::refund_deposit;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen};
+use serde_json::json;
+
+/// Mint window of an open edition series, which has no `copies` cap while the window is open.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OpenEdition {
+    pub starts_at: U64,
+    pub ends_at: U64,
+    /// Number of editions minted, recorded once the window has closed and the series is finalized.
+    pub final_supply: Option<U64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Create a series that anyone may buy from between `starts_at` and `ends_at`
+    /// (nanoseconds), without a copies cap. `token_metadata.copies` must be unset; it is
+    /// filled in with the final count by `nft_finalize_open_edition`.
+    #[payable]
+    pub fn nft_create_open_edition(
+        &mut self,
+        token_metadata: TokenMetadata,
+        price: Option<U128>,
+        starts_at: U64,
+        ends_at: U64,
+    ) -> TokenSeriesJson {
+        let initial_storage_usage = env::storage_usage();
+        assert!(token_metadata.title.is_some(), "FireFly: token_metadata.title is required");
+        assert!(token_metadata.copies.is_none(), "FireFly: Open editions have no copies cap");
+        assert!(starts_at.0 < ends_at.0, "FireFly: starts_at must be before ends_at");
+        assert!(ends_at.0 > env::block_timestamp(), "FireFly: ends_at must be in the future");
+
+        let price_res = assert_valid_price(price, self.max_price);
+        let token_series = self.internal_create_series(token_metadata, price_res, SeriesOptions::default());
+        self.open_editions.insert(
+            &token_series.token_series_id,
+            &OpenEdition { starts_at, ends_at, final_supply: None },
+        );
+
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+
+        token_series
+    }
+
+    /// Close an open edition whose window has ended: lock the series and record the number
+    /// of editions minted as its final supply. Callable by anyone, once.
+    pub fn nft_finalize_open_edition(&mut self, token_series_id: TokenSeriesId) -> U64 {
+        let mut open_edition = self.open_editions.get(&token_series_id).expect("FireFly: Not an open edition");
+        assert!(open_edition.final_supply.is_none(), "FireFly: Open edition already finalized");
+        assert!(
+            env::block_timestamp() >= open_edition.ends_at.0,
+            "FireFly: Open edition is still running"
+        );
+
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let final_supply = token_series.tokens.len();
+        token_series.is_mintable = false;
+        token_series.price = None;
+        token_series.metadata.copies = Some(final_supply);
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        open_edition.final_supply = Some(U64(final_supply));
+        self.open_editions.insert(&token_series_id, &open_edition);
+
+        env::log_str(
+            json!({
+                "type": "nft_finalize_open_edition",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "final_supply": U64(final_supply),
+                }
+            }).to_string().as_str()
+        );
+
+        U64(final_supply)
+    }
+
+    pub fn nft_open_edition(&self, token_series_id: TokenSeriesId) -> Option<OpenEdition> {
+        self.open_editions.get(&token_series_id)
+    }
+}
+
+impl Contract {
+    /// Panics when `token_series_id` is an open edition outside of its mint window.
+    pub(crate) fn assert_open_edition_window(&self, token_series_id: &TokenSeriesId) {
+        if let Some(open_edition) = self.open_editions.get(token_series_id) {
+            let now = env::block_timestamp();
+            assert!(now >= open_edition.starts_at.0, "FireFly: Open edition has not started");
+            assert!(now < open_edition.ends_at.0, "FireFly: Open edition has ended");
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn create_open_edition(context: &mut VMContextBuilder, contract: &mut Contract) -> TokenSeriesId {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .block_timestamp(0)
+            .build());
+        contract
+            .nft_create_open_edition(TokenMetadata { copies: None, ..sample_token_metadata() }, None, U64(10), U64(100))
+            .token_series_id
+    }
+
+    #[test]
+    fn test_finalize_open_edition() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token_series_id = create_open_edition(&mut context, &mut contract);
+
+        for _ in 0..3 {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST)
+                .block_timestamp(50)
+                .build());
+            contract.nft_mint(token_series_id.clone(), accounts(2));
+        }
+
+        testing_env!(context.attached_deposit(0).block_timestamp(100).build());
+        assert_eq!(contract.nft_finalize_open_edition(token_series_id.clone()), U64(3));
+        let token_series = contract.token_series_by_id.get(&token_series_id).unwrap();
+        assert!(!token_series.is_mintable);
+        assert_eq!(token_series.metadata.copies, Some(3));
+        assert_eq!(contract.nft_open_edition(token_series_id).unwrap().final_supply, Some(U64(3)));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Open edition has ended")]
+    fn test_mint_after_open_edition_ended() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token_series_id = create_open_edition(&mut context, &mut contract);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .block_timestamp(100)
+            .build());
+        contract.nft_mint(token_series_id, accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Open edition is still running")]
+    fn test_finalize_running_open_edition() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token_series_id = create_open_edition(&mut context, &mut contract);
+
+        testing_env!(context.block_timestamp(99).build());
+        contract.nft_finalize_open_edition(token_series_id);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::Contract;
+use crate::ContractExt;
+use crate::token::TokenId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+
+/// How long a posted challenge can be answered, one hour in nanoseconds.
+pub const CHALLENGE_TTL: u64 = 60 * 60 * 1_000_000_000;
+const MAX_CHALLENGE_LEN: usize = 256;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OwnershipProof {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub proved_at: U64,
+}
+
+/// A challenge posted by a dapp. It can be answered once, by the owner of a token, before it
+/// expires, so a proof can't be replayed for another challenge or another session.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OwnershipChallenge {
+    pub issuer_id: AccountId,
+    pub created_at: U64,
+    pub expires_at: U64,
+    pub proof: Option<OwnershipProof>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Post a challenge that a token owner can answer with `nft_prove_ownership`.
+    /// The caller pays for its storage.
+    #[payable]
+    pub fn nft_post_challenge(&mut self, challenge: String) -> OwnershipChallenge {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            !challenge.is_empty() && challenge.len() <= MAX_CHALLENGE_LEN,
+            "FireFly: challenge must be 1 to {} bytes",
+            MAX_CHALLENGE_LEN
+        );
+        assert!(
+            self.ownership_challenges.get(&challenge).is_none(),
+            "FireFly: challenge already posted"
+        );
+
+        let now = env::block_timestamp();
+        let ownership_challenge = OwnershipChallenge {
+            issuer_id: env::predecessor_account_id(),
+            created_at: now.into(),
+            expires_at: (now + CHALLENGE_TTL).into(),
+            proof: None,
+        };
+        self.ownership_challenges.insert(&challenge, &ownership_challenge);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+
+        ownership_challenge
+    }
+
+    /// Answer a challenge as the owner of `token_id`. Requires a deposit so it can only be
+    /// signed with a full access key, and covers the storage of the proof.
+    #[payable]
+    pub fn nft_prove_ownership(&mut self, token_id: TokenId, challenge: String) -> OwnershipProof {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+        assert!(
+            self.tokens.core.owner_by_id.get(&token_id) == Some(owner_id.clone()),
+            "FireFly: Token owner only"
+        );
+
+        let mut ownership_challenge =
+            self.ownership_challenges.get(&challenge).expect("FireFly: challenge not exist");
+        assert!(ownership_challenge.proof.is_none(), "FireFly: challenge already answered");
+        assert!(
+            env::block_timestamp() < ownership_challenge.expires_at.0,
+            "FireFly: challenge expired"
+        );
+
+        let proof = OwnershipProof {
+            token_id,
+            owner_id,
+            proved_at: env::block_timestamp().into(),
+        };
+        ownership_challenge.proof = Some(proof.clone());
+        self.ownership_challenges.insert(&challenge, &ownership_challenge);
+
+        env::log_str(
+            json!({
+                "type": "nft_prove_ownership",
+                "params": {
+                    "challenge": challenge,
+                    "issuer_id": ownership_challenge.issuer_id,
+                    "token_id": proof.token_id,
+                    "owner_id": proof.owner_id,
+                }
+            }).to_string().as_str()
+        );
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        proof
+    }
+
+    /// Remove a challenge, answered or not, refunding its storage to the issuer
+    #[payable]
+    pub fn nft_remove_challenge(&mut self, challenge: String) {
+        assert_one_yocto();
+        let ownership_challenge =
+            self.ownership_challenges.get(&challenge).expect("FireFly: challenge not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            ownership_challenge.issuer_id,
+            "FireFly: Challenge issuer only"
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        self.ownership_challenges.remove(&challenge);
+        let storage_released = initial_storage_usage - env::storage_usage();
+        Promise::new(ownership_challenge.issuer_id)
+            .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+    }
+
+    pub fn nft_get_challenge(&self, challenge: String) -> Option<OwnershipChallenge> {
+        self.ownership_challenges.get(&challenge)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn setup_token(context: &mut VMContextBuilder) -> (Contract, TokenId) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.nft_post_challenge("login:42".to_string());
+        (contract, token.token_id)
+    }
+
+    #[test]
+    fn test_prove_ownership() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_token(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_prove_ownership(token_id.clone(), "login:42".to_string());
+
+        let challenge = contract.nft_get_challenge("login:42".to_string()).unwrap();
+        assert_eq!(challenge.issuer_id, accounts(3));
+        let proof = challenge.proof.unwrap();
+        assert_eq!(proof.token_id, token_id);
+        assert_eq!(proof.owner_id, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: challenge already answered")]
+    fn test_prove_ownership_replay() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_token(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_prove_ownership(token_id.clone(), "login:42".to_string());
+        contract.nft_prove_ownership(token_id, "login:42".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: challenge expired")]
+    fn test_prove_ownership_expired() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_token(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .block_timestamp(CHALLENGE_TTL)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_prove_ownership(token_id, "login:42".to_string());
+    }
+}
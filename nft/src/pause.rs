@@ -0,0 +1,136 @@
+//! Emergency switches. Owners and moderators can stop minting, transfers or approvals at
+//! once, without the admin delay, while an incident is investigated; only owners can turn
+//! them back on, through `AdminAction::Unpause` when the owner set requires several
+//! confirmations.
+
+use crate::Contract;
+use crate::migrate::assert_not_migrating;
+use crate::ContractExt;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen};
+use serde_json::json;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PauseFlags {
+    #[serde(default)]
+    pub minting: bool,
+    #[serde(default)]
+    pub transfers: bool,
+    #[serde(default)]
+    pub approvals: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Pause every capability set in `flags`, leaving the others as they are. Owners and
+    /// moderators only.
+    #[payable]
+    pub fn pause(&mut self, flags: PauseFlags) -> PauseFlags {
+        assert_one_yocto();
+        self.assert_owner_or_moderator();
+        self.paused.minting |= flags.minting;
+        self.paused.transfers |= flags.transfers;
+        self.paused.approvals |= flags.approvals;
+        self.log_paused("pause");
+        self.paused
+    }
+
+    /// Resume every capability set in `flags`. Owner only, or `AdminAction::Unpause` for
+    /// owner sets requiring several confirmations.
+    #[payable]
+    pub fn unpause(&mut self, flags: PauseFlags) -> PauseFlags {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_unpause(flags)
+    }
+
+    pub fn get_paused(&self) -> PauseFlags {
+        self.paused
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_minting_not_paused(&self) {
+        assert!(!self.paused.minting, "FireFly: Minting is paused");
+    }
+
+    pub(crate) fn assert_transfers_not_paused(&self) {
+        assert!(!self.paused.transfers, "FireFly: Transfers are paused");
+    }
+
+    pub(crate) fn assert_approvals_not_paused(&self) {
+        assert!(!self.paused.approvals, "FireFly: Approvals are paused");
+    }
+
+    pub(crate) fn internal_unpause(&mut self, flags: PauseFlags) -> PauseFlags {
+        assert_not_migrating();
+        self.paused.minting &= !flags.minting;
+        self.paused.transfers &= !flags.transfers;
+        self.paused.approvals &= !flags.approvals;
+        self.log_paused("unpause");
+        self.paused
+    }
+
+    fn log_paused(&self, event_type: &str) {
+        env::log_str(
+            json!({
+                "type": event_type,
+                "params": {
+                    "paused": self.paused,
+                }
+            }).to_string().as_str()
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "FireFly: Transfers are paused")]
+    fn test_pause_transfers() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context.attached_deposit(1).build());
+        let paused = contract.pause(PauseFlags { transfers: true, ..Default::default() });
+        assert_eq!(paused, PauseFlags { minting: false, transfers: true, approvals: false });
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.nft_transfer(accounts(2), token.token_id, None, None);
+    }
+
+    #[test]
+    fn test_unpause_minting() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.pause(PauseFlags { minting: true, approvals: true, ..Default::default() });
+        let paused = contract.unpause(PauseFlags { minting: true, ..Default::default() });
+        assert_eq!(paused, PauseFlags { minting: false, transfers: false, approvals: true });
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_mint(token_series.token_series_id, accounts(1));
+    }
+}
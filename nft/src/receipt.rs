@@ -0,0 +1,63 @@
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::TokenSeriesId;
+use crate::token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance};
+
+/// Oldest receipts are dropped once an account has this many.
+pub const MAX_RECEIPTS_PER_ACCOUNT: usize = 100;
+
+/// Record of a paid mint, kept for the account that paid for it.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PurchaseReceipt {
+    pub token_series_id: TokenSeriesId,
+    pub token_id: TokenId,
+    pub receiver_id: AccountId,
+    pub price: U128,
+    pub purchased_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Get the purchases paid for by `account_id`, oldest first
+    pub fn nft_purchase_receipts(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<PurchaseReceipt> {
+        let receipts = self.purchase_receipts.get(&account_id).unwrap_or_default();
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        receipts.into_iter().skip(start_index as usize).take(limit).collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_add_purchase_receipt(
+        &mut self,
+        buyer_id: &AccountId,
+        token_series_id: TokenSeriesId,
+        token_id: TokenId,
+        receiver_id: AccountId,
+        price: Balance,
+    ) {
+        let mut receipts = self.purchase_receipts.get(buyer_id).unwrap_or_default();
+        if receipts.len() >= MAX_RECEIPTS_PER_ACCOUNT {
+            receipts.remove(0);
+        }
+        receipts.push(PurchaseReceipt {
+            token_series_id,
+            token_id,
+            receiver_id,
+            price: price.into(),
+            purchased_at: env::block_timestamp().into(),
+        });
+        self.purchase_receipts.insert(buyer_id, &receipts);
+    }
+}
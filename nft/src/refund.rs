@@ -0,0 +1,105 @@
+use crate::Contract;
+use crate::ContractExt;
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, env, near_bindgen, AccountId, Balance, Gas, Promise, PromiseResult,
+};
+use serde_json::json;
+
+const GAS_FOR_RESOLVE_PAYOUT: Gas = Gas(5_000_000_000_000);
+
+#[near_bindgen]
+impl Contract {
+    /// Callback of every payout transfer. A failed transfer (e.g. the receiver account was
+    /// deleted) is credited to the refund ledger instead of being lost.
+    #[private]
+    pub fn nft_resolve_payout(&mut self, account_id: AccountId, amount: U128) {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+        let pending = self.pending_refunds.get(&account_id).unwrap_or(0);
+        self.pending_refunds.insert(&account_id, &(pending + amount.0));
+
+        env::log_str(
+            json!({
+                "type": "nft_payout_failed",
+                "params": {
+                    "account_id": account_id,
+                    "amount": amount,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    /// Withdraw everything the refund ledger holds for the caller
+    #[payable]
+    pub fn claim_refund(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = self.pending_refunds.remove(&account_id).unwrap_or(0);
+        assert!(amount > 0, "FireFly: No pending refund");
+        self.internal_payout(account_id, amount);
+        amount.into()
+    }
+
+    pub fn get_pending_refund(&self, account_id: AccountId) -> U128 {
+        self.pending_refunds.get(&account_id).unwrap_or(0).into()
+    }
+}
+
+impl Contract {
+    /// Transfer `amount` to `account_id`, crediting it to the refund ledger if the transfer fails.
+    pub(crate) fn internal_payout(&self, account_id: AccountId, amount: Balance) -> Promise {
+        Promise::new(account_id.clone()).transfer(amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_RESOLVE_PAYOUT)
+                .nft_resolve_payout(account_id, amount.into()),
+        )
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+    use crate::test_utils::{get_context};
+    use super::*;
+
+    #[test]
+    fn test_failed_payout_is_claimable() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.nft_resolve_payout(accounts(2), U128(100));
+        assert_eq!(contract.get_pending_refund(accounts(2)), U128(100));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(2)).build());
+        assert_eq!(contract.claim_refund(), U128(100));
+        assert_eq!(contract.get_pending_refund(accounts(2)), U128(0));
+    }
+
+    #[test]
+    fn test_successful_payout_is_not_credited() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        contract.nft_resolve_payout(accounts(2), U128(100));
+        assert_eq!(contract.get_pending_refund(accounts(2)), U128(0));
+    }
+}
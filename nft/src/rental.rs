@@ -0,0 +1,231 @@
+//! Token rentals. An owner lists a token with a price and a duration; a renter paying the
+//! price becomes the user of the token, see `nft_user_of`, until the rental expires. The
+//! owner keeps the token all along. Expired rentals are not cleaned up by a timer: views
+//! ignore them and the next transfer of the token drops them.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::token::TokenId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RentalListing {
+    pub price: U128,
+    /// Length of a rental in nanoseconds
+    pub duration: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Rental {
+    pub user_id: AccountId,
+    pub expires_at: U64,
+}
+
+impl Rental {
+    pub fn is_active(&self) -> bool {
+        env::block_timestamp() < self.expires_at.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Offer one of the caller's tokens for rent, replacing any previous listing. A rental
+    /// already running keeps its terms. The caller pays for the storage.
+    #[payable]
+    pub fn nft_list_for_rent(&mut self, token_id: TokenId, price: U128, duration: U64) {
+        assert_at_least_one_yocto();
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        assert_eq!(env::predecessor_account_id(), owner_id, "FireFly: Token owner only");
+        assert!(duration.0 > 0, "FireFly: duration must be positive");
+        self.assert_not_soulbound(&token_id);
+
+        let initial_storage_usage = env::storage_usage();
+        let listing = RentalListing { price, duration };
+        self.rental_listings.insert(&token_id, &listing);
+        env::log_str(
+            json!({
+                "type": "nft_list_for_rent",
+                "params": {
+                    "token_id": token_id,
+                    "owner_id": owner_id,
+                    "price": listing.price,
+                    "duration": listing.duration,
+                }
+            }).to_string().as_str()
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    #[payable]
+    pub fn nft_delist_rental(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        assert_eq!(env::predecessor_account_id(), owner_id, "FireFly: Token owner only");
+
+        let initial_storage_usage = env::storage_usage();
+        if self.rental_listings.remove(&token_id).is_some() {
+            env::log_str(
+                json!({
+                    "type": "nft_delist_rental",
+                    "params": {
+                        "token_id": token_id,
+                        "owner_id": owner_id,
+                    }
+                }).to_string().as_str()
+            );
+        }
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(owner_id)
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+    }
+
+    /// Rent a listed token for the listing duration. The attached deposit must cover the
+    /// listing price, paid to the owner minus the protocol fee, plus storage.
+    #[payable]
+    pub fn nft_rent(&mut self, token_id: TokenId) -> Rental {
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        let listing = self.rental_listings.get(&token_id).expect("FireFly: Token is not for rent");
+        let user_id = env::predecessor_account_id();
+        assert_ne!(user_id, owner_id, "FireFly: Owner cannot rent their own token");
+        self.assert_not_banned(&user_id);
+        assert!(self.nft_rental(token_id.clone()).is_none(), "FireFly: Token is rented");
+
+        let rental = Rental {
+            user_id,
+            expires_at: U64(env::block_timestamp().saturating_add(listing.duration.0)),
+        };
+        self.rentals.insert(&token_id, &rental);
+
+        let price = listing.price.0;
+        let attached_deposit = env::attached_deposit();
+        let storage_cost = env::storage_byte_cost()
+            * Balance::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        assert!(
+            attached_deposit >= price + storage_cost,
+            "FireFly: Must attach {} yoctoNEAR to cover price and storage",
+            price + storage_cost
+        );
+        if price > 0 {
+            let proceeds = self.internal_take_fees(price, 0);
+            self.internal_credit_revenue(&owner_id, proceeds);
+        }
+        let refund = attached_deposit - price - storage_cost;
+        if refund > 1 {
+            Promise::new(rental.user_id.clone()).transfer(refund);
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_rent",
+                "params": {
+                    "token_id": token_id,
+                    "owner_id": owner_id,
+                    "user_id": rental.user_id,
+                    "price": listing.price,
+                    "expires_at": rental.expires_at,
+                }
+            }).to_string().as_str()
+        );
+        rental
+    }
+
+    pub fn nft_rental_listing(&self, token_id: TokenId) -> Option<RentalListing> {
+        self.rental_listings.get(&token_id)
+    }
+
+    /// Rental currently running on `token_id`, expired rentals are ignored.
+    pub fn nft_rental(&self, token_id: TokenId) -> Option<Rental> {
+        self.rentals.get(&token_id).filter(Rental::is_active)
+    }
+
+    /// Account currently renting `token_id`, `None` when it isn't rented.
+    pub fn nft_user_of(&self, token_id: TokenId) -> Option<AccountId> {
+        self.nft_rental(token_id).map(|rental| rental.user_id)
+    }
+}
+
+impl Contract {
+    /// Called before `token_id` changes hands: refuses while it is rented, then drops the
+    /// expired rental and the listing of the current owner.
+    pub(crate) fn internal_clear_rental(&mut self, token_id: &TokenId) {
+        if let Some(rental) = self.rentals.get(token_id) {
+            assert!(!rental.is_active(), "FireFly: Token is rented");
+            self.rentals.remove(token_id);
+        }
+        self.rental_listings.remove(token_id);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn setup_rented_token(context: &mut VMContextBuilder) -> (Contract, TokenId) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_list_for_rent(token.token_id.clone(), U128(1000), U64(100));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let rental = contract.nft_rent(token.token_id.clone());
+        assert_eq!(rental.expires_at, U64(100));
+        (contract, token.token_id)
+    }
+
+    #[test]
+    fn test_rental_expires() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_rented_token(&mut context);
+        assert_eq!(contract.nft_user_of(token_id.clone()), Some(accounts(2)));
+        assert_eq!(contract.nft_token(token_id.clone()).unwrap().owner_id, accounts(1));
+
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(100)
+            .build());
+        assert_eq!(contract.nft_user_of(token_id.clone()), None);
+        contract.nft_transfer(accounts(3), token_id.clone(), None, None);
+        assert_eq!(contract.nft_rental_listing(token_id), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token is rented")]
+    fn test_rented_token_cannot_transfer() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token_id) = setup_rented_token(&mut context);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.nft_transfer(accounts(3), token_id, None, None);
+    }
+}
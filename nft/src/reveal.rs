@@ -0,0 +1,218 @@
+//! Blind drops. A series is created and sold with placeholder metadata; once the creator
+//! reveals it, the real metadata replaces the placeholder on the series and on every token
+//! minted so far, optionally with different metadata for some editions. Editions can be drawn
+//! at random when minted, so buyers can't pick the editions that will reveal as rare by
+//! minting at the right moment.
+
+use crate::{Contract, StorageKey};
+use crate::ContractExt;
+use crate::events::NftMetadataUpdate;
+use crate::metadata::TokenMetadata;
+use crate::series::{TokenSeriesId, TokenSeriesJson};
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::collections::LookupMap;
+use near_sdk::{env, near_bindgen};
+use serde_json::json;
+use std::collections::HashMap;
+
+#[near_bindgen]
+impl Contract {
+    /// Replace the placeholder metadata of a series with `revealed_metadata`. Minted editions
+    /// listed in `edition_metadata` get their own metadata instead, e.g. per-edition art.
+    /// Editions minted afterwards get `revealed_metadata`. A series is revealed once, while
+    /// its metadata isn't frozen. Creator only, the caller pays for extra storage. Like
+    /// `nft_update_series_metadata` it rewrites the first `MAX_METADATA_REFRESH_TOKENS`
+    /// editions, the others through `nft_refresh_series_metadata`.
+    #[payable]
+    pub fn nft_reveal_series(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        revealed_metadata: TokenMetadata,
+        edition_metadata: Option<HashMap<u64, TokenMetadata>>,
+    ) -> TokenSeriesJson {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(!token_series.metadata_frozen, "FireFly: Token series metadata is frozen");
+        assert!(!token_series.is_revealed, "FireFly: Token series already revealed");
+        assert!(revealed_metadata.title.is_some(), "FireFly: token_metadata.title is required");
+
+        let edition_metadata = edition_metadata.unwrap_or_default();
+        let minted = self.internal_minted_count(&token_series_id, &token_series);
+        for edition in edition_metadata.keys() {
+            // drawn editions aren't minted in order
+            let is_minted = match token_series.edition_pool {
+                Some(_) => token_series
+                    .tokens
+                    .contains(&self.tokens.token_id_format.token_id(&token_series_id, *edition)),
+                None => *edition >= 1 && *edition <= minted,
+            };
+            assert!(is_minted, "FireFly: Edition {} is not minted", edition);
+        }
+
+        token_series.is_revealed = true;
+        let token_ids = self.internal_replace_series_metadata(
+            &token_series_id,
+            &mut token_series,
+            revealed_metadata,
+            edition_metadata,
+        );
+
+        env::log_str(
+            json!({
+                "type": "nft_reveal_series",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "token_metadata": token_series.metadata,
+                }
+            }).to_string().as_str()
+        );
+        if !token_ids.is_empty() {
+            let token_ids: Vec<&str> = token_ids.iter().map(String::as_str).collect();
+            NftMetadataUpdate { token_ids: &token_ids, memo: Some("reveal") }.emit();
+        }
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        token_series.to_json(token_series_id)
+    }
+
+    /// Draw the edition of each token minted from a series at random among the editions left,
+    /// instead of handing them out in order. The series needs a copies cap. Can only change
+    /// before the first mint. Creator only, the caller pays for the storage.
+    #[payable]
+    pub fn nft_set_series_random_editions(&mut self, token_series_id: TokenSeriesId, random_editions: bool) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(!token_series.is_multi_token, "FireFly: Token series is multi-token");
+        assert!(token_series.metadata.copies.is_some(), "FireFly: Token series has no copies cap");
+        assert_eq!(
+            self.internal_minted_count(&token_series_id, &token_series),
+            0,
+            "FireFly: Token series already has minted tokens"
+        );
+
+        token_series.edition_pool = if random_editions {
+            Some(LookupMap::new(
+                StorageKey::EditionPoolInner { token_series: token_series_id.clone() }
+                    .try_to_vec()
+                    .unwrap(),
+            ))
+        } else {
+            None
+        };
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        env::log_str(
+            json!({
+                "type": "nft_set_series_random_editions",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "random_editions": random_editions,
+                }
+            }).to_string().as_str()
+        );
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    fn test_reveal_series() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let first = contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+        let second = contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let revealed = TokenMetadata { title: Some("Olympus Mons".into()), ..Default::default() };
+        let mut edition_metadata = HashMap::new();
+        edition_metadata.insert(2, TokenMetadata { title: Some("Valles Marineris".into()), ..Default::default() });
+        let series = contract.nft_reveal_series(token_series.token_series_id.clone(), revealed, Some(edition_metadata));
+        assert_eq!(series.metadata.copies, Some(10));
+        assert!(contract.nft_get_series_single(token_series.token_series_id).is_revealed);
+
+        let first = contract.nft_token(first.token_id).unwrap();
+        let second = contract.nft_token(second.token_id).unwrap();
+        assert_eq!(first.metadata.title, Some("Olympus Mons".into()));
+        assert_eq!(second.metadata.title, Some("Valles Marineris".into()));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Edition 3 is not minted")]
+    fn test_reveal_unminted_edition() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+
+        let mut edition_metadata = HashMap::new();
+        edition_metadata.insert(3, sample_token_metadata());
+        contract.nft_reveal_series(token_series.token_series_id, sample_token_metadata(), Some(edition_metadata));
+    }
+
+    #[test]
+    fn test_random_editions() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_set_series_random_editions(token_series.token_series_id.clone(), true);
+        assert!(contract.nft_get_series_single(token_series.token_series_id.clone()).random_editions);
+
+        let mut token_ids = vec![];
+        for i in 0..10u8 {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST)
+                .random_seed([i; 32])
+                .build());
+            token_ids.push(contract.nft_mint(token_series.token_series_id.clone(), accounts(1)).token_id);
+        }
+        assert_ne!(token_ids[0], "1:1".to_string());
+        token_ids.sort_by_key(|token_id| contract.tokens.token_id_format.edition_of(token_id));
+        let expected: Vec<String> = (1..=10).map(|edition| format!("1:{}", edition)).collect();
+        assert_eq!(token_ids, expected);
+    }
+}
@@ -0,0 +1,189 @@
+//! Revenue ledger. Sale proceeds owed to creators, royalty holders, sellers and referrers are
+//! credited here instead of being transferred right away, so a sale never depends on a batch
+//! of transfers going through. Every account withdraws what it is owed with `claim_revenue`,
+//! except for the proceeds of minting a series, which are kept with the series' earnings
+//! until its creator calls `withdraw_series_earnings`.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::TokenSeriesId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance};
+use serde_json::json;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeriesEarnings {
+    /// Sum of the prices of every paid mint, before fees
+    pub sales_volume: U128,
+    pub paid_mints: U64,
+    /// Proceeds the creator hasn't withdrawn yet
+    pub unclaimed: U128,
+}
+
+impl Default for SeriesEarnings {
+    fn default() -> Self {
+        SeriesEarnings { sales_volume: U128(0), paid_mints: U64(0), unclaimed: U128(0) }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Withdraw all revenue credited to the caller.
+    #[payable]
+    pub fn claim_revenue(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = self.claimable_revenue.remove(&account_id).unwrap_or(0);
+        assert!(amount > 0, "FireFly: No revenue to claim");
+        self.internal_payout(account_id.clone(), amount);
+
+        env::log_str(
+            json!({
+                "type": "claim_revenue",
+                "params": {
+                    "account_id": account_id,
+                    "amount": U128(amount),
+                }
+            }).to_string().as_str()
+        );
+        amount.into()
+    }
+
+    pub fn get_claimable(&self, account_id: AccountId) -> U128 {
+        self.claimable_revenue.get(&account_id).unwrap_or(0).into()
+    }
+
+    /// Withdraw the unclaimed proceeds of a series. Creator only.
+    #[payable]
+    pub fn withdraw_series_earnings(&mut self, token_series_id: TokenSeriesId) -> U128 {
+        assert_one_yocto();
+        let creator_id = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("FireFly: Token series not exist")
+            .creator_id;
+        assert_eq!(env::predecessor_account_id(), creator_id, "FireFly: Creator only");
+        let mut earnings = self.series_earnings.get(&token_series_id).unwrap_or_default();
+        let amount = earnings.unclaimed.0;
+        assert!(amount > 0, "FireFly: No earnings to withdraw");
+        earnings.unclaimed = U128(0);
+        self.series_earnings.insert(&token_series_id, &earnings);
+        self.internal_payout(creator_id.clone(), amount);
+
+        env::log_str(
+            json!({
+                "type": "withdraw_series_earnings",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "creator_id": creator_id,
+                    "amount": U128(amount),
+                }
+            }).to_string().as_str()
+        );
+        amount.into()
+    }
+
+    pub fn get_series_earnings(&self, token_series_id: TokenSeriesId) -> SeriesEarnings {
+        self.series_earnings.get(&token_series_id).unwrap_or_default()
+    }
+}
+
+impl Contract {
+    /// Owe `amount` to `account_id`, to be withdrawn with `claim_revenue`.
+    pub(crate) fn internal_credit_revenue(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+        let claimable = self.claimable_revenue.get(account_id).unwrap_or(0);
+        self.claimable_revenue.insert(account_id, &(claimable + amount));
+    }
+
+    /// Count a paid mint of `price` towards the earnings of a series, `proceeds` being what
+    /// is left of it for the creator after fees.
+    pub(crate) fn internal_record_series_sale(
+        &mut self,
+        token_series_id: &TokenSeriesId,
+        price: Balance,
+        proceeds: Balance,
+    ) {
+        let mut earnings = self.series_earnings.get(token_series_id).unwrap_or_default();
+        earnings.sales_volume = U128(earnings.sales_volume.0 + price);
+        earnings.paid_mints = U64(earnings.paid_mints.0 + 1);
+        earnings.unclaimed = U128(earnings.unclaimed.0 + proceeds);
+        self.series_earnings.insert(token_series_id, &earnings);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use crate::admin::{AdminAction, DEFAULT_ADMIN_DELAY};
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    fn setup_sale(context: &mut VMContextBuilder) -> (Contract, TokenSeriesId, Balance) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(0)).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetReferralFee { referral_fee: 1_000 });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let price = 10u128.pow(24);
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(price)), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(price + MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_buy(token_series.token_series_id.clone(), accounts(2), U128(price), Some(accounts(3)));
+        (contract, token_series.token_series_id, price)
+    }
+
+    #[test]
+    fn test_referral_fee_is_claimable() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, _, price) = setup_sale(&mut context);
+        assert_eq!(contract.get_claimable(accounts(3)), U128(price / 10));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(3)).build());
+        assert_eq!(contract.claim_revenue(), U128(price / 10));
+        assert_eq!(contract.get_claimable(accounts(3)), U128(0));
+    }
+
+    #[test]
+    fn test_withdraw_series_earnings() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_series_id, price) = setup_sale(&mut context);
+        let earnings = contract.get_series_earnings(token_series_id.clone());
+        assert_eq!(earnings.sales_volume, U128(price));
+        assert_eq!(earnings.paid_mints, U64(1));
+        assert_eq!(earnings.unclaimed, U128(price - price / 10));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        assert_eq!(contract.withdraw_series_earnings(token_series_id.clone()), U128(price - price / 10));
+        assert_eq!(contract.get_series_earnings(token_series_id).unclaimed, U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: No revenue to claim")]
+    fn test_claim_without_revenue() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.claim_revenue();
+    }
+}
@@ -0,0 +1,330 @@
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::assert_valid_royalty;
+use crate::token::TokenId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Royalty shares are expressed in basis points of the sale balance.
+pub const ROYALTY_DENOMINATOR: u32 = 10_000;
+
+/// Payout map as defined by the [payouts standard](https://nomicon.io/Standards/Tokens/NonFungibleToken/Payout).
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Payout {
+    pub payout: HashMap<AccountId, U128>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// How `balance` from a sale of `token_id` is split between the royalty holders and the
+    /// owner. Panics if that takes more than `max_len_payout` receivers.
+    pub fn nft_payout(&self, token_id: TokenId, balance: U128, max_len_payout: Option<u32>) -> Payout {
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        self.internal_compute_payout(&token_id, &owner_id, balance.0, max_len_payout)
+    }
+
+    /// Transfer `token_id` like `nft_transfer` and return the payout for `balance` computed
+    /// for the owner the token was taken from, so a marketplace can settle the sale.
+    #[payable]
+    pub fn nft_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        assert_one_yocto();
+        self.assert_transfers_not_paused();
+        self.assert_receiver_allowed(&receiver_id);
+        self.assert_transfer_not_banned(&token_id, &receiver_id);
+        self.assert_not_soulbound(&token_id);
+        self.assert_not_locked(&token_id);
+        self.internal_clear_rental(&token_id);
+        self.internal_record_transfer(&token_id);
+        let sender_id = env::predecessor_account_id();
+        let (previous_owner_id, approvals) =
+            self.tokens.internal_transfer(&sender_id, &receiver_id, &token_id, approval_id, memo);
+        if let Some(approvals) = approvals {
+            self.tokens.internal_refund_approvals(&token_id, previous_owner_id.clone(), &approvals);
+        }
+        self.internal_compute_payout(&token_id, &previous_owner_id, balance.0, max_len_payout)
+    }
+
+    /// Payout a sale of `token_id` for `balance` would produce, without transferring anything.
+    /// Lets marketplaces show the royalty breakdown before executing the sale.
+    pub fn nft_payout_preview(
+        &self,
+        token_id: TokenId,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        self.internal_compute_payout(&token_id, &owner_id, balance.0, max_len_payout)
+    }
+
+    /// Give one token, e.g. a 1/1 within a series, its own royalty in place of the series
+    /// royalty, or with `None` go back to the series royalty. Series creator only, and only
+    /// while the creator still holds the token and it was never transferred, so buyers know
+    /// the royalty they buy into. Transfers before state version 5 weren't recorded.
+    #[payable]
+    pub fn nft_set_token_royalty(&mut self, token_id: TokenId, royalty: Option<HashMap<AccountId, u32>>) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let creator_id = self
+            .token_series_by_id
+            .get(&self.tokens.token_id_format.token_series_id_of(&token_id).to_string())
+            .expect("FireFly: Token series not exist")
+            .creator_id;
+        assert_eq!(env::predecessor_account_id(), creator_id, "FireFly: Creator only");
+        let owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        assert_eq!(owner_id, creator_id, "FireFly: Token royalty can only change while the creator holds the token");
+        assert!(
+            !self.transferred_token_ids.contains(&token_id),
+            "FireFly: Token royalty can't change once the token was transferred"
+        );
+
+        match &royalty {
+            Some(royalty) => {
+                assert_valid_royalty(royalty, self.max_royalty, self.max_royalty_accounts);
+                self.token_royalties.insert(&token_id, royalty);
+            }
+            None => {
+                self.token_royalties.remove(&token_id);
+            }
+        }
+
+        env::log_str(
+            json!({
+                "type": "nft_set_token_royalty",
+                "params": {
+                    "token_id": token_id,
+                    "royalty": royalty,
+                }
+            }).to_string().as_str()
+        );
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(creator_id).transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+    }
+
+    /// Royalty paid on sales of `token_id`: its own if it has one, else the series royalty.
+    pub fn nft_token_royalty(&self, token_id: TokenId) -> HashMap<AccountId, u32> {
+        self.internal_royalty_of(&token_id)
+    }
+}
+
+impl Contract {
+    /// Remember that `token_id` is leaving its owner, which freezes its royalty. The contract
+    /// pays for the entry, transfers only attach one yoctoNEAR.
+    pub(crate) fn internal_record_transfer(&mut self, token_id: &TokenId) {
+        if !self.transferred_token_ids.contains(token_id) {
+            self.transferred_token_ids.insert(token_id);
+        }
+    }
+
+    pub(crate) fn internal_royalty_of(&self, token_id: &TokenId) -> HashMap<AccountId, u32> {
+        self.token_royalties.get(token_id).unwrap_or_else(|| {
+            self.token_series_by_id
+                .get(&self.tokens.token_id_format.token_series_id_of(token_id).to_string())
+                .map(|token_series| token_series.royalty)
+                .unwrap_or_default()
+        })
+    }
+
+    /// Split `balance` between the royalty holders of the token, see `nft_token_royalty`, the
+    /// current owner receives the rest.
+    pub(crate) fn internal_compute_payout(
+        &self,
+        token_id: &TokenId,
+        owner_id: &AccountId,
+        balance: Balance,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        let royalty = self.internal_royalty_of(token_id);
+
+        if let Some(max_len_payout) = max_len_payout {
+            let payout_len = royalty.len() + usize::from(!royalty.contains_key(owner_id));
+            assert!(
+                payout_len <= max_len_payout as usize,
+                "FireFly: Market cannot payout to that many receivers"
+            );
+        }
+
+        let mut payout = HashMap::new();
+        let mut total_royalty: Balance = 0;
+        for (account_id, basis_points) in royalty.iter() {
+            if account_id == owner_id {
+                continue;
+            }
+            let amount = balance * *basis_points as u128 / ROYALTY_DENOMINATOR as u128;
+            total_royalty += amount;
+            payout.insert(account_id.clone(), U128(amount));
+        }
+        payout.insert(owner_id.clone(), U128(balance - total_royalty));
+
+        Payout { payout }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{env, testing_env};
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    fn test_payout_preview() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.default_royalty = 500;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        testing_env!(context.is_view(true).attached_deposit(0).build());
+        let payout = contract.nft_payout_preview(token.token_id, U128(1_000), Some(2));
+        assert_eq!(
+            payout.payout,
+            HashMap::from([(accounts(1), U128(50)), (accounts(2), U128(950))])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Market cannot payout to that many receivers")]
+    fn test_payout_preview_too_many_receivers() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.default_royalty = 500;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        testing_env!(context.is_view(true).attached_deposit(0).build());
+        contract.nft_payout_preview(token.token_id, U128(1_000), Some(1));
+    }
+
+    #[test]
+    fn test_transfer_payout() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.default_royalty = 1_000;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let payout = contract.nft_transfer_payout(accounts(3), token.token_id.clone(), None, None, U128(1_000), Some(10));
+        assert_eq!(
+            payout.payout,
+            HashMap::from([(accounts(1), U128(100)), (accounts(2), U128(900))])
+        );
+        assert!(contract.nft_is_owner(token.token_id.clone(), accounts(3)));
+        assert_eq!(
+            contract.nft_payout(token.token_id, U128(1_000), None).payout,
+            HashMap::from([(accounts(1), U128(100)), (accounts(3), U128(900))])
+        );
+    }
+
+    #[test]
+    fn test_token_royalty() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.default_royalty = 1_000;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+        contract.nft_set_token_royalty(token.token_id.clone(), Some(HashMap::from([(accounts(4), 2_000)])));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.nft_transfer(accounts(2), token.token_id.clone(), None, None);
+        assert_eq!(
+            contract.nft_payout(token.token_id, U128(1_000), None).payout,
+            HashMap::from([(accounts(4), U128(200)), (accounts(2), U128(800))])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token royalty can only change while the creator holds the token")]
+    fn test_token_royalty_after_transfer() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+        contract.nft_set_token_royalty(token.token_id, Some(HashMap::from([(accounts(4), 2_000)])));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token royalty can't change once the token was transferred")]
+    fn test_token_royalty_after_buy_back() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(1));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.nft_transfer(accounts(2), token.token_id.clone(), None, None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.nft_transfer(accounts(1), token.token_id.clone(), None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_set_token_royalty(token.token_id, Some(HashMap::from([(accounts(4), 2_000)])));
+    }
+}
@@ -1,20 +1,49 @@
 use crate::{Contract, StorageKey};
 use crate::ContractExt;
 use crate::metadata::TokenMetadata;
-use crate::token::TokenId;
-use crate::utils::refund_deposit;
-use near_sdk::collections::UnorderedSet;
+use crate::migrate::assert_not_migrating;
+use crate::nft_core::NonFungibleTokenCore;
+use crate::token::{Token, TokenId};
+use crate::events::NftMetadataUpdate;
+use crate::series_events::{
+    SeriesAcceptCreator, SeriesCreate, SeriesDelete, SeriesFreezeMetadata, SeriesTransferCreator,
+    SeriesUpdateBaseUri, SeriesUpdateCopies, SeriesUpdateMaxMintsPerWallet, SeriesUpdateMetadata,
+    SeriesUpdateMintWindow, SeriesUpdateMintable, SeriesUpdatePrice, SeriesUpdateRoyalty,
+};
+use crate::generative::{attributes_of, extra_with_attributes, TraitTable};
+use crate::utils::{assert_at_least_one_yocto, refund_deposit, yocto_to_near_string};
+use near_sdk::collections::{LookupMap, UnorderedSet};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{AccountId, Balance, near_bindgen, env};
+use std::collections::HashMap;
+use std::ops::Bound;
+use near_sdk::{assert_one_yocto, AccountId, Balance, near_bindgen, env, Promise};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::json_types::U128;
-use serde_json::json;
+use near_sdk::json_types::{Base64VecU8, U128, U64};
 
-const MAX_PRICE: Balance = 1_000_000_000 * 10u128.pow(24);
+/// Longest `base_uri` a series can set.
+pub const MAX_BASE_URI_LEN: usize = 256;
+/// Absolute ceiling for the configurable `max_price`, also its default.
+pub const MAX_PRICE: Balance = 1_000_000_000 * 10u128.pow(24);
+/// Royalties are in basis points, a series may never take more than 90% of a sale.
+pub const MAX_ROYALTY: u32 = 9000;
+pub const MAX_ROYALTY_ACCOUNTS: u32 = 50;
+/// Most tokens one call rewrites to replaced series metadata, and lists in one
+/// `nft_metadata_update`.
+pub const MAX_METADATA_REFRESH_TOKENS: u64 = 100;
+const TITLE_INDEX_DELIMITER: char = '\u{0}';
 
 /// Note that token IDs for NFTs are strings on NEAR. It's still fine to use autoincrementing numbers as unique IDs if desired, but they should be stringified. This is to make IDs more future-proof as chain-agnostic conventions and standards arise, and allows for more flexibility with considerations like bridging NFTs across chains, etc.
 pub type TokenSeriesId = String;
 
+/// Tokens of a series still to be rewritten to its replaced metadata, rewritten from
+/// `next_index` of its `tokens` on by `nft_refresh_series_metadata`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MetadataRefresh {
+    pub next_index: u64,
+    /// Editions given their own metadata by `nft_reveal_series`.
+    pub edition_metadata: HashMap<u64, TokenMetadata>,
+}
+
 /// In this implementation, the Token struct takes two extensions standards (metadata and approval) as optional fields, as they are frequently used in modern NFTs.
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct TokenSeries {
@@ -23,7 +52,114 @@ pub struct TokenSeries {
 	pub tokens: UnorderedSet<TokenId>,
     pub price: Option<Balance>,
     pub is_mintable: bool,
-    // royalty: HashMap<AccountId, u32>
+    pub royalty: HashMap<AccountId, u32>,
+    /// Editions are multi-token balances, see `multi_token`.
+    pub is_multi_token: bool,
+    /// Editions burned since minting, they no longer are in `tokens`.
+    pub burned: u64,
+    /// Block timestamps (nanoseconds) minting opens and closes at, unbounded when `None`.
+    pub mint_start: Option<u64>,
+    pub mint_end: Option<u64>,
+    /// Most editions a single receiver may be minted through sales, unlimited when `None`.
+    pub max_mints_per_wallet: Option<u64>,
+    /// Tokens of a soulbound series stay with the account they were minted to, e.g. badges.
+    pub is_soulbound: bool,
+    /// Once set the metadata of the series and its tokens can no longer change.
+    pub metadata_frozen: bool,
+    /// Set once the placeholder metadata was replaced, see `reveal`.
+    pub is_revealed: bool,
+    /// sha256 committed to at creation, e.g. of the revealed artwork in edition order.
+    pub provenance_hash: Option<Base64VecU8>,
+    /// Account `creator_id` was offered to, it takes over once it accepts.
+    pub pending_creator_id: Option<AccountId>,
+    /// Makes the price change over time instead of staying at `price`.
+    pub price_curve: Option<PriceCurve>,
+    /// Set when editions are drawn at random, see `nft_set_series_random_editions`. Holds
+    /// the editions not drawn yet by position, a missing position holding the edition after it.
+    pub edition_pool: Option<LookupMap<u64, u64>>,
+    /// Traits rolled for each token minted, see `generative`.
+    pub trait_tables: Option<Vec<TraitTable>>,
+    /// Gateway relative `media` of the series resolves against, instead of the contract's.
+    pub base_uri: Option<String>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PriceCurve {
+    /// Starts at `start_price` and steps down every `decay_interval` nanoseconds, linearly,
+    /// until it reaches `end_price` at `ends_at`.
+    DutchAuction {
+        start_price: U128,
+        end_price: U128,
+        starts_at: U64,
+        ends_at: U64,
+        decay_interval: U64,
+    },
+    /// `base_price` plus `increment` for every edition already minted.
+    Linear {
+        base_price: U128,
+        increment: U128,
+    },
+    /// `base_price` compounded by `growth_bps` basis points for every edition already minted.
+    Exponential {
+        base_price: U128,
+        growth_bps: u32,
+    },
+}
+
+impl PriceCurve {
+    /// Price of the next edition at `timestamp` once `minted` editions were minted.
+    pub fn price_at(&self, timestamp: u64, minted: u64) -> Balance {
+        match self {
+            PriceCurve::DutchAuction { start_price, end_price, starts_at, ends_at, decay_interval } => {
+                let total_steps = (ends_at.0 - starts_at.0) / decay_interval.0;
+                let steps = timestamp.saturating_sub(starts_at.0) / decay_interval.0;
+                if total_steps == 0 || steps >= total_steps {
+                    return end_price.0;
+                }
+                // steps < total_steps, so the remainder term can't overflow
+                let decay = start_price.0 - end_price.0;
+                let (steps, total_steps) = (Balance::from(steps), Balance::from(total_steps));
+                start_price.0 - (decay / total_steps * steps + decay % total_steps * steps / total_steps)
+            }
+            PriceCurve::Linear { base_price, increment } => {
+                base_price.0.saturating_add(increment.0.saturating_mul(Balance::from(minted)))
+            }
+            PriceCurve::Exponential { base_price, growth_bps } => {
+                let mut price = base_price.0;
+                // once above the absolute ceiling nobody can buy anyway
+                for _ in 0..minted {
+                    if price >= MAX_PRICE {
+                        break;
+                    }
+                    price += price * Balance::from(*growth_bps) / 10_000;
+                }
+                price
+            }
+        }
+    }
+
+    fn assert_valid(&self, max_price: Balance) {
+        match self {
+            PriceCurve::DutchAuction { start_price, end_price, starts_at, ends_at, decay_interval } => {
+                assert!(start_price.0 < max_price, "FireFly: price higher than {}", max_price);
+                assert!(start_price.0 >= end_price.0, "FireFly: start_price must be at least end_price");
+                assert!(starts_at.0 < ends_at.0, "FireFly: starts_at must be before ends_at");
+                assert!(decay_interval.0 > 0, "FireFly: decay_interval must be positive");
+            }
+            PriceCurve::Linear { base_price, .. } | PriceCurve::Exponential { base_price, .. } => {
+                assert!(base_price.0 < max_price, "FireFly: price higher than {}", max_price);
+            }
+        }
+    }
+
+    /// Price the curve starts from, stored as the series `price`.
+    fn start_price(&self) -> Balance {
+        match self {
+            PriceCurve::DutchAuction { start_price, .. } => start_price.0,
+            PriceCurve::Linear { base_price, .. } | PriceCurve::Exponential { base_price, .. } => base_price.0,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -32,190 +168,2156 @@ pub struct TokenSeriesJson {
     pub token_series_id: TokenSeriesId,
 	pub metadata: TokenMetadata,
 	pub creator_id: AccountId,
-    // royalty: HashMap<AccountId, u32>,
+    pub royalty: HashMap<AccountId, u32>,
     // transaction_fee: U128
 }
 
+impl TokenSeries {
+    pub fn to_json(self, token_series_id: TokenSeriesId) -> TokenSeriesJson {
+        TokenSeriesJson {
+            token_series_id,
+            metadata: self.metadata,
+            creator_id: self.creator_id,
+            royalty: self.royalty,
+        }
+    }
+
+    /// Price a buyer pays for the next edition right now, after `minted` editions, `None` if
+    /// the series isn't for sale.
+    pub fn current_price(&self, minted: u64) -> Option<Balance> {
+        self.price?;
+        match &self.price_curve {
+            Some(price_curve) => Some(price_curve.price_at(env::block_timestamp(), minted)),
+            None => self.price,
+        }
+    }
+
+    /// Panics outside of the mint window of the series.
+    pub fn assert_in_mint_window(&self) {
+        let now = env::block_timestamp();
+        if let Some(mint_start) = self.mint_start {
+            assert!(now >= mint_start, "FireFly: Minting has not started");
+        }
+        if let Some(mint_end) = self.mint_end {
+            assert!(now < mint_end, "FireFly: Minting has ended");
+        }
+    }
+}
+
+/// Series price in yoctoNEAR and as a decimal NEAR string, so front-ends don't have to convert.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenSeriesPriceJson {
+    pub token_series_id: TokenSeriesId,
+    pub price: Option<U128>,
+    pub price_near: Option<String>,
+    pub effective_price: Option<U128>,
+    pub effective_price_near: Option<String>,
+}
+
+/// What a mint page needs to show how far a series has sold.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenSeriesMintProgressJson {
+    pub minted: U64,
+    /// `None` when the series has no copies cap
+    pub copies: Option<U64>,
+    pub is_mintable: bool,
+    /// Price of the next edition, following the series price curve
+    pub price: Option<U128>,
+}
+
+/// Full state of a single series, see `nft_get_series_single`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenSeriesDetailJson {
+    pub token_series_id: TokenSeriesId,
+    pub metadata: TokenMetadata,
+    pub creator_id: AccountId,
+    pub royalty: HashMap<AccountId, u32>,
+    pub price: Option<U128>,
+    pub is_mintable: bool,
+    pub minted: U64,
+    /// `None` when the series has no copies cap
+    pub remaining_copies: Option<U64>,
+    pub mint_start: Option<U64>,
+    pub mint_end: Option<U64>,
+    pub max_mints_per_wallet: Option<U64>,
+    /// Basis points of each sale kept as transaction fee
+    pub transaction_fee: U128,
+    pub is_soulbound: bool,
+    pub metadata_frozen: bool,
+    pub is_revealed: bool,
+    pub provenance_hash: Option<Base64VecU8>,
+    pub pending_creator_id: Option<AccountId>,
+    pub price_curve: Option<PriceCurve>,
+    pub random_editions: bool,
+    pub base_uri: Option<String>,
+}
+
+/// A token together with what frontends usually look up on its series, see `nft_token_detail`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenDetailJson {
+    pub token: Token,
+    pub token_series_id: TokenSeriesId,
+    /// Parsed from the `"{token_series_id}:{edition}"` token id
+    pub edition: Option<U64>,
+    pub creator_id: AccountId,
+    pub royalty: HashMap<AccountId, u32>,
+    /// Price of the next edition of the series, `None` if it isn't for sale.
+    pub price: Option<U128>,
+}
+
+/// Optional settings of a new series, see `nft_create_series`.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeriesOptions {
+    /// The contract's default royalty applies when `None`.
+    pub royalty: Option<HashMap<AccountId, u32>>,
+    pub is_soulbound: Option<bool>,
+    pub provenance_hash: Option<Base64VecU8>,
+}
+
+/// Optional per-field replacements applied by `nft_clone_series`, anything left as `None` is copied from the source series.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenSeriesOverrides {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub media: Option<String>,
+    pub media_hash: Option<Base64VecU8>,
+    pub copies: Option<u64>,
+    pub extra: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<Base64VecU8>,
+    pub price: Option<U128>,
+    pub royalty: Option<HashMap<AccountId, u32>>,
+}
+
+/// Lowercase a title and collapse whitespace so searches don't depend on formatting.
+pub(crate) fn normalize_title(title: &str) -> String {
+    title.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Key of a series in the title index, the id keeps series sharing a title apart.
+pub(crate) fn title_index_key(title: &str, token_series_id: &str) -> String {
+    format!("{}{}{}", normalize_title(title), TITLE_INDEX_DELIMITER, token_series_id)
+}
+
+pub(crate) fn assert_valid_price(price: Option<U128>, max_price: Balance) -> Option<Balance> {
+    price.map(|price| {
+        assert!(
+            price.0 < max_price,
+            "FireFly: price higher than {}",
+            max_price
+        );
+        price.0
+    })
+}
+
+/// Royalties may be split between at most `max_accounts` accounts and add up to at most
+/// `max_royalty` basis points, the limits configured on the contract.
+pub(crate) fn assert_valid_royalty(royalty: &HashMap<AccountId, u32>, max_royalty: u32, max_accounts: u32) {
+    assert!(
+        royalty.len() <= max_accounts as usize,
+        "FireFly: royalty exceeds {} accounts",
+        max_accounts
+    );
+    let total: u32 = royalty.values().fold(0u32, |total, basis_points| total.saturating_add(*basis_points));
+    assert!(total <= max_royalty, "FireFly: Exceeds maximum royalty -> {}", max_royalty);
+}
+
 #[near_bindgen]
 impl Contract{
+    /// Create a series owned by the caller. Tokens of an `is_soulbound` series can't be
+    /// transferred or approved once minted. `provenance_hash` can't be changed later.
     #[payable]
     pub fn nft_create_series(
         &mut self,
         token_metadata: TokenMetadata,
         price: Option<U128>,
-        // royalty: Option<HashMap<AccountId, u32>>,
+        options: Option<SeriesOptions>,
     ) -> TokenSeriesJson {
         let initial_storage_usage = env::storage_usage();
-        let caller_id = env::predecessor_account_id();
+        let options = options.unwrap_or_default();
 
-        let token_series_id = (self.token_series_by_id.len() + 1).to_string();
+        let title = token_metadata.title.clone();
+        assert!(title.is_some(), "FireFly: token_metadata.title is required");
+        if let Some(royalty) = &options.royalty {
+            assert_valid_royalty(royalty, self.max_royalty, self.max_royalty_accounts);
+        }
 
-        assert!(
-            self.token_series_by_id.get(&token_series_id).is_none(),
-            "FireFly: duplicate token_series_id"
+        let price_res = assert_valid_price(price, self.max_price);
+        if let Some(provenance_hash) = &options.provenance_hash {
+            assert!(provenance_hash.0.len() == 32, "FireFly: provenance_hash has to be 32 bytes");
+        }
+        let token_series = self.internal_create_series(token_metadata, price_res, options);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+
+        token_series
+    }
+
+    /// Create a new series owned by the caller from the settings of `source_series_id`,
+    /// replacing only the fields given in `overrides`. Metadata, price or price curve,
+    /// royalty, soulbinding, mint window, per-wallet cap, `base_uri` and tags are copied; a
+    /// `price` override drops the price curve. Only the creator of the source series may
+    /// clone it.
+    #[payable]
+    pub fn nft_clone_series(
+        &mut self,
+        source_series_id: TokenSeriesId,
+        overrides: Option<TokenSeriesOverrides>,
+    ) -> TokenSeriesJson {
+        let initial_storage_usage = env::storage_usage();
+        let overrides = overrides.unwrap_or_default();
+
+        let source = self.token_series_by_id.get(&source_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            source.creator_id,
+            "FireFly: Creator only"
         );
 
-        let title = token_metadata.title.clone();
-        assert!(title.is_some(), "FireFly: token_metadata.title is required");
-        
-        // let mut total_perpetual = 0;
-        // let mut total_accounts = 0;
-        // let royalty_res: HashMap<AccountId, u32> = if let Some(royalty) = royalty {
-        //     for (k , v) in royalty.iter() {
-        //         if !is_valid_account_id(k.as_bytes()) {
-        //             env::panic("Not valid account_id for royalty".as_bytes());
-        //         };
-        //         total_perpetual += *v;
-        //         total_accounts += 1;
-        //     }
-        //     royalty
-        // } else {
-        //     HashMap::new()
-        // };
-
-        // assert!(total_accounts <= 50, "Paras: royalty exceeds 50 accounts");
-
-        // assert!(
-        //     total_perpetual <= 9000,
-        //     "Paras Exceeds maximum royalty -> 9000",
-        // );
-
-        let price_res: Option<u128> = if price.is_some() {
-            assert!(
-                price.unwrap().0 < MAX_PRICE,
-                "FireFly: price higher than {}",
-                MAX_PRICE
-            );
-            Some(price.unwrap().0)
-        } else {
-            None
+        let source_metadata = source.metadata;
+        let token_metadata = TokenMetadata {
+            title: overrides.title.or(source_metadata.title),
+            description: overrides.description.or(source_metadata.description),
+            media: overrides.media.or(source_metadata.media),
+            media_hash: overrides.media_hash.or(source_metadata.media_hash),
+            copies: overrides.copies.or(source_metadata.copies),
+            extra: overrides.extra.or(source_metadata.extra),
+            reference: overrides.reference.or(source_metadata.reference),
+            reference_hash: overrides.reference_hash.or(source_metadata.reference_hash),
+            ..source_metadata
         };
 
-        self.token_series_by_id.insert(&token_series_id, &TokenSeries{
-            metadata: token_metadata.clone(),
-            creator_id: caller_id.clone(),
-            tokens: UnorderedSet::new(
-                StorageKey::TokensBySeriesInner {
-                    token_series: token_series_id.clone(),
-                }
-                .try_to_vec()
-                .unwrap(),
-            ),
-            price: price_res,
-            is_mintable: true,
-            // royalty: royalty_res.clone(),
-        });
+        let (price_res, price_curve) = match overrides.price {
+            Some(price) => (assert_valid_price(Some(price), self.max_price), None),
+            None => (source.price, source.price_curve),
+        };
+        if let Some(royalty) = &overrides.royalty {
+            assert_valid_royalty(royalty, self.max_royalty, self.max_royalty_accounts);
+        }
+        let options = SeriesOptions {
+            royalty: Some(overrides.royalty.unwrap_or(source.royalty)),
+            is_soulbound: Some(source.is_soulbound),
+            provenance_hash: None,
+        };
+        let token_series = self.internal_create_series(token_metadata, price_res, options);
 
-        // set market data transaction fee (need to understand)
-        // let current_transaction_fee = self.calculate_current_transaction_fee();
-        // self.market_data_transaction_fee.insert(&token_series_id, &current_transaction_fee);
-
-        env::log_str(
-            json!({
-                "type": "nft_create_series",
-                "params": {
-                    "token_series_id": token_series_id,
-                    "token_metadata": token_metadata,
-                    "creator_id": caller_id.clone(),
-                    "price": price
-                    // "royalty": royalty_res,
-                    // "transaction_fee": &current_transaction_fee.to_string()
-                }
-            }).to_string().as_str()
-        );
+        let mut clone = self.token_series_by_id.get(&token_series.token_series_id).unwrap();
+        clone.price_curve = price_curve;
+        clone.mint_start = source.mint_start;
+        clone.mint_end = source.mint_end;
+        clone.max_mints_per_wallet = source.max_mints_per_wallet;
+        clone.base_uri = source.base_uri;
+        self.token_series_by_id.insert(&token_series.token_series_id, &clone);
+        let tags = self.tags_by_series.get(&source_series_id).unwrap_or_default();
+        self.internal_index_series_tags(&token_series.token_series_id, &tags);
 
         refund_deposit(env::storage_usage() - initial_storage_usage);
 
-		TokenSeriesJson{
-            token_series_id,
-			metadata: token_metadata,
-			creator_id: caller_id.into(),
-            // royalty: royalty_res,
-            // transaction_fee: current_transaction_fee.into()
-		}
+        token_series
     }
 
-    /**
-    Get list of all TokenSeries
-    */
-    pub fn nft_series_for_all(&self) -> Vec<TokenSeriesJson>{
-        self.token_series_by_id
-        .iter()
-        .map(|se| {
-            TokenSeriesJson{
-                token_series_id: se.0,
-                metadata: se.1.metadata,
-                creator_id: se.1.creator_id,
-            }
-        }).collect()
-        
+    /// Buy the next edition of a series for `receiver_id`. The attached deposit must cover the
+    /// current price plus storage; the price goes to the series creator and the rest is refunded.
+    /// Reverts, refunding the whole deposit, if the current price is above `max_price`.
+    /// `referral_id` is paid the referral fee out of the creator's share.
+    #[payable]
+    pub fn nft_buy(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        receiver_id: AccountId,
+        max_price: U128,
+        referral_id: Option<AccountId>,
+    ) -> Token {
+        self.assert_allowlist_over(&token_series_id);
+        self.internal_buy(token_series_id, receiver_id, max_price, referral_id)
     }
 
-    // pub fn calculate_current_transaction_fee(&mut self) -> u128 {
-    //     let transaction_fee: &TransactionFee = &self.transaction_fee;
-    //     if transaction_fee.next_fee.is_some() {
-    //         if to_sec(env::block_timestamp()) >= transaction_fee.start_time.unwrap() {
-    //             self.transaction_fee.current_fee = transaction_fee.next_fee.unwrap();
-    //             self.transaction_fee.next_fee = None;
-    //             self.transaction_fee.start_time = None;
-    //         }
-    //     }
-    //     self.transaction_fee.current_fee as u128
-    // }
-}
+    /// Change or, with `None`, remove the price of a series. Creator only.
+    #[payable]
+    pub fn nft_set_series_price(&mut self, token_series_id: TokenSeriesId, price: Option<U128>) -> Option<U128> {
+        assert_one_yocto();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(token_series.is_mintable, "FireFly: Token series is not mintable");
 
-#[cfg(all(test, not(target_arch = "wasm32")))]
-mod tests {
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::testing_env;
-    use super::*;
+        token_series.price = assert_valid_price(price, self.max_price);
+        token_series.price_curve = None;
+        self.token_series_by_id.insert(&token_series_id, &token_series);
 
-    // const MINT_STORAGE_COST: u128 = 5870000000000000000000;
-    const MINT_STORAGE_COST: u128 = 5910000000000000000000;
+        SeriesUpdatePrice { token_series_id: &token_series_id, price, price_curve: None }.emit();
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .current_account_id(accounts(0))
-            .signer_account_id(predecessor_account_id.clone())
-            .predecessor_account_id(predecessor_account_id);
-        builder
+        price
     }
 
-    fn sample_token_metadata() -> TokenMetadata {
-        TokenMetadata {
-            title: Some("Olympus Mons".into()),
-            description: Some("The tallest mountain in the charted solar system".into()),
-            media: None,
-            media_hash: None,
-            copies: Some(1u64),
-            issued_at: None,
-            expires_at: None,
-            starts_at: None,
-            updated_at: None,
-            extra: None,
-            reference: None,
-            reference_hash: None,
+    /// Sell a series along a price curve, e.g. a Dutch auction or a bonding curve, or with `None` go back to the
+    /// fixed `price` it started from. Creator only. `nft_buy` charges the price at the time of
+    /// the purchase and refunds the rest of the deposit.
+    #[payable]
+    pub fn nft_set_series_price_curve(&mut self, token_series_id: TokenSeriesId, price_curve: Option<PriceCurve>) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(token_series.is_mintable, "FireFly: Token series is not mintable");
+
+        if let Some(price_curve) = &price_curve {
+            price_curve.assert_valid(self.max_price);
+            token_series.price = Some(price_curve.start_price());
+        }
+        token_series.price_curve = price_curve.clone();
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        SeriesUpdatePrice {
+            token_series_id: &token_series_id,
+            price: token_series.price.map(U128),
+            price_curve: price_curve.as_ref(),
         }
+        .emit();
     }
 
-    #[test]
-    fn test_create_series() {
-        let mut context = get_context(accounts(1));
-        testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(1).into());
+    /// Replace the royalty of a series, paid on every later sale of its tokens. Creator only,
+    /// the caller pays for extra storage.
+    #[payable]
+    pub fn nft_set_series_royalty(&mut self, token_series_id: TokenSeriesId, royalty: HashMap<AccountId, u32>) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert_valid_royalty(&royalty, self.max_royalty, self.max_royalty_accounts);
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(1))
-            .build());
+        token_series.royalty = royalty;
+        self.token_series_by_id.insert(&token_series_id, &token_series);
 
-        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128::from(0u128)));
-        assert_eq!(token_series.token_series_id, "1".to_string());
-        assert_eq!(token_series.creator_id.to_string(), accounts(1).to_string());
-        assert_eq!(token_series.metadata, sample_token_metadata());
+        SeriesUpdateRoyalty { token_series_id: &token_series_id, royalty: &token_series.royalty }.emit();
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(token_series.creator_id)
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+    }
+
+    /// Offer the creator role of a series, with control over its price, royalty, metadata and
+    /// minting, to `new_creator_id`. Nothing changes until they accept; offering again replaces
+    /// the previous offer. Creator only, the caller pays for the storage.
+    #[payable]
+    pub fn nft_transfer_series_creator(&mut self, token_series_id: TokenSeriesId, new_creator_id: AccountId) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert_ne!(new_creator_id, token_series.creator_id, "FireFly: Already the creator");
+
+        token_series.pending_creator_id = Some(new_creator_id.clone());
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        SeriesTransferCreator {
+            token_series_id: &token_series_id,
+            creator_id: &token_series.creator_id,
+            new_creator_id: &new_creator_id,
+        }
+        .emit();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Become the creator of a series offered to the caller with `nft_transfer_series_creator`.
+    #[payable]
+    pub fn nft_accept_series_creator(&mut self, token_series_id: TokenSeriesId) {
+        assert_one_yocto();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let new_creator_id = env::predecessor_account_id();
+        assert_eq!(
+            token_series.pending_creator_id.as_ref(),
+            Some(&new_creator_id),
+            "FireFly: Pending creator only"
+        );
+
+        let previous_creator_id = std::mem::replace(&mut token_series.creator_id, new_creator_id.clone());
+        token_series.pending_creator_id = None;
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        SeriesAcceptCreator {
+            token_series_id: &token_series_id,
+            previous_creator_id: &previous_creator_id,
+            creator_id: &new_creator_id,
+        }
+        .emit();
+    }
+
+    /// Permanently stop minting from a series, guaranteeing collectors its current supply.
+    /// Creator only, cannot be undone.
+    #[payable]
+    pub fn nft_set_series_non_mintable(&mut self, token_series_id: TokenSeriesId) {
+        assert_one_yocto();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(token_series.is_mintable, "FireFly: Token series is already non-mintable");
+
+        token_series.is_mintable = false;
+        token_series.price = None;
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        SeriesUpdateMintable {
+            token_series_id: &token_series_id,
+            is_mintable: false,
+            copies: token_series.metadata.copies.map(U64),
+        }
+        .emit();
+    }
+
+    /// Stop minting from a series for good and cap its copies at the editions minted so far,
+    /// e.g. after an aborted drop. Creator only, cannot be undone. Returns the final copies.
+    #[payable]
+    pub fn nft_close_series(&mut self, token_series_id: TokenSeriesId) -> U64 {
+        assert_one_yocto();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        let minted = self.internal_minted_count(&token_series_id, &token_series);
+        assert!(
+            token_series.is_mintable || token_series.metadata.copies != Some(minted),
+            "FireFly: Token series is already closed"
+        );
+        self.assert_not_auctioned(&token_series_id);
+
+        token_series.is_mintable = false;
+        token_series.price = None;
+        token_series.price_curve = None;
+        token_series.metadata.copies = Some(minted);
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+        if let Some(mut open_edition) = self.open_editions.get(&token_series_id) {
+            open_edition.final_supply = Some(U64(minted));
+            self.open_editions.insert(&token_series_id, &open_edition);
+        }
+
+        SeriesUpdateMintable { token_series_id: &token_series_id, is_mintable: false, copies: Some(U64(minted)) }.emit();
+
+        U64(minted)
+    }
+
+    /// Remove a series nothing was minted from, with its sale settings and tags. Creator
+    /// only. Refunds the storage released.
+    #[payable]
+    pub fn nft_delete_series(&mut self, token_series_id: TokenSeriesId) {
+        assert_one_yocto();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let creator_id = env::predecessor_account_id();
+        assert_eq!(creator_id, token_series.creator_id, "FireFly: Creator only");
+        assert_eq!(
+            self.internal_minted_count(&token_series_id, &token_series),
+            0,
+            "FireFly: Token series already has minted tokens"
+        );
+        self.assert_not_auctioned(&token_series_id);
+
+        let initial_storage_usage = env::storage_usage();
+        if let Some(title) = &token_series.metadata.title {
+            self.token_series_by_title.remove(&title_index_key(title, &token_series_id));
+        }
+        self.internal_unindex_series_tags(&token_series_id);
+        self.tags_by_series.remove(&token_series_id);
+        self.market_data_transaction_fee.remove(&token_series_id);
+        self.open_editions.remove(&token_series_id);
+        self.allowlists.remove(&token_series_id);
+        self.drops.remove(&token_series_id);
+        self.ft_prices.remove(&token_series_id);
+        self.staking_reward_rates.remove(&token_series_id);
+        self.series_earnings.remove(&token_series_id);
+        self.token_series_by_id.remove(&token_series_id);
+        self.internal_remove_series_id(&token_series_id);
+        self.deleted_series += 1;
+
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(creator_id.clone())
+                .transfer(Balance::from(storage_released) * env::storage_byte_cost());
+        }
+
+        SeriesDelete { token_series_id: &token_series_id, creator_id: &creator_id }.emit();
+    }
+
+    /// Open and close minting of a series automatically at the given block timestamps
+    /// (nanoseconds), `None` leaving that side unbounded. Creator only.
+    #[payable]
+    pub fn nft_set_series_mint_window(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        mint_start: Option<U64>,
+        mint_end: Option<U64>,
+    ) {
+        assert_one_yocto();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        if let (Some(mint_start), Some(mint_end)) = (mint_start, mint_end) {
+            assert!(mint_start.0 < mint_end.0, "FireFly: mint_start must be before mint_end");
+        }
+
+        token_series.mint_start = mint_start.map(|mint_start| mint_start.0);
+        token_series.mint_end = mint_end.map(|mint_end| mint_end.0);
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        SeriesUpdateMintWindow { token_series_id: &token_series_id, mint_start, mint_end }.emit();
+    }
+
+    /// Limit, or with `None` stop limiting, how many editions of a series a single account
+    /// can receive through sales. Creator only.
+    #[payable]
+    pub fn nft_set_series_max_mints_per_wallet(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        max_mints_per_wallet: Option<U64>,
+    ) {
+        assert_one_yocto();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        if let Some(max_mints_per_wallet) = max_mints_per_wallet {
+            assert!(max_mints_per_wallet.0 > 0, "FireFly: max_mints_per_wallet must be positive");
+        }
+
+        token_series.max_mints_per_wallet = max_mints_per_wallet.map(|max| max.0);
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        SeriesUpdateMaxMintsPerWallet { token_series_id: &token_series_id, max_mints_per_wallet }.emit();
+    }
+
+    /// Lower the `copies` of a series by `decrease_copies`, down to at least the number
+    /// already minted. Reaching that number closes the series. Creator only.
+    #[payable]
+    pub fn nft_decrease_series_copies(&mut self, token_series_id: TokenSeriesId, decrease_copies: U64) -> U64 {
+        assert_one_yocto();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(!token_series.metadata_frozen, "FireFly: Token series metadata is frozen");
+        let copies = token_series.metadata.copies.expect("FireFly: Token series has no copies cap");
+        let minted = self.internal_minted_count(&token_series_id, &token_series);
+        let new_copies = copies.saturating_sub(decrease_copies.0);
+        assert!(
+            new_copies >= minted,
+            "FireFly: copies cannot go below the {} minted",
+            minted
+        );
+
+        token_series.metadata.copies = Some(new_copies);
+        if new_copies == minted {
+            token_series.is_mintable = false;
+            token_series.price = None;
+        }
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        SeriesUpdateCopies { token_series_id: &token_series_id, copies: U64(new_copies) }.emit();
+        if new_copies == minted {
+            SeriesUpdateMintable { token_series_id: &token_series_id, is_mintable: false, copies: Some(U64(new_copies)) }
+                .emit();
+        }
+
+        U64(new_copies)
+    }
+
+    /// Replace the metadata of a series and of the editions already minted from it, e.g. to
+    /// fix a typo or a broken media link. `copies` can't change this way, see
+    /// `nft_decrease_series_copies`. Creator only, while the metadata isn't frozen. Only the
+    /// first `MAX_METADATA_REFRESH_TOKENS` editions are rewritten here, the others through
+    /// `nft_refresh_series_metadata`. The caller pays for extra storage; storage released by
+    /// shorter token metadata stays with the contract, the minters paid for it.
+    #[payable]
+    pub fn nft_update_series_metadata(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        token_metadata: TokenMetadata,
+    ) -> TokenSeriesJson {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(!token_series.metadata_frozen, "FireFly: Token series metadata is frozen");
+        assert!(token_metadata.title.is_some(), "FireFly: token_metadata.title is required");
+
+        let token_ids =
+            self.internal_replace_series_metadata(&token_series_id, &mut token_series, token_metadata, HashMap::new());
+
+        SeriesUpdateMetadata { token_series_id: &token_series_id, token_metadata: &token_series.metadata }.emit();
+        if !token_ids.is_empty() {
+            let token_ids: Vec<&str> = token_ids.iter().map(String::as_str).collect();
+            NftMetadataUpdate { token_ids: &token_ids, memo: None }.emit();
+        }
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        token_series.to_json(token_series_id)
+    }
+
+    /// Rewrite up to `limit` more editions of a series whose metadata was replaced, at most
+    /// `MAX_METADATA_REFRESH_TOKENS`. Anyone can call it until every edition carries the new
+    /// metadata, the caller pays for extra storage. Returns the tokens rewritten.
+    #[payable]
+    pub fn nft_refresh_series_metadata(&mut self, token_series_id: TokenSeriesId, limit: Option<u64>) -> Vec<TokenId> {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert!(
+            self.series_metadata_refresh.contains_key(&token_series_id),
+            "FireFly: Token series metadata is up to date"
+        );
+
+        let limit = limit.unwrap_or(MAX_METADATA_REFRESH_TOKENS).min(MAX_METADATA_REFRESH_TOKENS);
+        let token_ids = self.internal_refresh_series_metadata(&token_series_id, &token_series, limit);
+        if !token_ids.is_empty() {
+            let token_ids: Vec<&str> = token_ids.iter().map(String::as_str).collect();
+            NftMetadataUpdate { token_ids: &token_ids, memo: None }.emit();
+        }
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        token_ids
     }
 
+    /// Editions of a series `nft_refresh_series_metadata` still has to rewrite.
+    pub fn nft_series_metadata_refresh_remaining(&self, token_series_id: TokenSeriesId) -> U64 {
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let remaining = self
+            .series_metadata_refresh
+            .get(&token_series_id)
+            .map_or(0, |refresh| token_series.tokens.len().saturating_sub(refresh.next_index));
+        U64(remaining)
+    }
+
+    /// Permanently stop any change to the metadata of a series and its tokens, including
+    /// `copies`. Creator only.
+    #[payable]
+    pub fn nft_freeze_series_metadata(&mut self, token_series_id: TokenSeriesId) {
+        assert_one_yocto();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(!token_series.metadata_frozen, "FireFly: Token series metadata is frozen");
+        token_series.metadata_frozen = true;
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        SeriesFreezeMetadata { token_series_id: &token_series_id }.emit();
+    }
 
-}
\ No newline at end of file
+    /// Resolve the relative `media` of a series against `base_uri` rather than the contract's
+    /// `base_uri`, or go back to the contract's with `None`. Creator only, while the metadata
+    /// isn't frozen. The caller pays for the storage.
+    #[payable]
+    pub fn nft_set_series_base_uri(&mut self, token_series_id: TokenSeriesId, base_uri: Option<String>) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(!token_series.metadata_frozen, "FireFly: Token series metadata is frozen");
+        if let Some(base_uri) = &base_uri {
+            assert!(
+                !base_uri.is_empty() && base_uri.len() <= MAX_BASE_URI_LEN,
+                "FireFly: base_uri must be between 1 and {} characters",
+                MAX_BASE_URI_LEN
+            );
+        }
+        token_series.base_uri = base_uri.clone();
+        self.token_series_by_id.insert(&token_series_id, &token_series);
+
+        SeriesUpdateBaseUri { token_series_id: &token_series_id, base_uri: base_uri.as_deref() }.emit();
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /**
+    Get list of all TokenSeries
+    */
+    pub fn nft_series_for_all(&self) -> Vec<TokenSeriesJson>{
+        self.nft_series(None, None)
+    }
+
+    /// Get series in creation order, `limit` at a time. Deleting a series moves the most
+    /// recent one into its place.
+    pub fn nft_series(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<TokenSeriesJson> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        assert!(
+            (self.token_series_ids.len() as u128) >= start_index,
+            "FireFly: Out of bounds, please use a smaller from_index."
+        );
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        (start_index as u64..self.token_series_ids.len())
+            .take(limit)
+            .map(|index| {
+                let token_series_id = self.token_series_ids.get(index).unwrap();
+                self.token_series_by_id.get(&token_series_id).unwrap().to_json(token_series_id)
+            })
+            .collect()
+    }
+
+    pub fn nft_token_detail(&self, token_id: TokenId) -> Option<TokenDetailJson> {
+        let token = self.tokens.nft_token(token_id)?;
+        let token_series_id = self.tokens.token_id_format.token_series_id_of(&token.token_id).to_string();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let minted = self.internal_minted_count(&token_series_id, &token_series);
+        Some(TokenDetailJson {
+            edition: self.tokens.token_id_format.edition_of(&token.token_id).map(U64),
+            price: token_series.current_price(minted).map(U128),
+            creator_id: token_series.creator_id,
+            royalty: self.internal_royalty_of(&token.token_id),
+            token_series_id,
+            token,
+        })
+    }
+
+    /// Full URI of a token's `media`. Relative media is joined to the series `base_uri`, or
+    /// else the contract's; `None` for unknown tokens and tokens without media.
+    pub fn nft_token_uri(&self, token_id: TokenId) -> Option<String> {
+        let media = self.tokens.token_metadata(&token_id)?.media?;
+        if media.contains("://") || media.starts_with("data:") {
+            return Some(media);
+        }
+        let token_series_id = self.tokens.token_id_format.token_series_id_of(&token_id).to_string();
+        let base_uri = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .and_then(|token_series| token_series.base_uri)
+            .or_else(|| self.metadata.get().and_then(|metadata| metadata.base_uri));
+        match base_uri {
+            Some(base_uri) => Some(format!("{}/{}", base_uri.trim_end_matches('/'), media.trim_start_matches('/'))),
+            None => Some(media),
+        }
+    }
+
+    pub fn nft_get_series_single(&self, token_series_id: TokenSeriesId) -> TokenSeriesDetailJson {
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let minted = self.internal_minted_count(&token_series_id, &token_series);
+        TokenSeriesDetailJson {
+            remaining_copies: token_series.metadata.copies.map(|copies| U64(copies.saturating_sub(minted))),
+            price: token_series.price.map(U128),
+            is_mintable: token_series.is_mintable,
+            minted: U64(minted),
+            mint_start: token_series.mint_start.map(U64),
+            mint_end: token_series.mint_end.map(U64),
+            max_mints_per_wallet: token_series.max_mints_per_wallet.map(U64),
+            transaction_fee: U128(self.market_data_transaction_fee(&token_series_id)),
+            is_soulbound: token_series.is_soulbound,
+            metadata_frozen: token_series.metadata_frozen,
+            is_revealed: token_series.is_revealed,
+            provenance_hash: token_series.provenance_hash,
+            pending_creator_id: token_series.pending_creator_id,
+            price_curve: token_series.price_curve,
+            random_editions: token_series.edition_pool.is_some(),
+            base_uri: token_series.base_uri,
+            token_series_id,
+            metadata: token_series.metadata,
+            creator_id: token_series.creator_id,
+            royalty: token_series.royalty,
+        }
+    }
+
+    pub fn nft_series_provenance_hash(&self, token_series_id: TokenSeriesId) -> Option<Base64VecU8> {
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        token_series.provenance_hash
+    }
+
+    /// Number of editions of a series that exist as unique tokens, burned ones excluded
+    pub fn nft_supply_for_series(&self, token_series_id: TokenSeriesId) -> U64 {
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        U64(token_series.tokens.len())
+    }
+
+    /// Editions of a series `account_id` received through sales, counted only while the
+    /// series has a `max_mints_per_wallet`.
+    pub fn nft_mints_by_wallet(&self, token_series_id: TokenSeriesId, account_id: AccountId) -> U64 {
+        U64(self.mints_per_wallet.get(&(token_series_id, account_id)).unwrap_or(0))
+    }
+
+    /// Get the tokens minted from a series, in mint order
+    pub fn nft_tokens_by_series(
+        &self,
+        token_series_id: TokenSeriesId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        let token_ids = token_series.tokens.as_vector();
+        (start_index as u64..token_ids.len())
+            .take(limit)
+            .filter_map(|index| self.tokens.nft_token(token_ids.get(index).unwrap()))
+            .collect()
+    }
+
+    /// Number of series created so far
+    pub fn nft_series_supply(&self) -> U64 {
+        U64(self.token_series_ids.len())
+    }
+
+    /// Get series whose normalized title (lowercase, single spaces) starts with `prefix`,
+    /// in title order
+    pub fn nft_series_search(
+        &self,
+        prefix: String,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenSeriesJson> {
+        let prefix = normalize_title(&prefix);
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        self.token_series_by_title
+            .range((Bound::Included(prefix.clone()), Bound::Unbounded))
+            .take_while(|(title_key, _)| title_key.starts_with(&prefix))
+            .skip(start_index as usize)
+            .take(limit)
+            .filter_map(|(_, token_series_id)| {
+                self.token_series_by_id
+                    .get(&token_series_id)
+                    .map(|token_series| token_series.to_json(token_series_id))
+            })
+            .collect()
+    }
+
+    /// Get the configured and the currently effective price of a series
+    pub fn nft_series_price(&self, token_series_id: TokenSeriesId) -> TokenSeriesPriceJson {
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let minted = self.internal_minted_count(&token_series_id, &token_series);
+        let effective_price = token_series.current_price(minted);
+        TokenSeriesPriceJson {
+            token_series_id,
+            price: token_series.price.map(U128),
+            price_near: token_series.price.map(yocto_to_near_string),
+            effective_price: effective_price.map(U128),
+            effective_price_near: effective_price.map(yocto_to_near_string),
+        }
+    }
+
+    pub fn nft_series_mint_progress(&self, token_series_id: TokenSeriesId) -> TokenSeriesMintProgressJson {
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let minted = self.internal_minted_count(&token_series_id, &token_series);
+        TokenSeriesMintProgressJson {
+            minted: U64(minted),
+            copies: token_series.metadata.copies.map(U64),
+            is_mintable: token_series.is_mintable,
+            price: token_series.current_price(minted).map(U128),
+        }
+    }
+}
+
+impl Contract {
+    /// Mint the next edition of a series to `receiver_id`, paid by the attached deposit. Shared
+    /// by `nft_buy` and `nft_mint_allowlisted`, which check who may buy first.
+    pub(crate) fn internal_buy(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        receiver_id: AccountId,
+        max_price: U128,
+        referral_id: Option<AccountId>,
+    ) -> Token {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_minting_not_paused();
+        self.assert_not_banned(&env::predecessor_account_id());
+        self.assert_not_banned(&receiver_id);
+        if let Some(referral_id) = &referral_id {
+            assert!(
+                referral_id != &env::predecessor_account_id() && referral_id != &receiver_id,
+                "FireFly: Buyer cannot be their own referrer"
+            );
+            self.assert_not_banned(referral_id);
+        }
+        self.assert_open_edition_window(&token_series_id);
+        self.assert_not_auctioned(&token_series_id);
+        self.assert_no_drop(&token_series_id);
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        let minted = self.internal_minted_count(&token_series_id, &token_series);
+        let price = token_series.current_price(minted).expect("FireFly: Token series is not for sale");
+        assert!(
+            price <= max_price.0,
+            "FireFly: price {} is higher than max_price {}",
+            price,
+            max_price.0
+        );
+        assert!(
+            env::attached_deposit() >= price,
+            "FireFly: attached deposit is less than price {}",
+            price
+        );
+
+        self.internal_record_wallet_mint(&token_series_id, &token_series, &receiver_id);
+        let for_treasury = self.internal_transaction_fee(&token_series_id, price);
+        let token = self.tokens.internal_mint(&mut self.token_series_by_id, token_series_id.clone(), receiver_id.clone(), None);
+        self.internal_add_purchase_receipt(
+            &env::predecessor_account_id(),
+            token_series_id.clone(),
+            token.token_id.clone(),
+            receiver_id,
+            price,
+        );
+
+        self.internal_charge_storage(price, env::storage_usage() - initial_storage_usage);
+        if price > 0 {
+            let mut proceeds = self.internal_take_fees(price, for_treasury);
+            if let Some(referral_id) = referral_id {
+                proceeds = self.internal_pay_referral(referral_id, price, proceeds);
+            }
+            self.internal_record_series_sale(&token_series_id, price, proceeds);
+        }
+
+        token
+    }
+
+    /// Count one more edition of `token_series_id` sold to `receiver_id`, panicking when that
+    /// goes over the series `max_mints_per_wallet`.
+    pub(crate) fn internal_record_wallet_mint(
+        &mut self,
+        token_series_id: &TokenSeriesId,
+        token_series: &TokenSeries,
+        receiver_id: &AccountId,
+    ) {
+        if let Some(max_mints_per_wallet) = token_series.max_mints_per_wallet {
+            let key = (token_series_id.clone(), receiver_id.clone());
+            let minted = self.mints_per_wallet.get(&key).unwrap_or(0);
+            assert!(
+                minted < max_mints_per_wallet,
+                "FireFly: {} already has the maximum of {} editions of this series",
+                receiver_id,
+                max_mints_per_wallet
+            );
+            self.mints_per_wallet.insert(&key, &(minted + 1));
+        }
+    }
+
+    /// Drop `token_series_id` from the listed ids, moving the last id into its slot.
+    fn internal_remove_series_id(&mut self, token_series_id: &TokenSeriesId) {
+        if let Some(index) = self.token_series_index.remove(token_series_id) {
+            self.token_series_ids.swap_remove(index);
+            if let Some(moved_id) = self.token_series_ids.get(index) {
+                self.token_series_index.insert(&moved_id, &index);
+            }
+        }
+    }
+
+    /// Store a new series created by the predecessor and log its creation. A series without
+    /// its own `royalty` gets the contract default. Only the metadata hashes are checked, against
+    /// the contract's `metadata_hash_policy`; other validation and storage refunds are left to
+    /// the caller.
+    pub(crate) fn internal_create_series(
+        &mut self,
+        token_metadata: TokenMetadata,
+        price: Option<Balance>,
+        options: SeriesOptions,
+    ) -> TokenSeriesJson {
+        assert_not_migrating();
+        token_metadata.assert_valid_hashes(self.metadata_hash_policy);
+        let SeriesOptions { royalty, is_soulbound, provenance_hash } = options;
+        let is_soulbound = is_soulbound.unwrap_or(false);
+        let caller_id = env::predecessor_account_id();
+        let royalty_res = royalty.unwrap_or_else(|| self.internal_default_royalty(&caller_id));
+        // ids of deleted series are never reused
+        let token_series_id = (self.token_series_ids.len() + self.deleted_series + 1).to_string();
+
+        assert!(
+            self.token_series_by_id.get(&token_series_id).is_none(),
+            "FireFly: duplicate token_series_id"
+        );
+
+        self.token_series_by_id.insert(&token_series_id, &TokenSeries{
+            metadata: token_metadata.clone(),
+            creator_id: caller_id.clone(),
+            tokens: UnorderedSet::new(
+                StorageKey::TokensBySeriesInner {
+                    token_series: token_series_id.clone(),
+                }
+                .try_to_vec()
+                .unwrap(),
+            ),
+            price,
+            is_mintable: true,
+            royalty: royalty_res.clone(),
+            is_multi_token: false,
+            burned: 0,
+            mint_start: None,
+            mint_end: None,
+            max_mints_per_wallet: None,
+            is_soulbound,
+            metadata_frozen: false,
+            is_revealed: false,
+            provenance_hash: provenance_hash.clone(),
+            pending_creator_id: None,
+            price_curve: None,
+            edition_pool: None,
+            trait_tables: None,
+            base_uri: None,
+        });
+        self.token_series_index.insert(&token_series_id, &self.token_series_ids.len());
+        self.token_series_ids.push(&token_series_id);
+        if let Some(title) = &token_metadata.title {
+            self.token_series_by_title
+                .insert(&title_index_key(title, &token_series_id), &token_series_id);
+        }
+
+        // lock in the transaction fee for sales of this series
+        let current_transaction_fee = self.calculate_current_transaction_fee();
+        self.market_data_transaction_fee.insert(&token_series_id, &current_transaction_fee);
+
+        SeriesCreate {
+            token_series_id: &token_series_id,
+            creator_id: &caller_id,
+            token_metadata: &token_metadata,
+            price: price.map(U128),
+            royalty: &royalty_res,
+            transaction_fee: U128(current_transaction_fee),
+            is_soulbound,
+            provenance_hash: provenance_hash.as_ref(),
+        }
+        .emit();
+
+		TokenSeriesJson{
+            token_series_id,
+			metadata: token_metadata,
+			creator_id: caller_id,
+            royalty: royalty_res,
+            // transaction_fee: current_transaction_fee.into()
+		}
+    }
+
+    /// Swap in new metadata for a series, keeping its `copies`, and for its minted tokens.
+    /// A token whose edition is in `edition_metadata` gets that metadata instead. All of it must
+    /// pass the contract's `metadata_hash_policy`. Returns the ids of the tokens rewritten.
+    pub(crate) fn internal_replace_series_metadata(
+        &mut self,
+        token_series_id: &TokenSeriesId,
+        token_series: &mut TokenSeries,
+        token_metadata: TokenMetadata,
+        edition_metadata: HashMap<u64, TokenMetadata>,
+    ) -> Vec<TokenId> {
+        assert!(
+            edition_metadata.len() as u64 <= MAX_METADATA_REFRESH_TOKENS,
+            "FireFly: At most {} editions can have their own metadata",
+            MAX_METADATA_REFRESH_TOKENS
+        );
+        token_metadata.assert_valid_hashes(self.metadata_hash_policy);
+        edition_metadata.values().for_each(|metadata| metadata.assert_valid_hashes(self.metadata_hash_policy));
+        let copies = token_series.metadata.copies;
+        let token_metadata = TokenMetadata { copies, ..token_metadata };
+        if let Some(title) = &token_series.metadata.title {
+            self.token_series_by_title.remove(&title_index_key(title, token_series_id));
+        }
+        if let Some(title) = &token_metadata.title {
+            self.token_series_by_title
+                .insert(&title_index_key(title, token_series_id), token_series_id);
+        }
+        token_series.metadata = token_metadata;
+        self.token_series_by_id.insert(token_series_id, token_series);
+
+        self.series_metadata_refresh
+            .insert(token_series_id, &MetadataRefresh { next_index: 0, edition_metadata });
+        self.internal_refresh_series_metadata(token_series_id, token_series, MAX_METADATA_REFRESH_TOKENS)
+    }
+
+    /// Rewrite the next `limit` tokens of a series to its metadata, returns the tokens rewritten.
+    pub(crate) fn internal_refresh_series_metadata(
+        &mut self,
+        token_series_id: &TokenSeriesId,
+        token_series: &TokenSeries,
+        limit: u64,
+    ) -> Vec<TokenId> {
+        let mut refresh = match self.series_metadata_refresh.get(token_series_id) {
+            Some(refresh) => refresh,
+            None => return vec![],
+        };
+        let copies = token_series.metadata.copies;
+        let tokens = token_series.tokens.as_vector();
+        let end = refresh.next_index.saturating_add(limit).min(tokens.len());
+        let token_ids: Vec<TokenId> = (refresh.next_index..end).filter_map(|index| tokens.get(index)).collect();
+        for token_id in token_ids.iter() {
+            let mut metadata = match self
+                .tokens
+                .token_id_format
+                .edition_of(token_id)
+                .and_then(|edition| refresh.edition_metadata.get(&edition))
+            {
+                Some(metadata) => TokenMetadata { copies, ..metadata.clone() },
+                None => token_series.metadata.clone(),
+            };
+            // rolled traits stay with the token
+            if token_series.trait_tables.is_some() {
+                let attributes = self
+                    .tokens
+                    .token_metadata(token_id)
+                    .and_then(|old_metadata| attributes_of(old_metadata.extra.as_deref()));
+                if let Some(attributes) = attributes {
+                    metadata.extra = Some(extra_with_attributes(metadata.extra, &attributes));
+                }
+            }
+            self.tokens.internal_set_token_metadata(token_id, &metadata);
+        }
+
+        if end == tokens.len() {
+            self.series_metadata_refresh.remove(token_series_id);
+        } else {
+            refresh.next_index = end;
+            self.series_metadata_refresh.insert(token_series_id, &refresh);
+        }
+        token_ids
+    }
+
+    /// Start a pending metadata refresh of a series over, after a burn moved one of its tokens
+    /// to a position the refresh already passed. Rewriting a token again changes nothing.
+    pub(crate) fn internal_restart_series_metadata_refresh(&mut self, token_series_id: &TokenSeriesId) {
+        if let Some(mut refresh) = self.series_metadata_refresh.get(token_series_id) {
+            refresh.next_index = 0;
+            self.series_metadata_refresh.insert(token_series_id, &refresh);
+        }
+    }
+
+    /// Panics if `token_id` belongs to a soulbound series.
+    pub(crate) fn assert_not_soulbound(&self, token_id: &TokenId) {
+        if let Some(token_series) = self.token_series_by_id.get(&self.tokens.token_id_format.token_series_id_of(token_id).to_string()) {
+            assert!(!token_series.is_soulbound, "FireFly: Token is soulbound");
+        }
+    }
+
+    /// Editions minted so far, counting multi-token balances for multi-token series.
+    pub(crate) fn internal_minted_count(&self, token_series_id: &TokenSeriesId, token_series: &TokenSeries) -> u64 {
+        if token_series.is_multi_token {
+            self.mt_supply.get(token_series_id).unwrap_or(0) as u64
+        } else {
+            token_series.tokens.len() + token_series.burned
+        }
+    }
+
+    /// The house royalty, paid to the series creator.
+    pub(crate) fn internal_default_royalty(&self, creator_id: &AccountId) -> HashMap<AccountId, u32> {
+        let mut royalty = HashMap::new();
+        if self.default_royalty > 0 {
+            royalty.insert(creator_id.clone(), self.default_royalty);
+        }
+        royalty
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::admin::{AdminAction, DEFAULT_ADMIN_DELAY};
+    use crate::metadata::MetadataHashPolicy;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    fn test_create_series() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128::from(0u128)), None);
+        assert_eq!(token_series.token_series_id, "1".to_string());
+        assert_eq!(token_series.creator_id.to_string(), accounts(1).to_string());
+        assert_eq!(token_series.metadata, sample_token_metadata());
+        assert!(token_series.royalty.is_empty());
+    }
+
+    #[test]
+    fn test_create_series_default_royalty() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetDefaultRoyalty { royalty: 500 });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        assert_eq!(token_series.royalty, HashMap::from([(accounts(1), 500)]));
+    }
+
+    #[test]
+    fn test_create_series_with_royalty() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.default_royalty = 500;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let royalty = HashMap::from([(accounts(1), 1_000), (accounts(3), 500)]);
+        let token_series = contract.nft_create_series(
+            sample_token_metadata(),
+            None,
+            Some(SeriesOptions { royalty: Some(royalty.clone()), ..Default::default() }),
+        );
+        assert_eq!(token_series.royalty, royalty);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST * 2)
+            .build());
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+        assert_eq!(
+            contract.nft_payout(token.token_id, U128(1_000), None).payout,
+            HashMap::from([(accounts(1), U128(100)), (accounts(3), U128(50)), (accounts(2), U128(850))])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Exceeds maximum royalty -> 9000")]
+    fn test_create_series_royalty_above_cap() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let royalty = HashMap::from([(accounts(1), 5_000), (accounts(3), 4_001)]);
+        contract.nft_create_series(
+            sample_token_metadata(),
+            None,
+            Some(SeriesOptions { royalty: Some(royalty), ..Default::default() }),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Creator or minter only")]
+    fn test_mint_not_creator() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(10)), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_mint(token_series.token_series_id, accounts(2));
+    }
+
+    #[test]
+    fn test_series_price() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let price = 1_500_000_000_000_000_000_000_000u128;
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(price)), None);
+
+        let series_price = contract.nft_series_price(token_series.token_series_id);
+        assert_eq!(series_price.price, Some(U128(price)));
+        assert_eq!(series_price.price_near, Some("1.5".to_string()));
+        assert_eq!(series_price.effective_price_near, Some("1.5".to_string()));
+        assert_eq!(yocto_to_near_string(2 * 10u128.pow(24)), "2".to_string());
+        assert_eq!(yocto_to_near_string(1), "0.000000000000000000000001".to_string());
+    }
+
+    #[test]
+    fn test_set_series_price() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(10)), None);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_set_series_price(token_series.token_series_id.clone(), Some(U128(20)));
+        assert_eq!(contract.nft_series_price(token_series.token_series_id.clone()).price, Some(U128(20)));
+        contract.nft_set_series_price(token_series.token_series_id.clone(), None);
+        assert_eq!(contract.nft_series_price(token_series.token_series_id).price, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Creator only")]
+    fn test_set_series_price_not_creator() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(10)), None);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(2)).build());
+        contract.nft_set_series_price(token_series.token_series_id, Some(U128(20)));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token series is not mintable")]
+    fn test_mint_non_mintable_series() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_set_series_non_mintable(token_series.token_series_id.clone());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.nft_mint(token_series.token_series_id, accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Series supply maxed")]
+    fn test_mint_past_copies() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let metadata = TokenMetadata { copies: Some(2), ..sample_token_metadata() };
+        let token_series = contract.nft_create_series(metadata, None, None);
+
+        for _ in 0..2 {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST * 2)
+                .build());
+            contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+        }
+        assert!(!contract.token_series_by_id.get(&token_series.token_series_id).unwrap().is_mintable);
+        assert_eq!(contract.nft_supply_for_series(token_series.token_series_id.clone()), U64(2));
+        let tokens = contract.nft_tokens_by_series(token_series.token_series_id.clone(), Some(U128(1)), Some(10));
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_id, format!("{}:2", token_series.token_series_id));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST * 2)
+            .build());
+        contract.nft_mint(token_series.token_series_id, accounts(2));
+    }
+
+    #[test]
+    fn test_decrease_series_copies() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let metadata = TokenMetadata { copies: Some(10), ..sample_token_metadata() };
+        let token_series = contract.nft_create_series(metadata, None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST * 2)
+            .build());
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+
+        testing_env!(context.attached_deposit(1).build());
+        assert_eq!(contract.nft_decrease_series_copies(token_series.token_series_id.clone(), U64(4)), U64(6));
+        let detail = contract.nft_get_series_single(token_series.token_series_id.clone());
+        assert_eq!(detail.minted, U64(1));
+        assert_eq!(detail.remaining_copies, Some(U64(5)));
+        assert!(detail.is_mintable);
+        assert_eq!(contract.nft_decrease_series_copies(token_series.token_series_id.clone(), U64(5)), U64(1));
+        assert!(!contract.token_series_by_id.get(&token_series.token_series_id).unwrap().is_mintable);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: copies cannot go below the 1 minted")]
+    fn test_decrease_series_copies_below_minted() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let metadata = TokenMetadata { copies: Some(10), ..sample_token_metadata() };
+        let token_series = contract.nft_create_series(metadata, None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST * 2)
+            .build());
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_decrease_series_copies(token_series.token_series_id, U64(10));
+    }
+
+    #[test]
+    fn test_series_pagination() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        for _ in 0..3 {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST)
+                .predecessor_account_id(accounts(1))
+                .build());
+            contract.nft_create_series(sample_token_metadata(), None, None);
+        }
+        assert_eq!(contract.nft_series_supply(), U64(3));
+        let page = contract.nft_series(Some(U128(1)), Some(1));
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].token_series_id, "2".to_string());
+        assert_eq!(contract.nft_series(Some(U128(1)), None).len(), 2);
+    }
+
+    #[test]
+    fn test_buy() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let price = 10u128.pow(24);
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(price)), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(price + 2 * MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = contract.nft_buy(token_series.token_series_id, accounts(2), U128(price), None);
+        assert_eq!(token.token_id, "1:1".to_string());
+        assert_eq!(token.owner_id, accounts(2));
+
+        let receipts = contract.nft_purchase_receipts(accounts(2), None, None);
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].token_id, "1:1".to_string());
+        assert_eq!(receipts[0].price, U128(price));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: price 1000000000000000000000000 is higher than max_price 1")]
+    fn test_buy_above_max_price() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let price = 10u128.pow(24);
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(price)), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(price + 2 * MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_buy(token_series.token_series_id, accounts(2), U128(1), None);
+    }
+
+    #[test]
+    fn test_series_search() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        for title in ["Olympus Mons", "olympus  Mons II", "Mauna Kea"] {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST)
+                .predecessor_account_id(accounts(1))
+                .build());
+            let mut metadata = sample_token_metadata();
+            metadata.title = Some(title.to_string());
+            contract.nft_create_series(metadata, None, None);
+        }
+
+        let found = contract.nft_series_search("OLYMPUS mons".to_string(), None, None);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].token_series_id, "1".to_string());
+        assert_eq!(found[1].token_series_id, "2".to_string());
+        let found = contract.nft_series_search("olympus".to_string(), Some(U128(1)), Some(1));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].token_series_id, "2".to_string());
+        assert!(contract.nft_series_search("everest".to_string(), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_clone_series() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let source = contract.nft_create_series(sample_token_metadata(), Some(U128::from(5u128)), None);
+        contract.nft_set_series_tags(source.token_series_id.clone(), vec!["landscape".to_string()]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_set_series_mint_window(source.token_series_id.clone(), Some(U64(100)), Some(U64(200)));
+        contract.nft_set_series_max_mints_per_wallet(source.token_series_id.clone(), Some(U64(2)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let royalty = HashMap::from([(accounts(2), 500)]);
+        let cloned = contract.nft_clone_series(
+            source.token_series_id,
+            Some(TokenSeriesOverrides {
+                title: Some("Olympus Mons II".into()),
+                royalty: Some(royalty.clone()),
+                ..Default::default()
+            }),
+        );
+        assert_eq!(cloned.token_series_id, "2".to_string());
+        assert_eq!(cloned.metadata.title, Some("Olympus Mons II".to_string()));
+        assert_eq!(cloned.metadata.description, sample_token_metadata().description);
+        assert_eq!(cloned.royalty, royalty);
+        let clone = contract.token_series_by_id.get(&cloned.token_series_id).unwrap();
+        assert_eq!(clone.price, Some(5));
+        assert_eq!((clone.mint_start, clone.mint_end), (Some(100), Some(200)));
+        assert_eq!(clone.max_mints_per_wallet, Some(2));
+        assert_eq!(contract.nft_series_tags(cloned.token_series_id), vec!["landscape".to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Creator only")]
+    fn test_clone_series_not_creator() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let source = contract.nft_create_series(sample_token_metadata(), None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_clone_series(source.token_series_id, None);
+    }
+
+    #[test]
+    fn test_mint_window() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(0)), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_set_series_mint_window(token_series.token_series_id.clone(), Some(U64(100)), Some(U64(200)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .block_timestamp(100)
+            .build());
+        contract.nft_buy(token_series.token_series_id.clone(), accounts(2), U128(0), None);
+        assert_eq!(contract.nft_supply_for_series(token_series.token_series_id), U64(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: charlie already has the maximum of 1 editions of this series")]
+    fn test_max_mints_per_wallet() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(TokenMetadata { copies: Some(5), ..sample_token_metadata() }, Some(U128(0)), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_set_series_max_mints_per_wallet(token_series.token_series_id.clone(), Some(U64(1)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .build());
+        contract.nft_buy(token_series.token_series_id.clone(), accounts(2), U128(0), None);
+        assert_eq!(contract.nft_mints_by_wallet(token_series.token_series_id.clone(), accounts(2)), U64(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .build());
+        contract.nft_buy(token_series.token_series_id, accounts(2), U128(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Minting has ended")]
+    fn test_mint_after_window() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(0)), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_set_series_mint_window(token_series.token_series_id.clone(), None, Some(U64(200)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .block_timestamp(200)
+            .build());
+        contract.nft_buy(token_series.token_series_id, accounts(2), U128(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token is soulbound")]
+    fn test_soulbound_transfer() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(
+            sample_token_metadata(),
+            None,
+            Some(SeriesOptions { is_soulbound: Some(true), ..Default::default() }),
+        );
+        assert!(contract.nft_get_series_single(token_series.token_series_id.clone()).is_soulbound);
+        let token = contract.nft_mint(token_series.token_series_id, accounts(2));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(2)).build());
+        contract.nft_transfer(accounts(3), token.token_id, None, None);
+    }
+
+    #[test]
+    fn test_update_series_metadata() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let metadata = TokenMetadata { title: Some("Valles Marineris".into()), copies: None, ..sample_token_metadata() };
+        let updated = contract.nft_update_series_metadata(token_series.token_series_id.clone(), metadata);
+        assert_eq!(updated.metadata.copies, Some(10));
+
+        let token = contract.nft_token(token.token_id).unwrap();
+        assert_eq!(token.metadata.title, Some("Valles Marineris".into()));
+        assert!(contract.nft_series_search("olympus".into(), None, None).is_empty());
+        assert_eq!(contract.nft_series_search("valles".into(), None, None).len(), 1);
+    }
+
+    #[test]
+    fn test_update_series_metadata_in_pages() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let metadata = TokenMetadata { copies: None, ..sample_token_metadata() };
+        let token_series_id = contract.nft_create_series(metadata, None, None).token_series_id;
+        for _ in 0..MAX_METADATA_REFRESH_TOKENS + 2 {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST)
+                .build());
+            contract.nft_mint(token_series_id.clone(), accounts(2));
+        }
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let metadata = TokenMetadata { title: Some("Valles Marineris".into()), copies: None, ..sample_token_metadata() };
+        contract.nft_update_series_metadata(token_series_id.clone(), metadata);
+        assert_eq!(contract.nft_series_metadata_refresh_remaining(token_series_id.clone()), U64(2));
+        let last_token_id = format!("{}:{}", token_series_id, MAX_METADATA_REFRESH_TOKENS + 2);
+        assert_ne!(contract.nft_token(last_token_id.clone()).unwrap().metadata.title, Some("Valles Marineris".into()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(3))
+            .build());
+        let token_ids = contract.nft_refresh_series_metadata(token_series_id.clone(), None);
+        assert_eq!(token_ids.len(), 2);
+        assert_eq!(contract.nft_series_metadata_refresh_remaining(token_series_id), U64(0));
+        assert_eq!(contract.nft_token(last_token_id).unwrap().metadata.title, Some("Valles Marineris".into()));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token series metadata is frozen")]
+    fn test_update_frozen_series_metadata() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_freeze_series_metadata(token_series.token_series_id.clone());
+        assert!(contract.nft_get_series_single(token_series.token_series_id.clone()).metadata_frozen);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.nft_update_series_metadata(token_series.token_series_id, sample_token_metadata());
+    }
+
+    #[test]
+    fn test_provenance_hash() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let provenance_hash = Base64VecU8(env::sha256(b"artwork in edition order"));
+        let token_series = contract.nft_create_series(
+            sample_token_metadata(),
+            None,
+            Some(SeriesOptions { provenance_hash: Some(provenance_hash.clone()), ..Default::default() }),
+        );
+        assert_eq!(contract.nft_series_provenance_hash(token_series.token_series_id), Some(provenance_hash));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Exceeds maximum royalty -> 1000")]
+    fn test_set_series_royalty_above_configured_cap() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .block_timestamp(0)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let mut royalty = HashMap::new();
+        royalty.insert(accounts(2), 1_000);
+        contract.nft_set_series_royalty(token_series.token_series_id.clone(), royalty.clone());
+        assert_eq!(contract.nft_get_series_single(token_series.token_series_id.clone()).royalty, royalty);
+
+        testing_env!(context.attached_deposit(1).build());
+        let action_id = contract
+            .propose_admin_action(AdminAction::SetRoyaltyLimits { max_royalty: 1_000, max_accounts: 5 });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+
+        royalty.insert(accounts(3), 1);
+        contract.nft_set_series_royalty(token_series.token_series_id, royalty);
+    }
+
+    #[test]
+    fn test_transfer_series_creator() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_transfer_series_creator(token_series.token_series_id.clone(), accounts(2));
+        let series = contract.nft_get_series_single(token_series.token_series_id.clone());
+        assert_eq!(series.creator_id, accounts(1));
+        assert_eq!(series.pending_creator_id, Some(accounts(2)));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(2)).build());
+        contract.nft_accept_series_creator(token_series.token_series_id.clone());
+        let series = contract.nft_get_series_single(token_series.token_series_id.clone());
+        assert_eq!(series.creator_id, accounts(2));
+        assert_eq!(series.pending_creator_id, None);
+        contract.nft_set_series_price(token_series.token_series_id, Some(U128(10)));
+    }
+
+    #[test]
+    fn test_dutch_auction_price() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let price = 10u128.pow(24);
+        contract.nft_set_series_price_curve(
+            token_series.token_series_id.clone(),
+            Some(PriceCurve::DutchAuction {
+                start_price: U128(10 * price),
+                end_price: U128(price),
+                starts_at: U64(0),
+                ends_at: U64(90),
+                decay_interval: U64(10),
+            }),
+        );
+        let series_price = contract.nft_series_price(token_series.token_series_id.clone());
+        assert_eq!(series_price.effective_price, Some(U128(10 * price)));
+
+        testing_env!(context.block_timestamp(25).build());
+        let series_price = contract.nft_series_price(token_series.token_series_id.clone());
+        assert_eq!(series_price.price, Some(U128(10 * price)));
+        assert_eq!(series_price.effective_price, Some(U128(8 * price)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(10 * price)
+            .block_timestamp(1_000)
+            .predecessor_account_id(accounts(2))
+            .build());
+        assert_eq!(contract.nft_series_price(token_series.token_series_id.clone()).effective_price, Some(U128(price)));
+        contract.nft_buy(token_series.token_series_id, accounts(2), U128(price), None);
+        let receipts = contract.nft_purchase_receipts(accounts(2), None, None);
+        assert_eq!(receipts[0].price, U128(price));
+    }
+
+    #[test]
+    fn test_bonding_curve_price() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let mut metadata = sample_token_metadata();
+        metadata.copies = Some(10);
+        let token_series = contract.nft_create_series(metadata, None, None);
+        let price = 10u128.pow(24);
+        contract.nft_set_series_price_curve(
+            token_series.token_series_id.clone(),
+            Some(PriceCurve::Linear { base_price: U128(price), increment: U128(price / 2) }),
+        );
+
+        for _ in 0..2 {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(2 * price + MINT_STORAGE_COST)
+                .predecessor_account_id(accounts(2))
+                .build());
+            contract.nft_buy(token_series.token_series_id.clone(), accounts(2), U128(2 * price), None);
+        }
+        let receipts = contract.nft_purchase_receipts(accounts(2), None, None);
+        assert_eq!(receipts.iter().map(|receipt| receipt.price).collect::<Vec<_>>(), vec![U128(price), U128(3 * price / 2)]);
+        assert_eq!(contract.nft_series_price(token_series.token_series_id).effective_price, Some(U128(2 * price)));
+    }
+
+    #[test]
+    fn test_token_detail() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), Some(U128(10)), None);
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+
+        let detail = contract.nft_token_detail(token.token_id).unwrap();
+        assert_eq!(detail.token.owner_id, accounts(2));
+        assert_eq!(detail.token_series_id, token_series.token_series_id);
+        assert_eq!(detail.edition, Some(U64(1)));
+        assert_eq!(detail.creator_id, accounts(1));
+        assert!(contract.nft_token_detail("1:2".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_delete_series() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_create_series(sample_token_metadata(), None, None);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.nft_delete_series("1".to_string());
+        assert_eq!(contract.nft_series_supply(), U64(2));
+        // "3" took the slot of "1"
+        contract.nft_delete_series("3".to_string());
+        assert_eq!(contract.nft_series_supply(), U64(1));
+        assert_eq!(contract.nft_series(None, None)[0].token_series_id, "2".to_string());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        assert_eq!(token_series.token_series_id, "4".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Token series already has minted tokens")]
+    fn test_delete_series_with_tokens() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_delete_series(token_series.token_series_id);
+    }
+
+    #[test]
+    fn test_close_series() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_metadata = TokenMetadata { copies: Some(5), ..sample_token_metadata() };
+        let token_series = contract.nft_create_series(token_metadata, Some(U128(10)), None);
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+
+        testing_env!(context.attached_deposit(1).build());
+        assert_eq!(contract.nft_close_series(token_series.token_series_id.clone()), U64(1));
+        let token_series = contract.nft_get_series_single(token_series.token_series_id);
+        assert_eq!(token_series.metadata.copies, Some(1));
+        assert_eq!(token_series.remaining_copies, Some(U64(0)));
+        assert!(!token_series.is_mintable);
+        assert_eq!(token_series.price, None);
+    }
+
+    #[test]
+    fn test_series_mint_progress() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_metadata = TokenMetadata { copies: Some(5), ..sample_token_metadata() };
+        let token_series = contract.nft_create_series(token_metadata, Some(U128(10)), None);
+        contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+
+        assert_eq!(
+            contract.nft_series_mint_progress(token_series.token_series_id),
+            TokenSeriesMintProgressJson {
+                minted: U64(1),
+                copies: Some(U64(5)),
+                is_mintable: true,
+                price: Some(U128(10)),
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: media requires media_hash")]
+    fn test_create_series_media_without_hash() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_metadata = TokenMetadata { media: Some("olympus.png".into()), ..sample_token_metadata() };
+        contract.nft_create_series(token_metadata, None, None);
+    }
+
+    #[test]
+    fn test_lenient_metadata_hash_policy() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetMetadataHashPolicy {
+            policy: MetadataHashPolicy::Lenient,
+        });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_config().metadata_hash_policy, MetadataHashPolicy::Lenient);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_metadata = TokenMetadata { media: Some("olympus.png".into()), ..sample_token_metadata() };
+        contract.nft_create_series(token_metadata, None, None);
+    }
+
+    #[test]
+    fn test_token_uri() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_metadata = TokenMetadata {
+            media: Some("olympus.png".into()),
+            media_hash: Some(Base64VecU8(vec![0u8; 32])),
+            ..sample_token_metadata()
+        };
+        let token_series = contract.nft_create_series(token_metadata, None, None);
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(2));
+        assert_eq!(contract.nft_token_uri(token.token_id.clone()), Some("olympus.png".to_string()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.nft_set_series_base_uri(token_series.token_series_id, Some("https://gateway.example/ipfs/".into()));
+        assert_eq!(
+            contract.nft_token_uri(token.token_id),
+            Some("https://gateway.example/ipfs/olympus.png".to_string())
+        );
+    }
+}
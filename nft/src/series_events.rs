@@ -0,0 +1,366 @@
+//! Events of token series, logged in the events format (nep-297) under the `firefly_series`
+//! standard so indexers can follow series without parsing contract specific logs.
+//!
+//! Besides [`SeriesCreate`] and [`SeriesDelete`], changes to the price, mintability, royalty
+//! and metadata of a series are logged as [`SeriesUpdatePrice`], [`SeriesUpdateMintable`],
+//! [`SeriesUpdateRoyalty`], [`SeriesUpdateMetadata`] and [`SeriesFreezeMetadata`], changes
+//! to its minting rules as [`SeriesUpdateMintWindow`], [`SeriesUpdateMaxMintsPerWallet`],
+//! [`SeriesUpdateCopies`] and [`SeriesUpdateBaseUri`], and handing it to another creator as
+//! [`SeriesTransferCreator`] and [`SeriesAcceptCreator`].
+//!
+//! <https://github.com/near/NEPs/blob/master/specs/Standards/EventsFormat.md>
+
+use crate::event::NearEvent;
+use crate::metadata::TokenMetadata;
+use crate::series::PriceCurve;
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::AccountId;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Data to log when a series is created. To log this event,
+/// call [`.emit()`](SeriesCreate::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesCreate<'a> {
+    pub token_series_id: &'a str,
+    pub creator_id: &'a AccountId,
+    pub token_metadata: &'a TokenMetadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<U128>,
+    pub royalty: &'a HashMap<AccountId, u32>,
+    pub transaction_fee: U128,
+    pub is_soulbound: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance_hash: Option<&'a Base64VecU8>,
+}
+
+impl SeriesCreate<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesCreate(&[self])).emit()
+    }
+}
+
+/// Data to log when the price of a series changes. `price` is the current price, the start
+/// of `price_curve` when the series is sold along one. To log this event,
+/// call [`.emit()`](SeriesUpdatePrice::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesUpdatePrice<'a> {
+    pub token_series_id: &'a str,
+    pub price: Option<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_curve: Option<&'a PriceCurve>,
+}
+
+impl SeriesUpdatePrice<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesUpdatePrice(&[self])).emit()
+    }
+}
+
+/// Data to log when a series stops being mintable, with its `copies` from then on. To log
+/// this event, call [`.emit()`](SeriesUpdateMintable::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesUpdateMintable<'a> {
+    pub token_series_id: &'a str,
+    pub is_mintable: bool,
+    pub copies: Option<U64>,
+}
+
+impl SeriesUpdateMintable<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesUpdateMintable(&[self])).emit()
+    }
+}
+
+/// Data to log when the royalty of a series is replaced. To log this event,
+/// call [`.emit()`](SeriesUpdateRoyalty::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesUpdateRoyalty<'a> {
+    pub token_series_id: &'a str,
+    pub royalty: &'a HashMap<AccountId, u32>,
+}
+
+impl SeriesUpdateRoyalty<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesUpdateRoyalty(&[self])).emit()
+    }
+}
+
+/// Data to log when the metadata of a series is replaced. The tokens rewritten with it are
+/// logged separately as `nft_metadata_update`. To log this event,
+/// call [`.emit()`](SeriesUpdateMetadata::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesUpdateMetadata<'a> {
+    pub token_series_id: &'a str,
+    pub token_metadata: &'a TokenMetadata,
+}
+
+impl SeriesUpdateMetadata<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesUpdateMetadata(&[self])).emit()
+    }
+}
+
+/// Data to log when the metadata of a series is frozen for good. To log this event,
+/// call [`.emit()`](SeriesFreezeMetadata::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesFreezeMetadata<'a> {
+    pub token_series_id: &'a str,
+}
+
+impl SeriesFreezeMetadata<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesFreezeMetadata(&[self])).emit()
+    }
+}
+
+/// Data to log when a series without minted tokens is deleted. To log this event,
+/// call [`.emit()`](SeriesDelete::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesDelete<'a> {
+    pub token_series_id: &'a str,
+    pub creator_id: &'a AccountId,
+}
+
+impl SeriesDelete<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesDelete(&[self])).emit()
+    }
+}
+
+/// Data to log when the block timestamps minting of a series opens and closes at change. To
+/// log this event, call [`.emit()`](SeriesUpdateMintWindow::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesUpdateMintWindow<'a> {
+    pub token_series_id: &'a str,
+    pub mint_start: Option<U64>,
+    pub mint_end: Option<U64>,
+}
+
+impl SeriesUpdateMintWindow<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesUpdateMintWindow(&[self])).emit()
+    }
+}
+
+/// Data to log when the limit of editions a single account can receive through sales
+/// changes. To log this event, call [`.emit()`](SeriesUpdateMaxMintsPerWallet::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesUpdateMaxMintsPerWallet<'a> {
+    pub token_series_id: &'a str,
+    pub max_mints_per_wallet: Option<U64>,
+}
+
+impl SeriesUpdateMaxMintsPerWallet<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesUpdateMaxMintsPerWallet(&[self])).emit()
+    }
+}
+
+/// Data to log when the creator lowers the `copies` of a series. To log this event,
+/// call [`.emit()`](SeriesUpdateCopies::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesUpdateCopies<'a> {
+    pub token_series_id: &'a str,
+    pub copies: U64,
+}
+
+impl SeriesUpdateCopies<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesUpdateCopies(&[self])).emit()
+    }
+}
+
+/// Data to log when the `base_uri` of a series changes. To log this event,
+/// call [`.emit()`](SeriesUpdateBaseUri::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesUpdateBaseUri<'a> {
+    pub token_series_id: &'a str,
+    pub base_uri: Option<&'a str>,
+}
+
+impl SeriesUpdateBaseUri<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesUpdateBaseUri(&[self])).emit()
+    }
+}
+
+/// Data to log when the creator of a series offers the role to `new_creator_id`. To log this
+/// event, call [`.emit()`](SeriesTransferCreator::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesTransferCreator<'a> {
+    pub token_series_id: &'a str,
+    pub creator_id: &'a AccountId,
+    pub new_creator_id: &'a AccountId,
+}
+
+impl SeriesTransferCreator<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesTransferCreator(&[self])).emit()
+    }
+}
+
+/// Data to log when an offered creator role is accepted. To log this event,
+/// call [`.emit()`](SeriesAcceptCreator::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesAcceptCreator<'a> {
+    pub token_series_id: &'a str,
+    pub previous_creator_id: &'a AccountId,
+    pub creator_id: &'a AccountId,
+}
+
+impl SeriesAcceptCreator<'_> {
+    pub fn emit(self) {
+        new_series_v1(SeriesEventKind::SeriesAcceptCreator(&[self])).emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct SeriesEvent<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: SeriesEventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+enum SeriesEventKind<'a> {
+    SeriesCreate(&'a [SeriesCreate<'a>]),
+    SeriesUpdatePrice(&'a [SeriesUpdatePrice<'a>]),
+    SeriesUpdateMintable(&'a [SeriesUpdateMintable<'a>]),
+    SeriesUpdateRoyalty(&'a [SeriesUpdateRoyalty<'a>]),
+    SeriesUpdateMetadata(&'a [SeriesUpdateMetadata<'a>]),
+    SeriesFreezeMetadata(&'a [SeriesFreezeMetadata<'a>]),
+    SeriesDelete(&'a [SeriesDelete<'a>]),
+    SeriesUpdateMintWindow(&'a [SeriesUpdateMintWindow<'a>]),
+    SeriesUpdateMaxMintsPerWallet(&'a [SeriesUpdateMaxMintsPerWallet<'a>]),
+    SeriesUpdateCopies(&'a [SeriesUpdateCopies<'a>]),
+    SeriesUpdateBaseUri(&'a [SeriesUpdateBaseUri<'a>]),
+    SeriesTransferCreator(&'a [SeriesTransferCreator<'a>]),
+    SeriesAcceptCreator(&'a [SeriesAcceptCreator<'a>]),
+}
+
+fn new_series_v1(event_kind: SeriesEventKind) -> NearEvent {
+    NearEvent::FireflySeries(SeriesEvent { version: "1.0.0", event_kind })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils;
+
+    fn bob() -> AccountId {
+        AccountId::new_unchecked("bob".to_string())
+    }
+
+    fn alice() -> AccountId {
+        AccountId::new_unchecked("alice".to_string())
+    }
+
+    #[test]
+    fn series_create() {
+        let creator_id = &bob();
+        SeriesCreate {
+            token_series_id: "1",
+            creator_id,
+            token_metadata: &TokenMetadata { title: Some("Olympus Mons".to_string()), ..Default::default() },
+            price: Some(U128(5)),
+            royalty: &HashMap::from([(creator_id.clone(), 500)]),
+            transaction_fee: U128(500),
+            is_soulbound: false,
+            provenance_hash: None,
+        }
+        .emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_series","version":"1.0.0","event":"series_create","data":[{"token_series_id":"1","creator_id":"bob","token_metadata":{"title":"Olympus Mons","description":null,"media":null,"media_hash":null,"copies":null,"issued_at":null,"expires_at":null,"starts_at":null,"updated_at":null,"extra":null,"reference":null,"reference_hash":null},"price":"5","royalty":{"bob":500},"transaction_fee":"500","is_soulbound":false}]}"#
+        );
+    }
+
+    #[test]
+    fn series_update_mintable() {
+        SeriesUpdateMintable { token_series_id: "1", is_mintable: false, copies: Some(U64(3)) }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_series","version":"1.0.0","event":"series_update_mintable","data":[{"token_series_id":"1","is_mintable":false,"copies":"3"}]}"#
+        );
+    }
+
+    #[test]
+    fn series_delete() {
+        SeriesDelete { token_series_id: "1", creator_id: &bob() }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_series","version":"1.0.0","event":"series_delete","data":[{"token_series_id":"1","creator_id":"bob"}]}"#
+        );
+    }
+
+    #[test]
+    fn series_update_mint_window() {
+        SeriesUpdateMintWindow { token_series_id: "1", mint_start: Some(U64(10)), mint_end: None }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_series","version":"1.0.0","event":"series_update_mint_window","data":[{"token_series_id":"1","mint_start":"10","mint_end":null}]}"#
+        );
+    }
+
+    #[test]
+    fn series_update_max_mints_per_wallet() {
+        SeriesUpdateMaxMintsPerWallet { token_series_id: "1", max_mints_per_wallet: Some(U64(2)) }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_series","version":"1.0.0","event":"series_update_max_mints_per_wallet","data":[{"token_series_id":"1","max_mints_per_wallet":"2"}]}"#
+        );
+    }
+
+    #[test]
+    fn series_update_copies() {
+        SeriesUpdateCopies { token_series_id: "1", copies: U64(7) }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_series","version":"1.0.0","event":"series_update_copies","data":[{"token_series_id":"1","copies":"7"}]}"#
+        );
+    }
+
+    #[test]
+    fn series_update_base_uri() {
+        SeriesUpdateBaseUri { token_series_id: "1", base_uri: Some("ipfs://bafy") }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_series","version":"1.0.0","event":"series_update_base_uri","data":[{"token_series_id":"1","base_uri":"ipfs://bafy"}]}"#
+        );
+    }
+
+    #[test]
+    fn series_transfer_creator() {
+        SeriesTransferCreator { token_series_id: "1", creator_id: &bob(), new_creator_id: &alice() }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_series","version":"1.0.0","event":"series_transfer_creator","data":[{"token_series_id":"1","creator_id":"bob","new_creator_id":"alice"}]}"#
+        );
+    }
+
+    #[test]
+    fn series_accept_creator() {
+        SeriesAcceptCreator { token_series_id: "1", previous_creator_id: &bob(), creator_id: &alice() }.emit();
+        assert_eq!(
+            test_utils::get_logs()[0],
+            r#"EVENT_JSON:{"standard":"firefly_series","version":"1.0.0","event":"series_accept_creator","data":[{"token_series_id":"1","previous_creator_id":"bob","creator_id":"alice"}]}"#
+        );
+    }
+}
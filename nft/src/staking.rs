@@ -0,0 +1,288 @@
+//! NFT staking. Holders stake tokens into the contract, which takes custody of them, and earn
+//! rewards in yoctoNEAR per second at the rate the owner set for the token's series. Rewards
+//! are paid from a pool anyone can fund and use the rate in force when they are claimed.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::events::NftTransfer;
+use crate::series::TokenSeriesId;
+use crate::token::TokenId;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance};
+use serde_json::json;
+
+const NANOSECONDS_PER_SECOND: u128 = 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Stake {
+    pub owner_id: AccountId,
+    pub staked_at: u64,
+    /// Rewards are accrued from this timestamp on
+    pub claimed_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakeJson {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub staked_at: U64,
+    pub pending_reward: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Reward in yoctoNEAR per second earned by each staked token of `token_series_id`,
+    /// 0 stops rewards for the series. Owner only, or `AdminAction::SetStakingRewardRate` for
+    /// owner sets requiring several confirmations.
+    #[payable]
+    pub fn set_staking_reward_rate(&mut self, token_series_id: TokenSeriesId, reward_rate: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_set_staking_reward_rate(token_series_id, reward_rate);
+    }
+
+    /// Add the attached deposit to the reward pool.
+    #[payable]
+    pub fn fund_staking_pool(&mut self) -> U128 {
+        let amount = env::attached_deposit();
+        assert!(amount > 0, "FireFly: Attach the amount to fund");
+        self.staking_pool += amount;
+
+        env::log_str(
+            json!({
+                "type": "fund_staking_pool",
+                "params": {
+                    "account_id": env::predecessor_account_id(),
+                    "amount": U128(amount),
+                }
+            }).to_string().as_str()
+        );
+        U128(self.staking_pool)
+    }
+
+    /// Move one of the caller's tokens into the contract and start earning rewards on it.
+    /// The caller pays for the storage.
+    #[payable]
+    pub fn nft_stake(&mut self, token_id: TokenId) {
+        assert_at_least_one_yocto();
+        self.assert_transfers_not_paused();
+        let owner_id = env::predecessor_account_id();
+        let token_owner_id = self.tokens.core.owner_by_id.get(&token_id).expect("FireFly: Token not found");
+        assert_eq!(token_owner_id, owner_id, "FireFly: Token owner only");
+        self.assert_not_banned(&owner_id);
+        self.assert_not_soulbound(&token_id);
+        self.assert_not_locked(&token_id);
+
+        let initial_storage_usage = env::storage_usage();
+        self.internal_clear_rental(&token_id);
+        self.internal_record_transfer(&token_id);
+        self.tokens.internal_remove_approvals(&token_id);
+        let contract_id = env::current_account_id();
+        self.tokens.internal_transfer_unguarded(&token_id, &owner_id, &contract_id);
+        let now = env::block_timestamp();
+        self.stakes.insert(&token_id, &Stake { owner_id: owner_id.clone(), staked_at: now, claimed_at: now });
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        NftTransfer {
+            old_owner_id: &owner_id,
+            new_owner_id: &contract_id,
+            token_ids: &[&token_id],
+            authorized_id: None,
+            memo: Some("stake"),
+        }
+        .emit();
+    }
+
+    /// Give a staked token back to its staker, paying its pending reward. With an underfunded
+    /// pool the reward is cut to what the pool holds, so tokens never get stuck.
+    #[payable]
+    pub fn nft_unstake(&mut self, token_id: TokenId) -> U128 {
+        assert_one_yocto();
+        let stake = self.stakes.get(&token_id).expect("FireFly: Token is not staked");
+        assert_eq!(env::predecessor_account_id(), stake.owner_id, "FireFly: Staker only");
+
+        let reward = self.internal_pending_reward(&token_id, &stake).min(self.staking_pool);
+        let initial_storage_usage = env::storage_usage();
+        self.stakes.remove(&token_id);
+        let contract_id = env::current_account_id();
+        self.tokens.internal_transfer_unguarded(&token_id, &contract_id, &stake.owner_id);
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        let amount = reward + Balance::from(storage_released) * env::storage_byte_cost();
+        self.staking_pool -= reward;
+        if amount > 0 {
+            self.internal_payout(stake.owner_id.clone(), amount);
+        }
+
+        NftTransfer {
+            old_owner_id: &contract_id,
+            new_owner_id: &stake.owner_id,
+            token_ids: &[&token_id],
+            authorized_id: None,
+            memo: Some("unstake"),
+        }
+        .emit();
+        U128(reward)
+    }
+
+    /// Pay the caller the rewards pending on their staked `token_ids`.
+    #[payable]
+    pub fn claim_rewards(&mut self, token_ids: Vec<TokenId>) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let now = env::block_timestamp();
+        let mut total: Balance = 0;
+        for token_id in token_ids.iter() {
+            let mut stake = self.stakes.get(token_id).expect("FireFly: Token is not staked");
+            assert_eq!(account_id, stake.owner_id, "FireFly: Staker only");
+            total += self.internal_pending_reward(token_id, &stake);
+            stake.claimed_at = now;
+            self.stakes.insert(token_id, &stake);
+        }
+        assert!(total <= self.staking_pool, "FireFly: Staking pool has insufficient funds");
+        self.staking_pool -= total;
+        if total > 0 {
+            self.internal_payout(account_id.clone(), total);
+        }
+
+        env::log_str(
+            json!({
+                "type": "claim_rewards",
+                "params": {
+                    "account_id": account_id,
+                    "token_ids": token_ids,
+                    "amount": U128(total),
+                }
+            }).to_string().as_str()
+        );
+        U128(total)
+    }
+
+    pub fn nft_stake_of(&self, token_id: TokenId) -> Option<StakeJson> {
+        self.stakes.get(&token_id).map(|stake| StakeJson {
+            pending_reward: U128(self.internal_pending_reward(&token_id, &stake)),
+            token_id,
+            owner_id: stake.owner_id,
+            staked_at: U64(stake.staked_at),
+        })
+    }
+
+    pub fn get_staking_reward_rate(&self, token_series_id: TokenSeriesId) -> U128 {
+        U128(self.staking_reward_rates.get(&token_series_id).unwrap_or(0))
+    }
+
+    pub fn get_staking_pool(&self) -> U128 {
+        U128(self.staking_pool)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_set_staking_reward_rate(&mut self, token_series_id: TokenSeriesId, reward_rate: U128) {
+        assert!(self.token_series_by_id.get(&token_series_id).is_some(), "FireFly: Token series not exist");
+        self.staking_reward_rates.insert(&token_series_id, &reward_rate.0);
+
+        env::log_str(
+            json!({
+                "type": "set_staking_reward_rate",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "reward_rate": reward_rate,
+                }
+            }).to_string().as_str()
+        );
+    }
+
+    fn internal_pending_reward(&self, token_id: &TokenId, stake: &Stake) -> Balance {
+        let reward_rate = self
+            .staking_reward_rates
+            .get(&self.tokens.token_id_format.token_series_id_of(token_id).to_string())
+            .unwrap_or(0);
+        let elapsed = u128::from(env::block_timestamp().saturating_sub(stake.claimed_at));
+        reward_rate * elapsed / NANOSECONDS_PER_SECOND
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::nft_core::NonFungibleTokenCore;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    fn test_stake_and_unstake() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_staking_reward_rate(token_series.token_series_id, U128(10));
+        testing_env!(context.attached_deposit(1000).build());
+        contract.fund_staking_pool();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_stake(token.token_id.clone());
+        assert_eq!(contract.nft_token(token.token_id.clone()).unwrap().owner_id, accounts(0));
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(30 * NANOSECONDS_PER_SECOND as u64)
+            .build());
+        assert_eq!(contract.claim_rewards(vec![token.token_id.clone()]), U128(300));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .block_timestamp(200 * NANOSECONDS_PER_SECOND as u64)
+            .build());
+        assert_eq!(contract.nft_unstake(token.token_id.clone()), U128(700));
+        assert_eq!(contract.get_staking_pool(), U128(0));
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Staking pool has insufficient funds")]
+    fn test_claim_rewards_underfunded_pool() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let token = contract.nft_mint(token_series.token_series_id.clone(), accounts(1));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_staking_reward_rate(token_series.token_series_id, U128(10));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_stake(token.token_id.clone());
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(NANOSECONDS_PER_SECOND as u64)
+            .build());
+        contract.claim_rewards(vec![token.token_id]);
+    }
+}
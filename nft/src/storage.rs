@@ -0,0 +1,249 @@
+//! NEP-145 storage management. Accounts can deposit NEAR ahead of time with `storage_deposit`;
+//! approvals and mints then take whatever storage the attached deposit doesn't cover from that
+//! balance, so callers no longer have to work out the exact storage cost of every call.
+//! The first deposit must cover the storage of the balance entry itself, that part stays
+//! locked until `storage_unregister` and the rest is `available`.
+
+use crate::Contract;
+use crate::ContractExt;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+
+/// Bytes taken by a `storage_deposits` entry of the longest account id: the record
+/// overhead, the key under its one byte prefix and the balance.
+const STORAGE_BALANCE_ENTRY_BYTES: u64 = 40 + 1 + 4 + 64 + 16;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Add the attached deposit to the storage balance of `account_id`, the caller by default.
+    /// Registering an account takes at least `storage_balance_bounds().min`. With
+    /// `registration_only` only that minimum is kept and the rest of the deposit, or all of
+    /// it for an account already registered, is refunded.
+    #[payable]
+    pub fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let min_balance = storage_balance_min();
+        let (balance, refund) = match self.storage_deposits.get(&account_id) {
+            Some(balance) if registration_only == Some(true) => (balance, amount),
+            Some(balance) => (balance + amount, 0),
+            None => {
+                assert!(
+                    amount >= min_balance,
+                    "FireFly: Registering takes a deposit of at least {} yoctoNEAR",
+                    min_balance
+                );
+                if registration_only == Some(true) {
+                    (min_balance, amount - min_balance)
+                } else {
+                    (amount, 0)
+                }
+            }
+        };
+        self.storage_deposits.insert(&account_id, &balance);
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        storage_balance(balance)
+    }
+
+    /// Withdraw `amount` from the caller's storage balance, everything by default.
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let balance = self.storage_deposits.get(&account_id).expect("FireFly: Account is not registered");
+        let available = balance.saturating_sub(storage_balance_min());
+        let amount = amount.map(|amount| amount.0).unwrap_or(available);
+        assert!(amount <= available, "FireFly: Cannot withdraw more than {}", available);
+
+        let balance = balance - amount;
+        self.storage_deposits.insert(&account_id, &balance);
+        if amount > 0 {
+            Promise::new(account_id).transfer(amount);
+        }
+        storage_balance(balance)
+    }
+
+    /// Close the caller's storage account and pay out its balance. Returns false if the caller
+    /// wasn't registered. No token depends on the balance, `force` is ignored.
+    #[payable]
+    pub fn storage_unregister(&mut self, #[allow(unused_variables)] force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        match self.storage_deposits.remove(&account_id) {
+            Some(balance) => {
+                if balance > 0 {
+                    Promise::new(account_id).transfer(balance);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds { min: U128(storage_balance_min()), max: None }
+    }
+
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_deposits.get(&account_id).map(storage_balance)
+    }
+}
+
+impl Contract {
+    /// Charge the caller `price` plus the cost of `storage_used` bytes. `price` must be
+    /// attached; storage is paid from the rest of the attached deposit, which is refunded
+    /// when it's more than enough, and otherwise from the caller's storage balance.
+    pub(crate) fn internal_charge_storage(&mut self, price: Balance, storage_used: u64) {
+        let account_id = env::predecessor_account_id();
+        let attached_deposit = env::attached_deposit();
+        let storage_cost = env::storage_byte_cost() * Balance::from(storage_used);
+        assert!(attached_deposit >= price, "FireFly: attached deposit is less than price {}", price);
+
+        let deposit_left = attached_deposit - price;
+        if deposit_left >= storage_cost {
+            let refund = deposit_left - storage_cost;
+            if refund > 1 {
+                Promise::new(account_id).transfer(refund);
+            }
+            return;
+        }
+        let shortfall = storage_cost - deposit_left;
+        assert!(
+            self.internal_take_storage_balance(&account_id, shortfall),
+            "FireFly: Must attach {} yoctoNEAR to cover price and storage",
+            price + storage_cost
+        );
+    }
+
+    /// Pay for `storage_used` bytes from the storage balance of `account_id`, for calls that
+    /// carry no NEAR deposit such as `ft_on_transfer`.
+    pub(crate) fn internal_use_storage_balance(&mut self, account_id: &AccountId, storage_used: u64) {
+        let storage_cost = env::storage_byte_cost() * Balance::from(storage_used);
+        assert!(
+            storage_cost == 0 || self.internal_take_storage_balance(account_id, storage_cost),
+            "FireFly: Storage balance of {} can't cover {} yoctoNEAR, use storage_deposit",
+            account_id,
+            storage_cost
+        );
+    }
+
+    /// Take `amount` from the available storage balance of `account_id`, returning false,
+    /// and taking nothing, if it doesn't cover it.
+    fn internal_take_storage_balance(&mut self, account_id: &AccountId, amount: Balance) -> bool {
+        match self.storage_deposits.get(account_id) {
+            Some(balance) if balance.saturating_sub(storage_balance_min()) >= amount => {
+                self.storage_deposits.insert(account_id, &(balance - amount));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Deposit locked by registering, the storage of the balance entry.
+fn storage_balance_min() -> Balance {
+    env::storage_byte_cost() * Balance::from(STORAGE_BALANCE_ENTRY_BYTES)
+}
+
+fn storage_balance(balance: Balance) -> StorageBalance {
+    StorageBalance { total: U128(balance), available: U128(balance.saturating_sub(storage_balance_min())) }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::approval::NonFungibleTokenApproval;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    fn test_mint_and_approve_from_storage_balance() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(MINT_STORAGE_COST + min_balance).build());
+        contract.storage_deposit(None, None);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        let token = contract.nft_mint(token_series.token_series_id, accounts(0));
+        contract.nft_approve(token.token_id, accounts(1), None, None);
+
+        let balance = contract.storage_balance_of(accounts(0)).unwrap();
+        assert!(balance.available.0 > 0 && balance.available.0 < MINT_STORAGE_COST);
+        assert_eq!(contract.storage_withdraw(None).available, U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Cannot withdraw more than 100")]
+    fn test_storage_withdraw_above_balance() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(min_balance + 100).build());
+        contract.storage_deposit(Some(accounts(1)), None);
+        assert_eq!(
+            contract.storage_balance_of(accounts(1)),
+            Some(StorageBalance { total: U128(min_balance + 100), available: U128(100) })
+        );
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.storage_withdraw(Some(U128(101)));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Registering takes a deposit of at least")]
+    fn test_storage_deposit_below_min() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.storage_deposit(Some(accounts(1)), None);
+    }
+
+    #[test]
+    fn test_storage_deposit_registration_only() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(min_balance + 100).build());
+        let balance = contract.storage_deposit(Some(accounts(1)), Some(true));
+        assert_eq!(balance, StorageBalance { total: U128(min_balance), available: U128(0) });
+
+        let balance = contract.storage_deposit(Some(accounts(1)), Some(true));
+        assert_eq!(balance, StorageBalance { total: U128(min_balance), available: U128(0) });
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{Contract, StorageKey};
+use crate::ContractExt;
+use crate::series::{TokenSeriesId, TokenSeriesJson};
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen};
+use serde_json::json;
+
+pub const MAX_TAGS_PER_SERIES: usize = 5;
+const MAX_TAG_LEN: usize = 32;
+
+/// Tags are lowercased and may only contain `a-z`, `0-9` and `-`, so "Art" and "art" are one tag.
+fn normalize_tag(tag: &str) -> String {
+    let tag = tag.trim().to_lowercase();
+    assert!(
+        !tag.is_empty() && tag.len() <= MAX_TAG_LEN,
+        "FireFly: tag must be 1 to {} characters",
+        MAX_TAG_LEN
+    );
+    assert!(
+        tag.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+        "FireFly: tag may only contain a-z, 0-9 and -"
+    );
+    tag
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Replace the tags of a series, creator only. The caller pays for the index storage.
+    #[payable]
+    pub fn nft_set_series_tags(&mut self, token_series_id: TokenSeriesId, tags: Vec<String>) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let token_series = self.token_series_by_id.get(&token_series_id).expect("FireFly: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "FireFly: Creator only"
+        );
+        assert!(
+            tags.len() <= MAX_TAGS_PER_SERIES,
+            "FireFly: at most {} tags per series",
+            MAX_TAGS_PER_SERIES
+        );
+        let mut new_tags: Vec<String> = vec![];
+        for tag in tags.iter().map(|tag| normalize_tag(tag)) {
+            if !new_tags.contains(&tag) {
+                new_tags.push(tag);
+            }
+        }
+
+        self.internal_unindex_series_tags(&token_series_id);
+        self.internal_index_series_tags(&token_series_id, &new_tags);
+
+        env::log_str(
+            json!({
+                "type": "nft_set_series_tags",
+                "params": {
+                    "token_series_id": token_series_id,
+                    "tags": new_tags,
+                }
+            }).to_string().as_str()
+        );
+
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn nft_series_tags(&self, token_series_id: TokenSeriesId) -> Vec<String> {
+        self.tags_by_series.get(&token_series_id).unwrap_or_default()
+    }
+
+    /// Get the series carrying `tag`
+    pub fn nft_series_by_tag(
+        &self,
+        tag: String,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenSeriesJson> {
+        let series_ids = match self.series_by_tag.get(&normalize_tag(&tag)) {
+            Some(series_ids) => series_ids,
+            None => return vec![],
+        };
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert!(limit != 0, "FireFly: Cannot provide limit of 0");
+        series_ids
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .filter_map(|token_series_id| {
+                self.token_series_by_id
+                    .get(&token_series_id)
+                    .map(|token_series| token_series.to_json(token_series_id))
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Store `tags`, already normalized and deduplicated, as the tags of a series and add it to
+    /// the index of each.
+    pub(crate) fn internal_index_series_tags(&mut self, token_series_id: &TokenSeriesId, tags: &Vec<String>) {
+        for tag in tags.iter() {
+            let mut series_ids = self.series_by_tag.get(tag).unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::SeriesByTagInner {
+                        tag_hash: env::sha256(tag.as_bytes()),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+            series_ids.insert(token_series_id);
+            self.series_by_tag.insert(tag, &series_ids);
+        }
+        if tags.is_empty() {
+            self.tags_by_series.remove(token_series_id);
+        } else {
+            self.tags_by_series.insert(token_series_id, tags);
+        }
+    }
+
+    /// Drop a series from the index of each of its tags, leaving `tags_by_series` as is.
+    pub(crate) fn internal_unindex_series_tags(&mut self, token_series_id: &TokenSeriesId) {
+        for tag in self.tags_by_series.get(token_series_id).unwrap_or_default() {
+            if let Some(mut series_ids) = self.series_by_tag.get(&tag) {
+                series_ids.remove(token_series_id);
+                if series_ids.is_empty() {
+                    self.series_by_tag.remove(&tag);
+                } else {
+                    self.series_by_tag.insert(&tag, &series_ids);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    const TAGS_STORAGE_COST: u128 = 10000000000000000000000;
+
+    #[test]
+    fn test_series_by_tag() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        for tags in [vec!["Art", "music"], vec!["art"]] {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST)
+                .predecessor_account_id(accounts(1))
+                .build());
+            let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(TAGS_STORAGE_COST)
+                .build());
+            contract.nft_set_series_tags(
+                token_series.token_series_id,
+                tags.into_iter().map(String::from).collect(),
+            );
+        }
+        assert_eq!(contract.nft_series_by_tag("art".to_string(), None, None).len(), 2);
+        assert_eq!(contract.nft_series_by_tag("music".to_string(), None, None).len(), 1);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(TAGS_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_set_series_tags("1".to_string(), vec!["gaming".to_string()]);
+        assert_eq!(contract.nft_series_tags("1".to_string()), vec!["gaming".to_string()]);
+        assert!(contract.nft_series_by_tag("music".to_string(), None, None).is_empty());
+        assert_eq!(contract.nft_series_by_tag("art".to_string(), None, None)[0].token_series_id, "2".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: at most 5 tags per series")]
+    fn test_too_many_tags() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        contract.nft_set_series_tags(
+            token_series.token_series_id,
+            (0..6).map(|i| format!("tag-{}", i)).collect(),
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! Fixtures shared by the unit tests of every module.
+
+use crate::TokenMetadata;
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::AccountId;
+
+/// Deposit covering the storage of creating a series or minting one token.
+pub(crate) const MINT_STORAGE_COST: u128 = 10000000000000000000000;
+
+pub(crate) fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id(accounts(0))
+        .signer_account_id(predecessor_account_id.clone())
+        .predecessor_account_id(predecessor_account_id);
+    builder
+}
+
+/// Metadata of a series of 10 copies, tests needing other values override them with
+/// `TokenMetadata { copies: Some(1), ..sample_token_metadata() }`.
+pub(crate) fn sample_token_metadata() -> TokenMetadata {
+    TokenMetadata {
+        title: Some("Olympus Mons".into()),
+        description: Some("The tallest mountain in the charted solar system".into()),
+        copies: Some(10u64),
+        ..Default::default()
+    }
+}
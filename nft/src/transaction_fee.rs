@@ -0,0 +1,163 @@
+//! Fee the contract takes from `nft_buy` proceeds for the treasury, in basis points of the price. Each series
+//! keeps the fee in force when it was created, so changing the fee never affects existing
+//! series. A new fee can be scheduled ahead of time with a `start_time`. The fee is changed
+//! through `AdminAction::SetTransactionFee`.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::series::TokenSeriesId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, Balance};
+
+pub const TRANSACTION_FEE_DENOMINATOR: u128 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransactionFee {
+    pub current_fee: u16,
+    /// Fee replacing `current_fee` from `start_time` (nanoseconds) on.
+    pub next_fee: Option<u16>,
+    pub start_time: Option<U64>,
+}
+
+impl TransactionFee {
+    /// Fee in force at the current block, taking a scheduled change into account.
+    pub fn fee_now(&self) -> u16 {
+        match (self.next_fee, self.start_time) {
+            (Some(next_fee), Some(start_time)) if env::block_timestamp() >= start_time.0 => next_fee,
+            _ => self.current_fee,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_transaction_fee(&self) -> TransactionFee {
+        self.transaction_fee.clone()
+    }
+
+    /// Fee in basis points taken from sales of `token_series_id`.
+    pub fn get_market_data_transaction_fee(&self, token_series_id: TokenSeriesId) -> U128 {
+        U128(self.market_data_transaction_fee(&token_series_id))
+    }
+}
+
+impl Contract {
+    /// Change the transaction fee of series created from now on, or with a `start_time`,
+    /// schedule the change for later.
+    pub(crate) fn internal_set_transaction_fee(&mut self, next_fee: u16, start_time: Option<U64>) {
+        self.calculate_current_transaction_fee();
+        match start_time {
+            Some(start_time) => {
+                assert!(
+                    start_time.0 > env::block_timestamp(),
+                    "FireFly: start_time must be in the future"
+                );
+                self.transaction_fee.next_fee = Some(next_fee);
+                self.transaction_fee.start_time = Some(start_time);
+            }
+            None => {
+                self.transaction_fee.current_fee = next_fee;
+                self.transaction_fee.next_fee = None;
+                self.transaction_fee.start_time = None;
+            }
+        }
+    }
+
+    /// Apply a scheduled fee change that is due, and return the fee now in force.
+    pub(crate) fn calculate_current_transaction_fee(&mut self) -> u128 {
+        if let Some(start_time) = self.transaction_fee.start_time {
+            if env::block_timestamp() >= start_time.0 {
+                let current_fee = self.transaction_fee.fee_now();
+                self.transaction_fee = TransactionFee { current_fee, next_fee: None, start_time: None };
+            }
+        }
+        u128::from(self.transaction_fee.current_fee)
+    }
+
+    /// Fee locked in by a series when it was created, series older than the fee fall back
+    /// to the fee in force.
+    pub(crate) fn market_data_transaction_fee(&self, token_series_id: &TokenSeriesId) -> u128 {
+        self.market_data_transaction_fee
+            .get(token_series_id)
+            .unwrap_or_else(|| u128::from(self.transaction_fee.fee_now()))
+    }
+
+    /// Part of a sale of `token_series_id` for `price` kept as transaction fee.
+    pub(crate) fn internal_transaction_fee(&self, token_series_id: &TokenSeriesId, price: Balance) -> Balance {
+        price * self.market_data_transaction_fee(token_series_id) / TRANSACTION_FEE_DENOMINATOR
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::admin::{AdminAction, DEFAULT_ADMIN_DELAY};
+    use crate::test_utils::{get_context, sample_token_metadata, MINT_STORAGE_COST};
+    use super::*;
+
+    #[test]
+    fn test_series_lock_in_transaction_fee() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetTransactionFee { next_fee: 500, start_time: None });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let token_series = contract.nft_create_series(sample_token_metadata(), None, None);
+
+        testing_env!(context.attached_deposit(1).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetTransactionFee {
+            next_fee: 300,
+            start_time: Some(U64(2 * DEFAULT_ADMIN_DELAY + 100)),
+        });
+        testing_env!(context.attached_deposit(1).block_timestamp(2 * DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_transaction_fee().current_fee, 500);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .block_timestamp(2 * DEFAULT_ADMIN_DELAY + 100)
+            .build());
+        let new_series = contract.nft_create_series(sample_token_metadata(), None, None);
+        assert_eq!(contract.get_transaction_fee().current_fee, 300);
+        assert_eq!(contract.get_market_data_transaction_fee(token_series.token_series_id), U128(500));
+        assert_eq!(contract.get_market_data_transaction_fee(new_series.token_series_id), U128(300));
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Owner only")]
+    fn test_set_transaction_fee_not_owner() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.propose_admin_action(AdminAction::SetTransactionFee { next_fee: 500, start_time: None });
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: start_time must be after the admin delay")]
+    fn test_set_transaction_fee_start_time_within_delay() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        contract.propose_admin_action(AdminAction::SetTransactionFee {
+            next_fee: 500,
+            start_time: Some(U64(DEFAULT_ADMIN_DELAY)),
+        });
+    }
+}
@@ -0,0 +1,118 @@
+//! Treasury receiving the contract's cut of sales: the protocol fee, taken from every paid
+//! mint, and the per-series transaction fee taken from `nft_buy`. Sales through `nft_buy`
+//! can also name a referrer, who is paid the referral fee out of the creator's proceeds.
+//!
+//! The treasury and the fees are changed through `AdminAction::SetTreasury`,
+//! `AdminAction::SetProtocolFee` and `AdminAction::SetReferralFee`.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::transaction_fee::TRANSACTION_FEE_DENOMINATOR;
+use near_sdk::{near_bindgen, AccountId, Balance, Promise};
+
+/// Highest protocol fee, in basis points.
+pub const MAX_PROTOCOL_FEE: u16 = 1_000;
+/// Highest referral fee, in basis points.
+pub const MAX_REFERRAL_FEE: u16 = 5_000;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_treasury(&self) -> AccountId {
+        self.treasury_id.clone()
+    }
+
+    pub fn get_protocol_fee(&self) -> u16 {
+        self.protocol_fee
+    }
+
+    pub fn get_referral_fee(&self) -> u16 {
+        self.referral_fee
+    }
+}
+
+impl Contract {
+    /// Send the treasury its share of a paid mint of `price`, the protocol fee plus
+    /// `transaction_fee`, and return what is left for the seller.
+    pub(crate) fn internal_take_fees(&self, price: Balance, transaction_fee: Balance) -> Balance {
+        let protocol_fee = price * u128::from(self.protocol_fee) / TRANSACTION_FEE_DENOMINATOR;
+        let for_treasury = (protocol_fee + transaction_fee).min(price);
+        if for_treasury > 0 {
+            Promise::new(self.treasury_id.clone()).transfer(for_treasury);
+        }
+        price - for_treasury
+    }
+
+    /// Credit `referral_id` the referral fee of a mint of `price` out of the seller's
+    /// `proceeds` and return what is left for the seller.
+    pub(crate) fn internal_pay_referral(&mut self, referral_id: AccountId, price: Balance, proceeds: Balance) -> Balance {
+        let referral_amount = (price * u128::from(self.referral_fee) / TRANSACTION_FEE_DENOMINATOR).min(proceeds);
+        self.internal_credit_revenue(&referral_id, referral_amount);
+        proceeds - referral_amount
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+    use crate::admin::{AdminAction, DEFAULT_ADMIN_DELAY};
+    use crate::test_utils::{get_context};
+    use super::*;
+
+    #[test]
+    fn test_take_fees() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        assert_eq!(contract.get_treasury(), accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let treasury_action = contract.propose_admin_action(AdminAction::SetTreasury { treasury_id: accounts(4) });
+        let fee_action = contract.propose_admin_action(AdminAction::SetProtocolFee { protocol_fee: 250 });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(treasury_action);
+        contract.execute_admin_action(fee_action);
+        assert_eq!(contract.get_treasury(), accounts(4));
+        assert_eq!(contract.get_protocol_fee(), 250);
+
+        assert_eq!(contract.internal_take_fees(10_000, 500), 9_250);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: protocol fee exceeds 1000")]
+    fn test_set_protocol_fee_above_max() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.propose_admin_action(AdminAction::SetProtocolFee { protocol_fee: MAX_PROTOCOL_FEE + 1 });
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: referral fee exceeds 5000")]
+    fn test_set_referral_fee_above_max() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.propose_admin_action(AdminAction::SetReferralFee { referral_fee: MAX_REFERRAL_FEE + 1 });
+    }
+
+    #[test]
+    fn test_pay_referral() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).block_timestamp(0).build());
+        let action_id = contract.propose_admin_action(AdminAction::SetReferralFee { referral_fee: 1_000 });
+        testing_env!(context.attached_deposit(1).block_timestamp(DEFAULT_ADMIN_DELAY).build());
+        contract.execute_admin_action(action_id);
+        assert_eq!(contract.get_referral_fee(), 1_000);
+
+        assert_eq!(contract.internal_pay_referral(accounts(3), 10_000, 9_500), 8_500);
+        assert_eq!(contract.internal_pay_referral(accounts(3), 10_000, 500), 0);
+    }
+}
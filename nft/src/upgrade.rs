@@ -0,0 +1,116 @@
+//! Self-upgrade. An owner stages the new wasm in contract storage, then an
+//! `AdminAction::UpdateContract` naming its hash goes through the usual admin delay and
+//! confirmations. Executing it deploys the staged code to this account and calls `migrate`
+//! in the same batch, so upgrades never need the account's full access key.
+
+use crate::Contract;
+use crate::ContractExt;
+use crate::utils::{assert_at_least_one_yocto, refund_deposit};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{env, near_bindgen, Gas, Promise};
+use serde_json::json;
+
+const STAGED_CODE_KEY: &[u8] = b"STAGED_CODE";
+/// Gas kept back from `execute_admin_action` when handing the rest to `migrate`.
+const GAS_RESERVED_FOR_UPDATE: Gas = Gas(20_000_000_000_000);
+
+#[near_bindgen]
+impl Contract {
+    /// Store `code` as the next version of the contract, replacing anything staged before.
+    /// Any owner can stage code since nothing is deployed until an `AdminAction::UpdateContract`
+    /// is confirmed, the caller pays for the storage.
+    #[payable]
+    pub fn stage_contract_code(&mut self, code: Base64VecU8) -> Base64VecU8 {
+        assert_at_least_one_yocto();
+        self.assert_in_owner_set();
+        assert!(!code.0.is_empty(), "FireFly: code is empty");
+        let initial_storage_usage = env::storage_usage();
+        env::storage_write(STAGED_CODE_KEY, &code.0);
+        let code_hash = Base64VecU8(env::sha256(&code.0));
+
+        env::log_str(
+            json!({
+                "type": "stage_contract_code",
+                "params": {
+                    "code_hash": code_hash,
+                }
+            }).to_string().as_str()
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        code_hash
+    }
+
+    /// sha256 of the staged code, to be named by `AdminAction::UpdateContract`.
+    pub fn get_staged_code_hash(&self) -> Option<Base64VecU8> {
+        env::storage_read(STAGED_CODE_KEY).map(|code| Base64VecU8(env::sha256(&code)))
+    }
+}
+
+impl Contract {
+    /// Deploy the staged code, which must hash to `code_hash`, and migrate the state.
+    pub(crate) fn internal_update_contract(&mut self, code_hash: Base64VecU8) -> Promise {
+        let code = env::storage_read(STAGED_CODE_KEY).expect("FireFly: No staged code");
+        assert!(env::sha256(&code) == code_hash.0, "FireFly: Staged code does not match code_hash");
+        env::storage_remove(STAGED_CODE_KEY);
+
+        let remaining_gas = env::prepaid_gas().0.saturating_sub(env::used_gas().0);
+        assert!(
+            remaining_gas > GAS_RESERVED_FOR_UPDATE.0,
+            "FireFly: Attach more than {} gas to update the contract",
+            GAS_RESERVED_FOR_UPDATE.0
+        );
+        let migrate_gas = Gas(remaining_gas - GAS_RESERVED_FOR_UPDATE.0);
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call("migrate".to_string(), vec![], 0, migrate_gas)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use super::*;
+
+    #[test]
+    fn test_stage_contract_code() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .prepaid_gas(Gas(300_000_000_000_000));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let code_hash = contract.stage_contract_code(Base64VecU8(b"\0asm new code".to_vec()));
+        assert_eq!(contract.get_staged_code_hash(), Some(code_hash.clone()));
+
+        contract.internal_update_contract(code_hash);
+        assert_eq!(contract.get_staged_code_hash(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "FireFly: Attach more than 20000000000000 gas to update the contract")]
+    fn test_update_contract_without_gas() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .prepaid_gas(Gas(300_000_000_000_000));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let code_hash = contract.stage_contract_code(Base64VecU8(b"\0asm new code".to_vec()));
+
+        testing_env!(context.prepaid_gas(Gas(10_000_000_000_000)).build());
+        contract.internal_update_contract(code_hash);
+    }
+}
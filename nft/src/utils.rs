@@ -1,5 +1,5 @@
+use crate::approval::Approvals;
 use near_sdk::{env, require, AccountId, Balance, Promise};
-use std::collections::HashMap;
 use std::mem::size_of;
 
 // TODO: need a way for end users to determine how much an approval will cost.
@@ -21,9 +21,9 @@ where
 
 pub fn refund_approved_account_ids(
     account_id: AccountId,
-    approved_account_ids: &HashMap<AccountId, u64>,
+    approved_account_ids: &Approvals,
 ) -> Promise {
-    refund_approved_account_ids_iter(account_id, approved_account_ids.keys())
+    refund_approved_account_ids_iter(account_id, approved_account_ids.account_ids())
 }
 
 pub fn refund_deposit_to_account(storage_used: u64, account_id: AccountId) {
@@ -46,6 +46,19 @@ pub fn refund_deposit(storage_used: u64) {
     refund_deposit_to_account(storage_used, env::predecessor_account_id())
 }
 
+/// Format a yoctoNEAR amount as a decimal NEAR string, e.g. `1500000000000000000000000` -> `"1.5"`.
+pub fn yocto_to_near_string(amount: Balance) -> String {
+    let one_near: Balance = 10u128.pow(24);
+    let whole = amount / one_near;
+    let fraction = amount % one_near;
+    if fraction == 0 {
+        whole.to_string()
+    } else {
+        let fraction = format!("{:024}", fraction);
+        format!("{}.{}", whole, fraction.trim_end_matches('0'))
+    }
+}
+
 /// Assert that at least 1 yoctoNEAR was attached.
 pub(crate) fn assert_at_least_one_yocto() {
     require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR")